@@ -1,90 +1,1441 @@
+#[path = "../src/attachments.rs"]
+mod attachments;
+#[path = "../src/categorize.rs"]
+mod categorize;
+#[path = "../src/config.rs"]
+mod config;
+#[path = "../src/dates.rs"]
+mod dates;
+#[path = "../src/db.rs"]
+mod db;
+#[path = "../src/drafts.rs"]
+mod drafts;
+#[cfg(feature = "semantic")]
+#[path = "../src/embeddings.rs"]
+mod embeddings;
+#[path = "../src/error.rs"]
+mod error;
+#[path = "../src/export.rs"]
+mod export;
+#[path = "../src/import.rs"]
+mod import;
+#[path = "../src/links.rs"]
+mod links;
+#[path = "../src/logging.rs"]
+mod logging;
+#[path = "../src/notes.rs"]
+mod notes;
+#[path = "../src/read_later.rs"]
+mod read_later;
+#[path = "../src/review.rs"]
+mod review;
+#[path = "../src/search.rs"]
+mod search;
+#[path = "../src/tags.rs"]
+mod tags;
+#[path = "../src/templates.rs"]
+mod templates;
+
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tauri::Manager;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 #[derive(Serialize)]
 struct Note {
     id: u64,
     title: String,
     content: String,
+    encrypted: bool,
+    source: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct AddNoteArgs {
     title: String,
     content: String,
+    #[serde(default)]
+    source: Option<String>,
+    /// Draft to discard once the note is saved
+    #[serde(default)]
+    draft_key: Option<String>,
+    /// Tags from the composer's tag field, on top of `#tags` in the content
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Payload of every note change event, so windows can refresh just the affected note.
+/// Events: `note-added`, `note-updated`, `note-deleted`, `review-rated`.
+#[derive(Clone, Serialize)]
+struct NoteEvent {
+    id: u64,
+}
+
+/// Payload of `export-progress`. The last event for an export has `finished` set, once
+/// the file at `path` is complete.
+#[derive(Clone, Serialize)]
+struct ExportProgress {
+    done: usize,
+    total: usize,
+    path: String,
+    finished: bool,
+}
+
+fn emit_export_progress(app: &AppHandle, path: &str, done: usize, total: usize, finished: bool) {
+    let payload = ExportProgress { done, total, path: path.to_string(), finished };
+    if let Err(e) = app.emit("export-progress", payload) {
+        tracing::warn!("Failed to emit export-progress: {}", e);
+    }
+}
+
+/// Notify all windows of a change. The change is already committed, so a failed emit
+/// is only logged rather than failing the command.
+fn emit_note_event(app: &AppHandle, event: &str, id: u64) {
+    if let Err(e) = app.emit(event, NoteEvent { id }) {
+        tracing::warn!("Failed to emit {} for note {}: {}", event, id, e);
+    }
 }
 
 // Global database connection (thread-safe)
 lazy_static::lazy_static! {
     static ref DB: std::sync::Mutex<rusqlite::Connection> = {
-        let conn = rusqlite::Connection::open_in_memory().unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS notes (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT NOT NULL, content TEXT NOT NULL)",
-            [],
-        ).unwrap();
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap_or_else(|e| {
+            tracing::error!("Failed to open database: {}", e);
+            panic!("Failed to open database: {}", e)
+        });
+        if let Err(e) = db::init_schema(&conn) {
+            tracing::error!("Failed to initialize database schema: {}", e);
+            panic!("Failed to initialize database schema: {}", e);
+        }
+        #[cfg(feature = "semantic")]
+        if let Err(e) = embeddings::init(&conn) {
+            tracing::error!("Failed to initialize embeddings: {}", e);
+        }
+        if let Err(e) = db::set_fts_mode(&mut conn, CONFIG.fts_mode) {
+            tracing::error!("Failed to switch search index mode: {}", e);
+        }
+        if CONFIG.tag_case == config::TagCase::Lower {
+            if let Err(e) = tags::fold_tag_case(&mut conn) {
+                tracing::error!("Failed to fold tag case: {}", e);
+            }
+        }
         std::sync::Mutex::new(conn)
     };
+
+    // User configuration, loaded once at startup
+    static ref CONFIG: config::Config = config::Config::load();
+}
+
+/// Active review session, if any (managed state, in memory only)
+type ReviewSessionState = std::sync::Mutex<Option<review::ReviewSession>>;
+
+/// State of the most recently rated card before its rating, for one level of undo
+type LastRatingState = std::sync::Mutex<Option<review::ReviewSnapshot>>;
+
+/// Lean query mode for big vaults, decided once at startup (see `Config::performance_mode`)
+static PERFORMANCE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Search result cap while in performance mode
+const LEAN_SEARCH_MAX_RESULTS: usize = 100;
+
+fn search_max_results() -> usize {
+    if PERFORMANCE_MODE.load(Ordering::Relaxed) {
+        CONFIG.search_max_results.min(LEAN_SEARCH_MAX_RESULTS)
+    } else {
+        CONFIG.search_max_results
+    }
+}
+
+/// List previews are skipped entirely in performance mode
+fn preview_chars() -> usize {
+    if PERFORMANCE_MODE.load(Ordering::Relaxed) {
+        0
+    } else {
+        CONFIG.preview_chars
+    }
 }
 
 #[tauri::command]
-fn add_note(args: AddNoteArgs) -> Result<Note, String> {
-    let mut conn = DB.lock().map_err(|e| e.to_string())?;
-    
-    let id: u64 = conn
-        .query_row(
-            "INSERT INTO notes (title, content) VALUES (?, ?)",
-            [&args.title, &args.content],
-            |row| row.get::<_, u64>(0),
-        )
-        .map_err(|e| e.to_string())?;
+fn add_note(app: AppHandle, args: AddNoteArgs) -> Result<Note, String> {
+    notes::check_note_size(&args.content, CONFIG.max_note_bytes).map_err(|e| e.to_string())?;
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    if CONFIG.unique_titles {
+        notes::ensure_title_available(&conn, &args.title).map_err(|e| e.to_string())?;
+    }
+    let (knowledge_type, tags) = CONFIG.note_fields(&args.title, &args.content, &args.tags);
 
-    Ok(Note { id, title: args.title, content: args.content })
+    let note = insert_note(&conn, args, Some(knowledge_type), &tags, true)?;
+    emit_note_event(&app, "note-added", note.id);
+    Ok(note)
 }
 
+/// Save a hotkey capture: `CONFIG.capture` decides its type and default tags (see
+/// `Config::capture_fields`), and it waits in the inbox until marked processed
 #[tauri::command]
-fn get_notes() -> Result<Vec<Note>, String> {
+fn quick_capture(app: AppHandle, args: AddNoteArgs) -> Result<Note, String> {
+    notes::check_note_size(&args.content, CONFIG.max_note_bytes).map_err(|e| e.to_string())?;
     let conn = DB.lock().map_err(|e| e.to_string())?;
-    
-    let mut stmt = conn.prepare("SELECT id, title, content FROM notes ORDER BY id DESC").map_err(|e| e.to_string())?;
-    
-    let notes: Result<Vec<Note>, String> = stmt.query_map([], |row| {
+    if CONFIG.unique_titles {
+        notes::ensure_title_available(&conn, &args.title).map_err(|e| e.to_string())?;
+    }
+    let (knowledge_type, tags) = CONFIG.capture_fields(&args.title, &args.content, &args.tags);
+
+    let note = insert_note(&conn, args, knowledge_type, &tags, false)?;
+    emit_note_event(&app, "note-added", note.id);
+    Ok(note)
+}
+
+/// Insert for `add_note` and `quick_capture`, then sync links and metadata and discard
+/// the note's draft. `processed = false` puts the note in the inbox.
+fn insert_note(
+    conn: &rusqlite::Connection,
+    args: AddNoteArgs,
+    knowledge_type: Option<categorize::KnowledgeType>,
+    tags: &[String],
+    processed: bool,
+) -> Result<Note, String> {
+    let source = args.source.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let tags = serde_json::to_string(tags).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO notes (title, content, source, tags, knowledge_type, review_due, is_processed)
+         VALUES (?, ?, ?, ?, ?, strftime('%s', 'now'), ?)",
+        rusqlite::params![&args.title, &args.content, &source, &tags, knowledge_type.map(|k| k.as_db_str()), processed],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid() as u64;
+    links::sync_links(conn, id).map_err(|e| e.to_string())?;
+    notes::refresh_metadata(conn, id).map_err(|e| e.to_string())?;
+
+    if let Some(key) = &args.draft_key {
+        drafts::clear_draft(conn, key).map_err(|e| e.to_string())?;
+    }
+
+    Ok(Note { id, title: args.title, content: args.content, encrypted: false, source })
+}
+
+#[tauri::command]
+fn create_from_template(app: AppHandle, knowledge_type: String, title: String) -> Result<templates::TemplatedNote, String> {
+    let kind = categorize::KnowledgeType::from_db_str(&knowledge_type)
+        .ok_or_else(|| format!("Unknown knowledge type '{}'", knowledge_type))?;
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    if CONFIG.unique_titles {
+        notes::ensure_title_available(&conn, &title).map_err(|e| e.to_string())?;
+    }
+    let note = templates::create_from_template(&conn, kind, &title, CONFIG.tag_case, CONFIG.skip_code_tags).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-added", note.id);
+    Ok(note)
+}
+
+#[tauri::command]
+fn preview_categorize(title: String, content: String) -> serde_json::Value {
+    let categorization = categorize::categorize_with_confidence(&content, &title, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs);
+    serde_json::json!({
+        "knowledge_type": categorization.knowledge_type,
+        "tags": categorization.tags,
+        "confidence": categorization.confidence,
+        "low_confidence": categorization.confidence < categorize::LOW_CONFIDENCE,
+    })
+}
+
+#[tauri::command]
+fn explain_categorization(title: String, content: String) -> serde_json::Value {
+    let rules = categorize::explain(&content, &title, CONFIG.default_knowledge_type(), &CONFIG.keyword_packs);
+    let winner = rules.iter().find(|rule| rule.won);
+    serde_json::json!({
+        "knowledge_type": winner.map(|rule| rule.knowledge_type),
+        "confidence": winner.map(|rule| rule.confidence),
+        "rules": rules,
+    })
+}
+
+#[tauri::command]
+fn smart_paste(text: String) -> categorize::PasteProposal {
+    categorize::smart_paste(&text, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs)
+}
+
+#[tauri::command]
+fn list_active_packs() -> Vec<categorize::KeywordPack> {
+    CONFIG.keyword_packs.clone()
+}
+
+#[tauri::command]
+fn find_by_title(title: String) -> Result<Option<notes::Note>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::find_by_title(&conn, &title).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_notes(include_archived: Option<bool>) -> Result<Vec<Note>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    all_notes(&conn, include_archived.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+/// Every note, newest first; archived ones only with `include_archived`
+fn all_notes(conn: &rusqlite::Connection, include_archived: bool) -> Result<Vec<Note>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, CASE WHEN encrypted THEN '' ELSE content END, encrypted, source FROM notes
+         WHERE ?1 OR is_archived = 0
+         ORDER BY id DESC",
+    )?;
+
+    let notes = stmt.query_map([include_archived], |row| {
         Ok(Note {
             id: row.get(0)?,
             title: row.get(1)?,
             content: row.get(2)?,
+            encrypted: row.get(3)?,
+            source: row.get(4)?,
         })
-    }).map_err(|e| e.to_string())?.collect();
-
-    notes
+    })?;
+    notes.collect()
 }
 
 #[tauri::command]
-fn search_notes(query: String) -> Result<Vec<Note>, String> {
+fn search_notes(
+    query: String,
+    limit: Option<usize>,
+    include_archived: Option<bool>,
+    fields: Option<Vec<String>>,
+    highlight: Option<search::Highlight>,
+    include_attachments: Option<bool>,
+) -> Result<serde_json::Value, String> {
     let conn = DB.lock().map_err(|e| e.to_string())?;
+    let include_archived = include_archived.unwrap_or(false);
     
     if query.trim().is_empty() {
-        return Ok(Vec::new());
+        return Ok(serde_json::json!({ "notes": [], "truncated": false, "too_short": false }));
     }
 
-    let mut stmt = conn.prepare("SELECT id, title, content FROM notes WHERE title LIKE ? OR content LIKE ? ORDER BY id DESC").map_err(|e| e.to_string())?;
-    
-    let search_term = format!("%{}%", query);
-    
-    let notes: Result<Vec<Note>, String> = stmt.query_map([search_term.clone(), search_term], |row| {
-        Ok(Note {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            content: row.get(2)?,
-        })
-    }).map_err(|e| e.to_string())?.collect();
+    let mut results = match fields {
+        Some(fields) => {
+            let mut projection = search::Projection::parse(&fields, preview_chars())?;
+            if let Some(highlight) = highlight {
+                projection = projection.with_highlight(highlight)?;
+            }
+            let results = search::search_projected(
+                &conn,
+                &query,
+                &projection,
+                limit,
+                search_max_results(),
+                CONFIG.min_query_len,
+                include_archived,
+                CONFIG.search_weights(),
+            );
+            serde_json::to_value(results.map_err(|e| e.to_string())?)
+        }
+        None if highlight.is_some() => return Err("Highlighting needs `fields` including 'snippet'".to_string()),
+        None => {
+            let results = search::search_notes(
+                &conn,
+                &query,
+                limit,
+                search_max_results(),
+                CONFIG.min_query_len,
+                include_archived,
+                CONFIG.search_weights(),
+                CONFIG.strict_knowledge_types,
+            );
+            serde_json::to_value(results.map_err(|e| e.to_string())?)
+        }
+    }
+    .map_err(|e| e.to_string())?;
 
-    notes
+    if include_attachments.unwrap_or(false) {
+        let limit = limit.unwrap_or(search_max_results()).min(search_max_results());
+        let hits = search::attachment_hits(&conn, &query, limit, CONFIG.min_query_len, include_archived).map_err(|e| e.to_string())?;
+        results["attachment_hits"] = serde_json::to_value(hits).map_err(|e| e.to_string())?;
+    }
+    Ok(results)
 }
 
-fn main() {
-    tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![add_note, get_notes, search_notes])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+#[tauri::command]
+fn regex_search(pattern: String, limit: Option<usize>, include_archived: Option<bool>) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    search::regex_search(&conn, &pattern, limit, search_max_results(), include_archived.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// Neighbours returned by `semantic_search` when the caller gives no `k`
+#[cfg(feature = "semantic")]
+const SEMANTIC_DEFAULT_K: usize = 10;
+
+#[cfg(feature = "semantic")]
+#[tauri::command]
+fn semantic_search(query: String, k: Option<usize>) -> Result<Vec<serde_json::Value>, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    embeddings::semantic_search(&mut conn, &query, k.unwrap_or(SEMANTIC_DEFAULT_K)).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "semantic")]
+#[tauri::command]
+fn compute_embeddings() -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    embeddings::sync_embeddings(&mut conn).map_err(|e| e.to_string())
+}
+
+// Registered either way, so the frontend gets a clear error instead of a missing command
+#[cfg(not(feature = "semantic"))]
+#[tauri::command]
+#[allow(unused_variables)]
+fn semantic_search(query: String, k: Option<usize>) -> Result<Vec<serde_json::Value>, String> {
+    Err("Semantic search isn't available in this build (enable the `semantic` feature)".to_string())
+}
+
+#[cfg(not(feature = "semantic"))]
+#[tauri::command]
+fn compute_embeddings() -> Result<usize, String> {
+    Err("Semantic search isn't available in this build (enable the `semantic` feature)".to_string())
+}
+
+#[tauri::command]
+fn search_count(query: String, include_archived: Option<bool>) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+
+    if query.trim().is_empty() {
+        return Ok(0);
+    }
+
+    search::search_count(&conn, &query, CONFIG.min_query_len, include_archived.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_note(app: AppHandle, id: u64, title: String, content: String, source: Option<String>) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::update_note(
+        &conn,
+        id,
+        &title,
+        &content,
+        source.as_deref(),
+        CONFIG.max_note_bytes,
+        CONFIG.tag_case,
+        CONFIG.default_knowledge_type(),
+        CONFIG.skip_code_tags,
+        &CONFIG.keyword_packs,
+    )
+    .map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn apply_edits(app: AppHandle, id: u64, edits: notes::EditPatch) -> Result<(), String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::apply_edits(
+        &mut conn,
+        id,
+        &edits,
+        CONFIG.max_note_bytes,
+        CONFIG.tag_case,
+        CONFIG.default_knowledge_type(),
+        CONFIG.skip_code_tags,
+        &CONFIG.keyword_packs,
+    )
+    .map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn append_note(app: AppHandle, id: u64, text: String, timestamp: bool) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::append_note(
+        &conn,
+        id,
+        &text,
+        timestamp,
+        CONFIG.max_note_bytes,
+        CONFIG.tag_case,
+        CONFIG.default_knowledge_type(),
+        CONFIG.skip_code_tags,
+        &CONFIG.keyword_packs,
+    )
+    .map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_note(app: AppHandle, id: u64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::delete_note(&conn, id).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-deleted", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn compact_ids(session: State<'_, ReviewSessionState>, last_rating: State<'_, LastRatingState>) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let renumbered = notes::compact_ids(&mut conn).map_err(|e| e.to_string())?;
+
+    // Ids held in memory refer to the old numbering
+    if renumbered > 0 {
+        *session.lock().map_err(|e| e.to_string())? = None;
+        *last_rating.lock().map_err(|e| e.to_string())? = None;
+    }
+    Ok(renumbered)
+}
+
+#[tauri::command]
+fn move_notes_to_vault(app: AppHandle, ids: Vec<u64>, target_vault: String) -> Result<Vec<(u64, u64)>, String> {
+    // Moving into a typo'd path would quietly create a new vault nobody opens
+    if !std::path::Path::new(&target_vault).is_file() {
+        return Err(format!("No vault database at {}", target_vault));
+    }
+    let mut target = db::open(std::path::Path::new(&target_vault)).map_err(|e| e.to_string())?;
+    db::migrate(&target).map_err(|e| e.to_string())?;
+
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let moved = notes::move_notes(&mut conn, &mut target, &ids).map_err(|e| e.to_string())?;
+    for (id, _) in &moved {
+        emit_note_event(&app, "note-updated", *id);
+    }
+    Ok(moved)
+}
+
+#[tauri::command]
+fn toggle_lock(id: u64) -> Result<bool, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::toggle_lock(&conn, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn archive_note(app: AppHandle, id: u64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_archived(&conn, id, true).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn convert_to_checklist(app: AppHandle, id: u64) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let created = notes::convert_to_checklist(&conn, id).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(created)
+}
+
+#[tauri::command]
+fn tidy_note(app: AppHandle, id: u64) -> Result<bool, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let changed = notes::tidy_note(&conn, id).map_err(|e| e.to_string())?;
+    if changed {
+        emit_note_event(&app, "note-updated", id);
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn touch_note(app: AppHandle, id: u64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::touch_note(&conn, id).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn record_note_open(id: u64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::record_open(&conn, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stale_notes(older_than_days: u32, max_opens: u32) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::stale_notes(&conn, older_than_days, max_opens).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_always_top(app: AppHandle, id: u64, always_top: bool) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_always_top(&conn, id, always_top).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn unarchive_note(app: AppHandle, id: u64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_archived(&conn, id, false).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn recategorize_all(only_default: bool) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::recategorize_all(&mut conn, only_default, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn recategorize_note(app: AppHandle, id: u64) -> Result<serde_json::Value, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let classification = notes::recategorize_note(&conn, id, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(classification)
+}
+
+#[tauri::command]
+fn get_review_cards() -> Result<Vec<review::ReviewCard>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::due_cards(&conn, None).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn undo_last_rating(app: AppHandle, session: State<'_, ReviewSessionState>, last_rating: State<'_, LastRatingState>) -> Result<u64, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let snapshot = last_rating.lock().map_err(|e| e.to_string())?.take().ok_or("Nothing to undo")?;
+    review::restore(&mut conn, &snapshot).map_err(|e| e.to_string())?;
+
+    if let Some(session) = session.lock().map_err(|e| e.to_string())?.as_mut() {
+        session.forget(snapshot.id);
+    }
+    emit_note_event(&app, "review-rated", snapshot.id);
+    Ok(snapshot.id)
+}
+
+/// Cards listed by `hardest_cards` when the caller gives no limit
+const HARDEST_CARDS_DEFAULT_LIMIT: usize = 20;
+
+#[tauri::command]
+fn card_history(id: u64) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::card_history(&conn, id).map_err(|e| e.to_string())
+}
+
+const REVIEW_LOG_DEFAULT_LIMIT: usize = 100;
+
+#[tauri::command]
+fn review_log(
+    from: Option<i64>,
+    to: Option<i64>,
+    rating: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let rating = match rating {
+        Some(rating) => Some(review::Rating::parse(&rating).ok_or_else(|| format!("Unknown rating: {}", rating))?),
+        None => None,
+    };
+
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::review_log(&conn, from, to, rating, limit.unwrap_or(REVIEW_LOG_DEFAULT_LIMIT), offset.unwrap_or(0))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn hardest_cards(limit: Option<usize>) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::hardest_cards(&conn, limit.unwrap_or(HARDEST_CARDS_DEFAULT_LIMIT)).map_err(|e| e.to_string())
+}
+
+/// Lapses that make a card a leech when the caller gives no threshold
+const LEECH_DEFAULT_LAPSES: i64 = 4;
+
+#[tauri::command]
+fn leeches(min_lapses: Option<i64>) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let min_lapses = min_lapses.or(CONFIG.sm2.leech_lapses).unwrap_or(LEECH_DEFAULT_LAPSES);
+    review::leeches(&conn, min_lapses).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_card_suspended(app: AppHandle, id: u64, suspended: bool) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    if !review::set_suspended(&conn, id, suspended).map_err(|e| e.to_string())? {
+        return Err(error::QuickNoteError::NotFound(id).to_string());
+    }
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn review_forecast(days: u32) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::forecast(&conn, days).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn notes_without_review() -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::unenrolled_notes(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn enroll_in_review(ids: Vec<u64>) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    review::enroll(&mut conn, &ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rate_review_card(
+    app: AppHandle,
+    session: State<'_, ReviewSessionState>,
+    last_rating: State<'_, LastRatingState>,
+    card_id: u64,
+    rating: String,
+) -> Result<(), String> {
+    let rating = review::Rating::parse(&rating).ok_or_else(|| format!("Unknown rating: {}", rating))?;
+
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    if session.lock().map_err(|e| e.to_string())?.as_ref().is_some_and(|s| s.refuses(card_id)) {
+        return Err("The review session's time limit has been reached".to_string());
+    }
+    let before = review::rate_card(&mut conn, card_id, rating, &CONFIG.sm2).map_err(|e| e.to_string())?;
+    *last_rating.lock().map_err(|e| e.to_string())? = Some(before);
+
+    if let Some(session) = session.lock().map_err(|e| e.to_string())?.as_mut() {
+        session.record(card_id, rating);
+    }
+    emit_note_event(&app, "review-rated", card_id);
+    Ok(())
+}
+
+#[tauri::command]
+fn normalize_easiness(min: f64, max: f64) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    review::normalize_easiness(&mut conn, min, max, &CONFIG.sm2).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn next_review_card() -> Result<Option<review::ReviewCard>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    review::next_card(&conn, CONFIG.review_shuffle_ties).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_review_session(session: State<'_, ReviewSessionState>, max_cards: usize, max_minutes: u32) -> Result<Vec<review::ReviewCard>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let cards = review::due_cards(&conn, Some(max_cards)).map_err(|e| e.to_string())?;
+
+    // Starting a new session discards any unfinished one
+    let ids = cards.iter().map(|card| card.id).collect();
+    *session.lock().map_err(|e| e.to_string())? = Some(review::ReviewSession::new(ids, max_minutes));
+    Ok(cards)
+}
+
+/// Progress of the running session, including the time left, without ending it
+#[tauri::command]
+fn review_session_stats(session: State<'_, ReviewSessionState>) -> Result<review::SessionStats, String> {
+    let session = session.lock().map_err(|e| e.to_string())?;
+    session.as_ref().map(|s| s.stats()).ok_or_else(|| "No active review session".to_string())
+}
+
+#[tauri::command]
+fn end_review_session(session: State<'_, ReviewSessionState>) -> Result<review::SessionStats, String> {
+    let finished = session.lock().map_err(|e| e.to_string())?.take();
+    finished.map(|s| s.stats()).ok_or_else(|| "No active review session".to_string())
+}
+
+#[tauri::command]
+fn notes_by_tag_prefix(prefix: String) -> Result<Vec<notes::NoteSummary>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::notes_by_tag_prefix(&conn, &prefix, CONFIG.tag_case).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn notes_by_dialect(dialect: String) -> Result<Vec<notes::NoteSummary>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::notes_by_dialect(&conn, &dialect).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn notes_by_language(language: String) -> Result<Vec<notes::NoteSummary>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::notes_by_language(&conn, &language).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn detect_snippet_languages() -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::detect_snippet_languages(&mut conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_sql_dialect(id: u64, dialect: Option<String>) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_sql_dialect(&conn, id, dialect.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn notes_by_source(prefix: String) -> Result<Vec<notes::NoteSummary>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::notes_by_source(&conn, &prefix).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resync_tags() -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::resync_tags(&mut conn, CONFIG.tag_case, CONFIG.skip_code_tags).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn repair_tags_json() -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::repair_tags_json(&mut conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn auto_tag_all(dry_run: bool) -> Result<Vec<serde_json::Value>, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::auto_tag_all(&mut conn, &CONFIG.tag_keywords, CONFIG.tag_case, dry_run).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn related_tags(tag: String, limit: usize) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::related_tags(&conn, &tag, limit, CONFIG.tag_case).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rename_tag(from: String, to: String) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::rename_tag(&mut conn, &from, &to, CONFIG.tag_case).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn dedupe_tags(dry_run: bool) -> Result<tags::TagDedupe, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::dedupe_tags(&mut conn, CONFIG.tag_case, dry_run).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn tag_tree() -> Result<Vec<tags::TagNode>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    tags::tag_tree(&conn, CONFIG.tag_case).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_jsonl(app: AppHandle, path: String) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    let progress = |done, total| emit_export_progress(&app, &path, done, total, false);
+    let count = export::export_jsonl(&conn, std::io::BufWriter::new(file), progress).map_err(|e| e.to_string())?;
+    emit_export_progress(&app, &path, count, count, true);
+    Ok(count)
+}
+
+#[tauri::command]
+fn export_vault(app: AppHandle, path: String, passphrase: Option<String>) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let progress = |done, total| emit_export_progress(&app, &path, done, total, false);
+    let count = export::export_vault_file(
+        &conn,
+        std::path::Path::new(&path),
+        passphrase.as_deref(),
+        CONFIG.backup_plaintext_sidecar,
+        progress,
+    )
+    .map_err(|e| e.to_string())?;
+    emit_export_progress(&app, &path, count, count, true);
+    Ok(count)
+}
+
+#[tauri::command]
+fn export_share_bundle(path: String, ids: Vec<u64>, passphrase: Option<String>) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    export::export_share_bundle(&conn, std::io::BufWriter::new(file), &ids, passphrase.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_content_only(path: String) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    export::export_content_only(&conn, std::io::BufWriter::new(file)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_review_state(path: String) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    export::export_review_state(&conn, std::io::BufWriter::new(file)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_content(path: String) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+    import::import_content(&mut conn, std::io::BufReader::new(file), CONFIG.tag_case).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_review_state(path: String) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+    import::import_review_state(&mut conn, std::io::BufReader::new(file)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn vault_backup_info(path: String) -> Result<export::VaultManifest, String> {
+    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+    import::read_vault_manifest(std::io::BufReader::new(file)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_vault(path: String, passphrase: Option<String>) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+    import::import_vault(&mut conn, std::io::BufReader::new(file), passphrase.as_deref(), CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn duplicate_titles() -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::duplicate_titles(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_notes(
+    date_format: Option<String>,
+    include_archived: Option<bool>,
+    min_priority: Option<u8>,
+    by_priority: Option<bool>,
+) -> Result<Vec<notes::NoteListItem>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let date_format = date_format.as_deref().or(CONFIG.date_format.as_deref());
+    notes::list_notes(
+        &conn,
+        preview_chars(),
+        date_format,
+        include_archived.unwrap_or(false),
+        min_priority,
+        by_priority.unwrap_or(false),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn high_priority_notes(date_format: Option<String>) -> Result<Vec<notes::NoteListItem>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let date_format = date_format.as_deref().or(CONFIG.date_format.as_deref());
+    notes::high_priority_notes(&conn, preview_chars(), date_format).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_priority(app: AppHandle, id: u64, level: i64) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_priority(&conn, id, level).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn inbox(date_format: Option<String>) -> Result<Vec<notes::NoteListItem>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let date_format = date_format.as_deref().or(CONFIG.date_format.as_deref());
+    notes::inbox(&conn, preview_chars(), date_format).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn mark_processed(ids: Vec<u64>) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::mark_processed(&mut conn, &ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_read_later(note_id: u64, position: Option<usize>) -> Result<(), String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    read_later::add(&mut conn, note_id, position).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_read_later(note_id: u64) -> Result<bool, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    read_later::remove(&conn, note_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reorder_read_later(note_ids: Vec<u64>) -> Result<(), String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    read_later::reorder(&mut conn, &note_ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn read_later_list() -> Result<Vec<notes::NoteSummary>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    read_later::list(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_reminder(app: AppHandle, id: u64, at: Option<i64>) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::set_reminder(&conn, id, at).map_err(|e| e.to_string())?;
+    emit_note_event(&app, "note-updated", id);
+    Ok(())
+}
+
+#[tauri::command]
+fn due_reminders() -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::due_reminders(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn recent_notes(limit: usize, date_format: Option<String>) -> Result<Vec<notes::NoteListItem>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let date_format = date_format.as_deref().or(CONFIG.date_format.as_deref());
+    notes::recent_notes(&conn, limit, preview_chars(), date_format, false).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reclassify(ids: Vec<u64>, to_type: String) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::reclassify(&mut conn, &ids, &to_type).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_jsonl(path: String, reindex: bool) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+    import::import_jsonl(&mut conn, std::io::BufReader::new(file), reindex, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reindex_imported(since_id: u64, recategorize: bool) -> Result<usize, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    import::reindex_imported(&mut conn, since_id, recategorize, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_in_note(id: u64, query: String, case_sensitive: bool, whole_word: bool) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::search_in_note(&conn, id, &query, case_sensitive, whole_word).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn jump_search(query: String) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    search::jump_search(&conn, &query).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_review_ics(path: String) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let ics = export::export_review_ics(&conn).map_err(|e| e.to_string())?;
+    std::fs::write(&path, ics).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_markdown_combined(ids: Vec<u64>, path: String) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let markdown = export::export_markdown_combined(&conn, &ids).map_err(|e| e.to_string())?;
+    std::fs::write(&path, markdown).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_filtered(
+    query: String,
+    knowledge_type: Option<String>,
+    tag: Option<String>,
+    format: export::ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    let knowledge_type = match knowledge_type {
+        Some(kind) => Some(categorize::KnowledgeType::from_db_str(&kind).ok_or_else(|| format!("Unknown knowledge type '{}'", kind))?),
+        None => None,
+    };
+
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let ids = search::filter_ids(&conn, &query, knowledge_type, tag.as_deref(), CONFIG.tag_case, false).map_err(|e| e.to_string())?;
+    let exported = export::export_notes(&conn, &ids, format).map_err(|e| e.to_string())?;
+    std::fs::write(&path, exported).map_err(|e| e.to_string())?;
+    Ok(ids.len())
+}
+
+#[tauri::command]
+fn export_deck(query: String, knowledge_type: Option<String>, tag: Option<String>, path: String) -> Result<usize, String> {
+    let knowledge_type = match knowledge_type {
+        Some(kind) => Some(categorize::KnowledgeType::from_db_str(&kind).ok_or_else(|| format!("Unknown knowledge type '{}'", kind))?),
+        None => None,
+    };
+
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let ids = search::filter_ids(&conn, &query, knowledge_type, tag.as_deref(), CONFIG.tag_case, false).map_err(|e| e.to_string())?;
+    export::export_deck(&conn, &ids, std::path::Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_html_site(dir: String) -> Result<usize, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    export::export_html_site(&conn, std::path::Path::new(&dir)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn duplicate_note(id: u64) -> Result<u64, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::duplicate_note(&conn, id).map_err(|e| e.to_string())
+}
+
+/// Current time as epoch seconds
+fn unix_now() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+#[tauri::command]
+fn split_note(id: u64, by: String, keep_original: bool) -> Result<Vec<u64>, String> {
+    let mut conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::split_note(&mut conn, id, &by, keep_original, CONFIG.tag_case, CONFIG.default_knowledge_type(), CONFIG.skip_code_tags, &CONFIG.keyword_packs).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stub_notes(min_chars: usize) -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::stub_notes(&conn, min_chars).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn on_this_day() -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::on_this_day(&conn, unix_now()).map_err(|e| e.to_string())
+}
+
+/// A draft as the quick-add composer stores it: `{title, content}` as JSON. Anything
+/// else is taken as bare content.
+#[derive(Deserialize)]
+struct ComposerDraft {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl ComposerDraft {
+    fn parse(draft: &str) -> Self {
+        serde_json::from_str(draft).unwrap_or_else(|_| Self { title: String::new(), content: draft.to_string(), tags: Vec::new() })
+    }
+}
+
+/// Drafts are unsaved quick captures, so they carry the type and tags `quick_capture`
+/// would give them under `CONFIG.capture`
+#[tauri::command]
+fn save_draft(key: String, content: String) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let draft = ComposerDraft::parse(&content);
+    let (knowledge_type, tags) = CONFIG.capture_fields(&draft.title, &draft.content, &draft.tags);
+    drafts::save_draft(&conn, &key, &content, knowledge_type, &tags, CONFIG.max_note_bytes).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_attachment(note_id: u64, filename: String, bytes: Vec<u8>) -> Result<u64, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    attachments::add_attachment(&conn, note_id, &filename, &bytes, CONFIG.max_attachment_bytes).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_attachments(note_id: u64) -> Result<Vec<attachments::AttachmentInfo>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    attachments::list_attachments(&conn, note_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_attachment(id: u64) -> Result<attachments::Attachment, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    attachments::get_attachment(&conn, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn load_draft(key: String) -> Result<Option<drafts::Draft>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    drafts::load_draft(&conn, &key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_draft(key: String) -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    drafts::clear_draft(&conn, &key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn broken_links() -> Result<Vec<serde_json::Value>, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    links::broken_links(&conn).map_err(|e| e.to_string())
+}
+
+/// Neighbourhood depth for `note_graph` when a root is given without one
+const GRAPH_DEFAULT_DEPTH: usize = 2;
+
+#[tauri::command]
+fn note_graph(root_id: Option<u64>, depth: Option<usize>) -> Result<serde_json::Value, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    links::note_graph(&conn, root_id, depth.unwrap_or(GRAPH_DEFAULT_DEPTH)).map_err(|e| e.to_string())
+}
+
+/// Recently updated notes shown on the home screen
+const TODAY_RECENT_LIMIT: usize = 5;
+
+#[tauri::command]
+fn today() -> Result<serde_json::Value, String> {
+    // One lock for the whole home screen instead of a round-trip per widget
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+
+    let due_cards = review::due_count(&conn).map_err(|e| e.to_string())?;
+    let recent = notes::recent_notes(&conn, TODAY_RECENT_LIMIT, preview_chars(), CONFIG.date_format.as_deref(), false)
+        .map_err(|e| e.to_string())?;
+    let on_this_day = notes::on_this_day(&conn, unix_now()).map_err(|e| e.to_string())?;
+    let stats = notes::vault_stats(&conn).map_err(|e| e.to_string())?;
+
+    Ok(serde_json::json!({
+        "due_cards": due_cards,
+        "recent": recent,
+        "on_this_day": on_this_day,
+        "note_count": stats["note_count"],
+        "stats": stats,
+    }))
+}
+
+/// Terms listed by `text_stats`
+const TEXT_STATS_TOP_TERMS: usize = 20;
+
+#[tauri::command]
+fn text_stats() -> Result<serde_json::Value, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    notes::text_stats(&conn, CONFIG.stopwords.as_deref(), TEXT_STATS_TOP_TERMS).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_note_count() -> Result<u64, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    db::note_count(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn vault_tier() -> Result<serde_json::Value, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let note_count = db::note_count(&conn).map_err(|e| e.to_string())?;
+
+    Ok(serde_json::json!({
+        "note_count": note_count,
+        "tier": db::vault_tier(note_count, CONFIG.large_vault_threshold),
+        "performance_mode": PERFORMANCE_MODE.load(Ordering::Relaxed),
+    }))
+}
+
+#[tauri::command]
+fn optimize_vault() -> Result<(), String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    db::optimize(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn diagnostics() -> Result<serde_json::Value, String> {
+    let conn = DB.lock().map_err(|e| e.to_string())?;
+    let mut diagnostics = db::diagnostics(&conn).map_err(|e| e.to_string())?;
+    diagnostics["config_path"] = serde_json::json!(CONFIG.path);
+    Ok(diagnostics)
+}
+
+fn main() {
+    logging::init(&CONFIG.log_level, false);
+    for warning in &CONFIG.warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    tauri::Builder::default()
+        .invoke_handler(tauri::generate_handler![
+            add_note,
+            quick_capture,
+            create_from_template,
+            get_notes,
+            find_by_title,
+            preview_categorize,
+            explain_categorization,
+            smart_paste,
+            list_active_packs,
+            search_notes,
+            search_count,
+            regex_search,
+            semantic_search,
+            compute_embeddings,
+            update_note,
+            apply_edits,
+            append_note,
+            recategorize_all,
+            recategorize_note,
+            get_review_cards,
+            rate_review_card,
+            next_review_card,
+            normalize_easiness,
+            undo_last_rating,
+            review_forecast,
+            hardest_cards,
+            leeches,
+            set_card_suspended,
+            card_history,
+            review_log,
+            notes_without_review,
+            enroll_in_review,
+            start_review_session,
+            review_session_stats,
+            end_review_session,
+            notes_by_tag_prefix,
+            tag_tree,
+            rename_tag,
+            dedupe_tags,
+            related_tags,
+            auto_tag_all,
+            resync_tags,
+            repair_tags_json,
+            export_jsonl,
+            export_vault,
+            export_share_bundle,
+            vault_backup_info,
+            import_vault,
+            export_content_only,
+            export_review_state,
+            import_content,
+            import_review_state,
+            list_notes,
+            duplicate_titles,
+            high_priority_notes,
+            set_priority,
+            recent_notes,
+            inbox,
+            mark_processed,
+            add_read_later,
+            remove_read_later,
+            reorder_read_later,
+            read_later_list,
+            set_reminder,
+            due_reminders,
+            reclassify,
+            import_jsonl,
+            reindex_imported,
+            jump_search,
+            search_in_note,
+            export_review_ics,
+            export_html_site,
+            export_filtered,
+            export_deck,
+            export_markdown_combined,
+            duplicate_note,
+            split_note,
+            delete_note,
+            move_notes_to_vault,
+            compact_ids,
+            toggle_lock,
+            archive_note,
+            unarchive_note,
+            set_always_top,
+            touch_note,
+            record_note_open,
+            stale_notes,
+            tidy_note,
+            convert_to_checklist,
+            broken_links,
+            note_graph,
+            on_this_day,
+            stub_notes,
+            today,
+            text_stats,
+            diagnostics,
+            notes_by_source,
+            notes_by_dialect,
+            set_sql_dialect,
+            notes_by_language,
+            detect_snippet_languages,
+            save_draft,
+            load_draft,
+            clear_draft,
+            add_attachment,
+            list_attachments,
+            get_attachment,
+            get_note_count,
+            vault_tier,
+            optimize_vault,
+        ])
+        .setup(|app| {
+            app.manage(ReviewSessionState::default());
+            app.manage(LastRatingState::default());
+
+            let conn = DB.lock().map_err(|e| e.to_string())?;
+            let note_count = db::note_count(&conn)?;
+            let tier = db::vault_tier(note_count, CONFIG.large_vault_threshold);
+            tracing::info!("Vault opened: {} notes ({:?} tier)", note_count, tier);
+            PERFORMANCE_MODE.store(CONFIG.performance_mode.unwrap_or(tier == db::VaultTier::Large), Ordering::Relaxed);
+
+            // Once per launch; the frontend can also poll `vault_tier` if it misses this
+            if tier == db::VaultTier::Large && CONFIG.performance_mode.is_none() {
+                let payload = serde_json::json!({
+                    "note_count": note_count,
+                    "threshold": CONFIG.large_vault_threshold,
+                    "suggestion": "optimize_vault",
+                });
+                if let Err(e) = app.handle().emit("vault-large", payload) {
+                    tracing::warn!("Failed to emit vault-large: {}", e);
+                }
+            }
+
+            // Reminders that came due while the app was closed; later ones are up to the
+            // frontend, which can poll `due_reminders`
+            let due = notes::due_reminders(&conn)?;
+            if !due.is_empty() {
+                if let Err(e) = app.handle().emit("reminders-due", due) {
+                    tracing::warn!("Failed to emit reminders-due: {}", e);
+                }
+            }
+            Ok(())
+        })
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_event_payload_is_just_the_id() {
+        assert_eq!(serde_json::to_value(NoteEvent { id: 7 }).unwrap(), serde_json::json!({ "id": 7 }));
+    }
+
+    #[test]
+    fn note_list_hides_archived_notes_by_default() {
+        let conn = db::test_vault();
+        let kept = db::test_note(&conn, "Kept", "body");
+        let archived = db::test_note(&conn, "Archived", "body");
+        notes::set_archived(&conn, archived, true).unwrap();
+
+        let ids = |include_archived| all_notes(&conn, include_archived).unwrap().into_iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids(false), vec![kept]);
+        assert_eq!(ids(true), vec![archived, kept]);
+    }
+
+    fn args(title: &str, draft_key: Option<&str>) -> AddNoteArgs {
+        AddNoteArgs {
+            title: title.to_string(),
+            content: "body".to_string(),
+            source: Some("  ".to_string()),
+            draft_key: draft_key.map(str::to_string),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_captures_land_in_the_inbox() {
+        let conn = db::test_vault();
+        drafts::save_draft(&conn, "composer", "body", None, &[], 1024).unwrap();
+        let note = insert_note(&conn, args("regular", Some("composer")), None, &[], true).unwrap();
+        assert_eq!(note.source, None);
+        assert!(drafts::load_draft(&conn, "composer").unwrap().is_none());
+
+        let tags = ["inbox".to_string()];
+        let capture = insert_note(&conn, args("capture", None), Some(categorize::KnowledgeType::Note), &tags, false).unwrap();
+        let inbox = notes::inbox(&conn, 0, None).unwrap();
+        assert_eq!(inbox.iter().map(|n| n.id).collect::<Vec<_>>(), vec![capture.id]);
+    }
+
+    #[test]
+    fn composer_drafts_are_read_as_title_and_content() {
+        let draft = ComposerDraft::parse(r#"{"title": "Query", "content": "SELECT 1\n#db"}"#);
+        assert_eq!((draft.title.as_str(), draft.content.as_str()), ("Query", "SELECT 1\n#db"));
+        assert!(draft.tags.is_empty());
+
+        let bare = ComposerDraft::parse("just text #idea");
+        assert_eq!((bare.title.as_str(), bare.content.as_str()), ("", "just text #idea"));
+    }
 }
@@ -1,6 +1,6 @@
 import React, { useState, useEffect } from 'react';
 import { SearchBar } from './components/SearchBar';
-import { QuickAddModal } from './components/QuickAddModal';
+import { QuickAddModal, QUICK_ADD_DRAFT_KEY } from './components/QuickAddModal';
 import { NoteList } from './components/NoteList';
 import { ReviewMode } from './components/ReviewMode';
 
@@ -16,6 +16,9 @@ declare global {
   interface Window {
     __TAURI__: {
       invoke: (cmd: string, args?: any) => Promise<any>;
+      event: {
+        listen: (event: string, handler: (event: { payload: any }) => void) => Promise<() => void>;
+      };
     };
   }
 }
@@ -55,16 +58,22 @@ const App = () => {
     const handleQuickAdd = () => setIsQuickAddOpen(true);
     document.addEventListener('quickAdd' as any, handleQuickAdd);
 
+    // Backend emits these after every mutation (payload: { id }), from any window
+    const unlisteners = ['note-added', 'note-updated', 'note-deleted'].map((event) =>
+      window.__TAURI__.event.listen(event, () => loadNotes())
+    );
+
     return () => {
       window.removeEventListener('keydown', handleKeyDown);
       document.removeEventListener('quickAdd' as any, handleQuickAdd);
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
     };
   }, []);
 
   const loadNotes = async () => {
     try {
       const result = await window.__TAURI__.invoke('search_notes', { query: '' });
-      setNotes(result || []);
+      setNotes(result?.notes || []);
     } catch (error) {
       console.error('Failed to load notes:', error);
     }
@@ -80,7 +89,10 @@ const App = () => {
       }
 
       const result = await window.__TAURI__.invoke('search_notes', { query });
-      setNotes(result || []);
+      setNotes(result?.notes || []);
+      if (result?.truncated) {
+        console.warn('Search results were truncated; try a more specific query.');
+      }
     } catch (error) {
       console.error('Search failed:', error);
     }
@@ -88,12 +100,15 @@ const App = () => {
 
   const handleNoteSelect = (note: Note) => {
     setSelectedNoteId(note.id);
+    // Feeds the access counter behind stale_notes; a failure here shouldn't block opening
+    window.__TAURI__.invoke('record_note_open', { id: note.id }).catch((error: unknown) => {
+      console.error('Failed to record note open:', error);
+    });
   };
 
   const handleSaveNote = async (title: string, content: string) => {
     try {
-      await window.__TAURI__.invoke('add_note', { title, content });
-      await loadNotes(); // Refresh list
+      await window.__TAURI__.invoke('add_note', { title, content, draft_key: QUICK_ADD_DRAFT_KEY });
       
       // Auto-focus search after adding
       document.querySelector('.search-bar')?.focus();
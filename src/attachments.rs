@@ -0,0 +1,175 @@
+//! Small files (diagrams, error screenshots) attached to notes
+//!
+//! Attachments are stored as blobs in their own table, so they stay out of the FTS index
+//! and note lists; deleting a note deletes its attachments (see `db::migrate`).
+
+use crate::error::QuickNoteError;
+use rusqlite::Connection;
+use serde::Serialize;
+
+/// Attachment details without the bytes, for listing
+#[derive(Debug, Serialize)]
+pub struct AttachmentInfo {
+    pub id: u64,
+    pub note_id: u64,
+    pub filename: String,
+    pub mime: String,
+    pub size: usize,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Attachment {
+    #[serde(flatten)]
+    pub info: AttachmentInfo,
+    pub data: Vec<u8>,
+}
+
+/// Attach `data` to a note, guessing the MIME type from the file extension.
+/// Returns the new attachment's id.
+pub fn add_attachment(
+    conn: &Connection,
+    note_id: u64,
+    filename: &str,
+    data: &[u8],
+    max_bytes: usize,
+) -> Result<u64, QuickNoteError> {
+    if data.len() > max_bytes {
+        return Err(QuickNoteError::AttachmentTooLarge { size: data.len(), limit: max_bytes });
+    }
+    let filename = filename.trim();
+    if filename.is_empty() {
+        return Err(QuickNoteError::InvalidInput("Attachment filename must not be empty".to_string()));
+    }
+    let exists: bool = conn.query_row("SELECT COUNT(*) > 0 FROM notes WHERE id = ?1", [note_id], |row| row.get(0))?;
+    if !exists {
+        return Err(QuickNoteError::NotFound(note_id));
+    }
+
+    conn.execute(
+        "INSERT INTO attachments (note_id, filename, mime, data, size) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![note_id, filename, guess_mime(filename), data, data.len() as i64],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+/// A note's attachments, oldest first
+pub fn list_attachments(conn: &Connection, note_id: u64) -> Result<Vec<AttachmentInfo>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, filename, mime, size, created_at FROM attachments WHERE note_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([note_id], |row| {
+        Ok(AttachmentInfo {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            filename: row.get(2)?,
+            mime: row.get(3)?,
+            size: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn get_attachment(conn: &Connection, id: u64) -> Result<Attachment, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, filename, mime, size, created_at, data FROM attachments WHERE id = ?1",
+    )?;
+    let mut rows = stmt.query([id])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Attachment {
+            info: AttachmentInfo {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                filename: row.get(2)?,
+                mime: row.get(3)?,
+                size: row.get(4)?,
+                created_at: row.get(5)?,
+            },
+            data: row.get(6)?,
+        }),
+        None => Err(QuickNoteError::InvalidInput(format!("Attachment {} not found", id))),
+    }
+}
+
+/// MIME type for common attachment extensions; anything else is `application/octet-stream`
+fn guess_mime(filename: &str) -> &'static str {
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "sql" => "application/sql",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    #[test]
+    fn binary_attachment_round_trips_byte_for_byte() {
+        let conn = test_vault();
+        let note = test_note(&conn, "Error screenshot", "see attachment");
+        let data: Vec<u8> = (0..=255).chain([0, 0, 255]).collect();
+
+        let id = add_attachment(&conn, note, " shot.PNG ", &data, 1024).unwrap();
+        let attachment = get_attachment(&conn, id).unwrap();
+        assert_eq!(attachment.data, data);
+        assert_eq!(attachment.info.filename, "shot.PNG");
+        assert_eq!(attachment.info.mime, "image/png");
+        assert_eq!(attachment.info.size, data.len());
+
+        let listed = list_attachments(&conn, note).unwrap();
+        assert_eq!(listed.iter().map(|a| a.id).collect::<Vec<_>>(), vec![id]);
+    }
+
+    #[test]
+    fn size_cap_is_inclusive() {
+        let conn = test_vault();
+        let note = test_note(&conn, "Note", "");
+        assert!(add_attachment(&conn, note, "a.bin", &[1; 4], 4).is_ok());
+        assert!(matches!(
+            add_attachment(&conn, note, "b.bin", &[1; 5], 4),
+            Err(QuickNoteError::AttachmentTooLarge { size: 5, limit: 4 })
+        ));
+    }
+
+    #[test]
+    fn attachment_text_stays_out_of_search() {
+        let conn = test_vault();
+        let note = test_note(&conn, "Note", "body");
+        add_attachment(&conn, note, "log.txt", b"attachedneedle", 1024).unwrap();
+
+        let hits: i64 = conn
+            .query_row("SELECT COUNT(*) FROM notes_fts WHERE notes_fts MATCH 'attachedneedle'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(hits, 0);
+    }
+
+    #[test]
+    fn attaching_to_missing_note_is_not_found() {
+        let conn = test_vault();
+        assert!(matches!(add_attachment(&conn, 42, "a.txt", b"x", 10), Err(QuickNoteError::NotFound(42))));
+    }
+
+    #[test]
+    fn deleting_note_deletes_its_attachments() {
+        let conn = test_vault();
+        let note = test_note(&conn, "Note", "");
+        let id = add_attachment(&conn, note, "a.txt", b"x", 10).unwrap();
+        conn.execute("DELETE FROM notes WHERE id = ?1", [note]).unwrap();
+        assert!(get_attachment(&conn, id).is_err());
+    }
+}
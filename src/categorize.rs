@@ -0,0 +1,708 @@
+//! Knowledge types and the auto-categorizer shared by the CLI and the Tauri backend
+
+use crate::config::TagCase;
+use crate::tags::extract_tags;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KnowledgeType {
+    Concept,
+    Snippet,
+    Checklist,
+    Note,
+    Process,
+    SQLQuery,
+    DebugPattern,
+}
+
+impl KnowledgeType {
+    /// Value stored in the `knowledge_type` column (must match the CHECK constraint)
+    pub fn as_db_str(&self) -> &'static str {
+        match self {
+            Self::Concept => "Concept",
+            Self::Snippet => "Snippet",
+            Self::Checklist => "Checklist",
+            Self::Note => "Note",
+            Self::Process => "Process",
+            Self::SQLQuery => "SQLQuery",
+            Self::DebugPattern => "DebugPattern",
+        }
+    }
+
+    /// Parse a stored `knowledge_type` value
+    pub fn from_db_str(value: &str) -> Option<Self> {
+        match value {
+            "Concept" => Some(Self::Concept),
+            "Snippet" => Some(Self::Snippet),
+            "Checklist" => Some(Self::Checklist),
+            "Note" => Some(Self::Note),
+            "Process" => Some(Self::Process),
+            "SQLQuery" => Some(Self::SQLQuery),
+            "DebugPattern" => Some(Self::DebugPattern),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for KnowledgeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Concept => write!(f, "Concept"),
+            Self::Snippet => write!(f, "Snippet"),
+            Self::Checklist => write!(f, "Checklist"),
+            Self::Note => write!(f, "Note"),
+            Self::Process => write!(f, "Process"),
+            Self::SQLQuery => write!(f, "SQL Query"),
+            Self::DebugPattern => write!(f, "Debug Pattern"),
+        }
+    }
+}
+
+/// Extra categorizer keywords from `config.json`, e.g. a Python pack mapping `traceback`
+/// to `DebugPattern` or a French one mapping `erreur`. Packs add to the built-in signals;
+/// they never remove any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeywordPack {
+    pub name: String,
+    /// Knowledge type (as stored, e.g. `DebugPattern`) → substrings that signal it,
+    /// matched case-insensitively against the content
+    pub patterns: BTreeMap<String, Vec<String>>,
+}
+
+impl KeywordPack {
+    /// Reject packs naming unknown types or containing blank patterns
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("pack name must not be empty".to_string());
+        }
+        for (kind, patterns) in &self.patterns {
+            if KnowledgeType::from_db_str(kind).is_none() {
+                return Err(format!("unknown knowledge type '{}'", kind));
+            }
+            if patterns.iter().any(|p| p.trim().is_empty()) {
+                return Err(format!("blank pattern for {}", kind));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Below this confidence the UI should ask the user to confirm the type
+#[cfg(feature = "gui")]
+pub const LOW_CONFIDENCE: f64 = 0.5;
+
+/// Categorizer output with how sure it is of the type
+#[derive(Debug, Clone, Serialize)]
+pub struct Categorization {
+    pub knowledge_type: KnowledgeType,
+    pub tags: Vec<String>,
+    /// 0.0-1.0, see `evaluate_rules`
+    pub confidence: f64,
+}
+
+/// Auto-categorize note based on content patterns; `default_type` is used when none match.
+/// `skip_code_tags` ignores `#words` in code (see `tags::extract_tags`); `packs` add
+/// keywords to the built-in rules.
+pub fn categorize_note(
+    content: &str,
+    title: &str,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> (KnowledgeType, Vec<String>) {
+    let categorization = categorize_with_confidence(content, title, tag_case, default_type, skip_code_tags, packs);
+    (categorization.knowledge_type, categorization.tags)
+}
+
+/// `categorize_note` plus a confidence score for the chosen type
+pub fn categorize_with_confidence(
+    content: &str,
+    title: &str,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Categorization {
+    let tags = extract_tags(content, tag_case, skip_code_tags);
+    let (knowledge_type, confidence) = classify(content, title, default_type, packs);
+    Categorization { knowledge_type, tags, confidence }
+}
+
+/// One categorizer rule, as evaluated by `explain`
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTrace {
+    /// `sql_keywords`, `debug_keywords`, `numbered_title`, `pack_keywords` or `default_type`
+    pub rule: &'static str,
+    pub knowledge_type: KnowledgeType,
+    pub matched: bool,
+    /// Distinct keywords (or numbered steps) found for the rule's type
+    pub signals: Vec<String>,
+    /// Keyword packs with a pattern among `signals`
+    pub packs: Vec<String>,
+    /// What the rule scores its type if it wins (0 when it didn't match)
+    pub confidence: f64,
+    /// The first matching rule, which decides the type
+    pub won: bool,
+}
+
+/// Every categorizer rule in evaluation order with what it found, for support to see why
+/// a note got its type. Exactly one rule has `won` set (the default always matches).
+#[cfg(feature = "gui")]
+pub fn explain(content: &str, title: &str, default_type: KnowledgeType, packs: &[KeywordPack]) -> Vec<RuleTrace> {
+    let mut rules = evaluate_rules(content, title, default_type, packs);
+    if let Some(winner) = rules.iter_mut().find(|rule| rule.matched) {
+        winner.won = true;
+    }
+    rules
+}
+
+/// Pick the type and score it: the first matching rule of `evaluate_rules`
+fn classify(content: &str, title: &str, default_type: KnowledgeType, packs: &[KeywordPack]) -> (KnowledgeType, f64) {
+    evaluate_rules(content, title, default_type, packs)
+        .into_iter()
+        .find(|rule| rule.matched)
+        .map_or((default_type, 0.2), |rule| (rule.knowledge_type, rule.confidence))
+}
+
+/// The categorizer rules in order: SQL, then debug, then a numbered process, then any
+/// other type a keyword pack has a match for, else `default_type`. A pack pattern
+/// triggers its type's rule and counts as a signal. Confidence grows with the number of
+/// distinct signals for the type: one signal scores 0.5, each further one adds 0.15,
+/// capped at 0.95. Falling through to the default means nothing matched, so it scores 0.2.
+fn evaluate_rules(content: &str, title: &str, default_type: KnowledgeType, packs: &[KeywordPack]) -> Vec<RuleTrace> {
+    let lower_content = content.to_lowercase();
+    let lower_title = title.to_lowercase();
+    let score = |matched: usize| (0.5 + 0.15 * matched.saturating_sub(1) as f64).min(0.95);
+    // Distinct signals for `kind` found in the content, and the packs they came from
+    let signals = |kind: KnowledgeType, builtin: &[&str]| {
+        let mut found: Vec<String> = builtin.iter().filter(|s| lower_content.contains(*s)).map(|s| s.to_string()).collect();
+        let mut hit_packs = Vec::new();
+        for pack in packs {
+            for pattern in pack.patterns.get(kind.as_db_str()).into_iter().flatten() {
+                let pattern = pattern.to_lowercase();
+                if lower_content.contains(&pattern) {
+                    if !hit_packs.contains(&pack.name) {
+                        hit_packs.push(pack.name.clone());
+                    }
+                    if !found.contains(&pattern) {
+                        found.push(pattern);
+                    }
+                }
+            }
+        }
+        (found, hit_packs)
+    };
+    let rule = |rule, knowledge_type, matched, signals: Vec<String>, packs, confidence| RuleTrace {
+        rule,
+        knowledge_type,
+        matched,
+        signals,
+        packs,
+        confidence: if matched { confidence } else { 0.0 },
+        won: false,
+    };
+    let mut rules = Vec::new();
+
+    let (found, hit_packs) = signals(
+        KnowledgeType::SQLQuery,
+        &["select", "from ", "insert into", "where ", "join ", "group by", "order by", "update ", "create table"],
+    );
+    let matched = lower_content.contains("select") || lower_content.contains("from ") || lower_content.contains("insert into") || !hit_packs.is_empty();
+    let confidence = score(found.len());
+    rules.push(rule("sql_keywords", KnowledgeType::SQLQuery, matched, found, hit_packs, confidence));
+
+    let (found, hit_packs) = signals(
+        KnowledgeType::DebugPattern,
+        &["error", "exception", "panic", "stack trace", "traceback", "fix", "caused by"],
+    );
+    let matched = lower_content.contains("error") || lower_content.contains("exception") || lower_content.contains("panic") || !hit_packs.is_empty();
+    let confidence = score(found.len());
+    rules.push(rule("debug_keywords", KnowledgeType::DebugPattern, matched, found, hit_packs, confidence));
+
+    // Each numbered step in the body is another signal
+    let steps: Vec<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+        .collect();
+    let matched = lower_title.starts_with(|c: char| c.is_ascii_digit()) && content.split('\n').count() > 3;
+    let confidence = score(steps.len());
+    rules.push(rule("numbered_title", KnowledgeType::Process, matched, steps, Vec::new(), confidence));
+
+    // Types without a built-in keyword rule can still be picked by a pack
+    for kind in [
+        KnowledgeType::Process,
+        KnowledgeType::Snippet,
+        KnowledgeType::Checklist,
+        KnowledgeType::Note,
+        KnowledgeType::Concept,
+    ] {
+        let (found, hit_packs) = signals(kind, &[]);
+        let confidence = score(found.len());
+        rules.push(rule("pack_keywords", kind, !found.is_empty(), found, hit_packs, confidence));
+    }
+
+    // Nothing matched: the configured fallback (Concept unless changed)
+    rules.push(rule("default_type", default_type, true, Vec::new(), Vec::new(), 0.2));
+    rules
+}
+
+/// SQL dialect of a `SQLQuery` note, guessed from dialect-specific syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SqlDialect {
+    Postgres,
+    MySql,
+    SqlServer,
+    Unknown,
+}
+
+impl SqlDialect {
+    pub fn as_db_str(&self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::MySql => "mysql",
+            Self::SqlServer => "sqlserver",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn from_db_str(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            "mysql" => Some(Self::MySql),
+            "sqlserver" | "mssql" => Some(Self::SqlServer),
+            "unknown" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// Guess the dialect from keywords only one dialect uses. No signals, or signals from
+/// more than one dialect, give `Unknown` rather than a coin flip.
+pub fn detect_sql_dialect(content: &str) -> SqlDialect {
+    // Fence markers (```sql) would otherwise count as MySQL backtick quoting
+    let code: String = content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let upper = code.to_uppercase();
+
+    let postgres = ["SERIAL", "RETURNING", "ILIKE", "JSONB", "::"].iter().any(|k| upper.contains(k));
+    let mysql = upper.contains("AUTO_INCREMENT") || upper.contains("ENGINE=") || code.contains('`');
+    let sqlserver = upper.contains("IDENTITY(") || upper.contains(" TOP ") || upper.contains("GETDATE()");
+
+    match (postgres, mysql, sqlserver) {
+        (true, false, false) => SqlDialect::Postgres,
+        (false, true, false) => SqlDialect::MySql,
+        (false, false, true) => SqlDialect::SqlServer,
+        _ => SqlDialect::Unknown,
+    }
+}
+
+/// Syntax markers distinctive enough to identify a language, used by `detect_language`
+const LANGUAGE_MARKERS: &[(&str, &[&str])] = &[
+    ("rust", &["fn ", "let mut ", "impl ", "pub fn", "-> ", "&str", "println!", "use std::", "Option<", "Result<", "#[derive"]),
+    ("python", &["def ", "elif ", "self.", "print(", "__init__", "import ", "None", "lambda ", "except ", "True:"]),
+    ("javascript", &["function ", "const ", "=> ", "console.log", "===", "require(", "document.", "let "]),
+    ("typescript", &["interface ", ": string", ": number", ": boolean", "export type ", "as const", "=> "]),
+    ("go", &["func ", "package ", ":= ", "fmt.", "err != nil", "go func", "chan "]),
+    ("java", &["public class ", "public static void", "System.out", "private ", "@Override", "new ArrayList"]),
+    ("shell", &["#!/bin/", "echo ", "sudo ", "| grep", "export ", "fi\n", "$("]),
+];
+
+/// Fence info strings that name the same language as a `LANGUAGE_MARKERS` key
+fn language_alias(hint: &str) -> &str {
+    match hint {
+        "rs" => "rust",
+        "py" | "python3" => "python",
+        "js" | "jsx" | "node" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "golang" => "go",
+        "sh" | "bash" | "zsh" | "console" => "shell",
+        other => other,
+    }
+}
+
+/// Normalize a language name or fence hint (`rs`, `Python`) to what `detect_language` stores
+pub fn normalize_language(name: &str) -> String {
+    language_alias(&name.trim().to_lowercase()).to_string()
+}
+
+/// Guess a snippet's language. An explicit fence hint (```` ```rust ````) always wins;
+/// otherwise the language with the most distinct syntax markers, needing at least two
+/// and a clear lead over the runner-up. `None` when unsure.
+pub fn detect_language(content: &str) -> Option<String> {
+    let hint = content.lines().find_map(|line| {
+        let trimmed = line.trim_start();
+        let info = trimmed.strip_prefix("```").or_else(|| trimmed.strip_prefix("~~~"))?;
+        info.split_whitespace().next().map(|word| word.trim_matches(|c| c == '{' || c == '}' || c == '.'))
+    });
+    if let Some(hint) = hint.filter(|hint| !hint.is_empty()) {
+        return Some(normalize_language(hint));
+    }
+
+    let mut scores: Vec<(usize, &str)> = LANGUAGE_MARKERS
+        .iter()
+        .map(|(language, markers)| (markers.iter().filter(|m| content.contains(*m)).count(), *language))
+        .collect();
+    scores.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    match scores.as_slice() {
+        [(best, language), (second, _), ..] if *best >= 2 && *best > *second => Some(language.to_string()),
+        _ => None,
+    }
+}
+
+/// Longest title `smart_paste` proposes, in characters
+#[cfg(feature = "gui")]
+const PASTE_TITLE_CHARS: usize = 60;
+
+/// What `smart_paste` suggests for pasted text; nothing is saved until the user confirms
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Serialize)]
+pub struct PasteProposal {
+    pub title: String,
+    pub knowledge_type: KnowledgeType,
+    pub tags: Vec<String>,
+    pub confidence: f64,
+    /// The pasted text tidied up for its type, e.g. code wrapped in a fence
+    pub body: String,
+}
+
+/// Guess structure from the shape of pasted text: a stack trace becomes a `DebugPattern`,
+/// a block of SQL a `SQLQuery` (both fenced as code), a numbered list a `Process` and a
+/// bulleted list a `Checklist` (bullets turned into `- [ ]` boxes). Anything else gets
+/// the regular categorizer's verdict with the text unchanged.
+#[cfg(feature = "gui")]
+pub fn smart_paste(
+    text: &str,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> PasteProposal {
+    let text = text.replace("\r\n", "\n");
+    let text = text.trim_matches('\n').trim_end();
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let fenced = text.trim_start().starts_with("```") || text.trim_start().starts_with("~~~");
+    let share = |pred: &dyn Fn(&str) -> bool| {
+        !lines.is_empty() && lines.iter().filter(|l| pred(l.trim_start())).count() * 2 > lines.len()
+    };
+
+    let Categorization { mut knowledge_type, mut confidence, .. } =
+        categorize_with_confidence(text, "", tag_case, default_type, skip_code_tags, packs);
+    let mut title = lines.first().map(|l| l.trim()).unwrap_or_default();
+    let mut body = text.to_string();
+
+    if let Some(trace_title) = stack_trace_title(&lines) {
+        knowledge_type = KnowledgeType::DebugPattern;
+        confidence = 0.9;
+        title = trace_title;
+        if !fenced {
+            body = format!("```\n{}\n```", text);
+        }
+    } else if knowledge_type == KnowledgeType::SQLQuery {
+        if !fenced && share(&looks_like_sql) {
+            body = format!("```sql\n{}\n```", text);
+        }
+    } else if lines.len() >= 2 && share(&|l| numbered_item(l).is_some()) {
+        knowledge_type = KnowledgeType::Process;
+        confidence = 0.8;
+    } else if lines.len() >= 2 && share(&|l| bullet_item(l).is_some()) {
+        knowledge_type = KnowledgeType::Checklist;
+        confidence = 0.8;
+        body = text
+            .lines()
+            .map(|line| match bullet_item(line.trim_start()) {
+                Some(item) if !item.starts_with("[ ]") && !item.to_lowercase().starts_with("[x]") => {
+                    format!("{}- [ ] {}", &line[..line.len() - line.trim_start().len()], item)
+                }
+                _ => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    // A list's first line is usually an item, not a heading
+    let title = numbered_item(title)
+        .or_else(|| bullet_item(title))
+        .unwrap_or(title)
+        .trim_start_matches(['#', '`', '~'])
+        .trim();
+    let title = match title.char_indices().nth(PASTE_TITLE_CHARS) {
+        Some((cut, _)) => format!("{}…", title[..cut].trim_end()),
+        None => title.to_string(),
+    };
+
+    PasteProposal {
+        title,
+        knowledge_type,
+        tags: extract_tags(&body, tag_case, skip_code_tags),
+        confidence,
+        body,
+    }
+}
+
+/// The error line of a Python, Java/JS or Rust stack trace, if `lines` look like one
+#[cfg(feature = "gui")]
+fn stack_trace_title<'a>(lines: &[&'a str]) -> Option<&'a str> {
+    let is_frame = |line: &str| {
+        let line = line.trim_start();
+        line.starts_with("at ")
+            || line.starts_with("Traceback (most recent call last)")
+            || line.starts_with("stack backtrace:")
+            || (line.starts_with("File \"") && line.contains(", line "))
+            || line.split_once(": ").is_some_and(|(n, _)| n.parse::<u32>().is_ok())
+    };
+    if lines.iter().filter(|l| is_frame(l)).count() < 2 {
+        return None;
+    }
+
+    let is_error = |line: &&&str| {
+        !is_frame(line) && ["Error", "Exception", "panicked"].iter().any(|k| line.contains(k))
+    };
+    // Python puts the error last; Java, JS and Rust put it first
+    let error = if lines[0].starts_with("Traceback") {
+        lines.iter().rev().find(is_error)
+    } else {
+        lines.iter().find(is_error)
+    };
+    Some(error.unwrap_or(&lines[0]).trim())
+}
+
+#[cfg(feature = "gui")]
+fn looks_like_sql(line: &str) -> bool {
+    let upper = line.to_uppercase();
+    ["SELECT", "FROM", "WHERE", "JOIN", "INSERT", "UPDATE", "DELETE", "CREATE", "GROUP BY", "ORDER BY", "AND", "OR", "SET", "VALUES", "LIMIT", "HAVING", ")", "--"]
+        .iter()
+        .any(|k| upper.starts_with(k))
+}
+
+/// `1. step` or `1) step` → `step`
+#[cfg(feature = "gui")]
+pub fn numbered_item(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == line.len() {
+        return None;
+    }
+    rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") "))
+}
+
+/// `- item`, `* item` or `• item` → `item`
+#[cfg(feature = "gui")]
+pub fn bullet_item(line: &str) -> Option<&str> {
+    ["- ", "* ", "• "].iter().find_map(|b| line.strip_prefix(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_unambiguous_sql_dialects() {
+        assert_eq!(detect_sql_dialect("CREATE TABLE t (id SERIAL PRIMARY KEY)"), SqlDialect::Postgres);
+        assert_eq!(detect_sql_dialect("INSERT INTO t (a) VALUES (1) RETURNING id"), SqlDialect::Postgres);
+        assert_eq!(detect_sql_dialect("CREATE TABLE t (id INT AUTO_INCREMENT)"), SqlDialect::MySql);
+        assert_eq!(detect_sql_dialect("SELECT `name` FROM `users`"), SqlDialect::MySql);
+        assert_eq!(detect_sql_dialect("SELECT TOP 10 * FROM users"), SqlDialect::SqlServer);
+        assert_eq!(detect_sql_dialect("CREATE TABLE t (id INT IDENTITY(1,1))"), SqlDialect::SqlServer);
+    }
+
+    #[test]
+    fn sql_dialect_falls_back_to_unknown() {
+        assert_eq!(detect_sql_dialect("SELECT id FROM users WHERE active = 1"), SqlDialect::Unknown);
+        // Conflicting signals aren't a coin flip
+        assert_eq!(detect_sql_dialect("SELECT `id` FROM t RETURNING id"), SqlDialect::Unknown);
+        // A ```sql fence isn't MySQL quoting
+        assert_eq!(detect_sql_dialect("```sql\nSELECT 1\n```"), SqlDialect::Unknown);
+    }
+
+    fn confidence(content: &str) -> (KnowledgeType, f64) {
+        let result = categorize_with_confidence(content, "", TagCase::Lower, KnowledgeType::Concept, false, &[]);
+        (result.knowledge_type, result.confidence)
+    }
+
+    #[test]
+    fn strong_sql_signal_scores_high() {
+        let (kind, score) =
+            confidence("SELECT email, COUNT(*) FROM users u JOIN orders o ON o.uid = u.id WHERE x GROUP BY email ORDER BY 2");
+        assert_eq!(kind, KnowledgeType::SQLQuery);
+        assert!(score >= 0.9, "{}", score);
+        assert!(score <= 0.95);
+    }
+
+    #[test]
+    fn single_signal_scores_half() {
+        assert_eq!(confidence("the panic was harmless"), (KnowledgeType::DebugPattern, 0.5));
+    }
+
+    #[test]
+    fn default_type_scores_low() {
+        let result = categorize_with_confidence("just a thought", "", TagCase::Lower, KnowledgeType::Note, false, &[]);
+        assert_eq!(result.knowledge_type, KnowledgeType::Note);
+        assert_eq!(result.confidence, 0.2);
+    }
+
+    #[cfg(feature = "gui")]
+    fn paste(text: &str) -> PasteProposal {
+        smart_paste(text, TagCase::Lower, KnowledgeType::Concept, false, &[])
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn stack_trace_becomes_fenced_debug_pattern() {
+        let trace = "Traceback (most recent call last):\n  File \"app.py\", line 3, in <module>\n    main()\n  File \"app.py\", line 1, in main\nKeyError: 'user'";
+        let proposal = paste(trace);
+        assert_eq!(proposal.knowledge_type, KnowledgeType::DebugPattern);
+        assert_eq!(proposal.title, "KeyError: 'user'");
+        assert_eq!(proposal.body, format!("```\n{}\n```", trace));
+
+        let java = "java.lang.NullPointerException: name\n    at com.acme.App.run(App.java:12)\n    at com.acme.App.main(App.java:5)";
+        assert_eq!(paste(java).title, "java.lang.NullPointerException: name");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn numbered_list_becomes_process() {
+        let proposal = paste("1. Stop the service\n2. Run the migration\n3) Start it again");
+        assert_eq!(proposal.knowledge_type, KnowledgeType::Process);
+        assert_eq!(proposal.title, "Stop the service");
+        assert_eq!(proposal.body, "1. Stop the service\n2. Run the migration\n3) Start it again");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn bullets_become_checklist_boxes() {
+        let proposal = paste("- milk\n  * eggs\n- [x] bread");
+        assert_eq!(proposal.knowledge_type, KnowledgeType::Checklist);
+        assert_eq!(proposal.body, "- [ ] milk\n  - [ ] eggs\n- [x] bread");
+        assert_eq!(proposal.title, "milk");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn sql_block_is_fenced_once() {
+        let sql = "SELECT email, COUNT(*)\nFROM users\nJOIN orders ON orders.uid = users.id\nGROUP BY email";
+        let proposal = paste(sql);
+        assert_eq!(proposal.knowledge_type, KnowledgeType::SQLQuery);
+        assert_eq!(proposal.body, format!("```sql\n{}\n```", sql));
+
+        let fenced = format!("```sql\n{}\n```", sql);
+        assert_eq!(paste(&fenced).body, fenced);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn plain_text_is_left_alone_and_long_titles_cut() {
+        let long = "a".repeat(80);
+        let proposal = paste(&format!("{}\r\nsecond line\r\n", long));
+        assert_eq!(proposal.body, format!("{}\nsecond line", long));
+        assert_eq!(proposal.title, format!("{}…", "a".repeat(PASTE_TITLE_CHARS)));
+    }
+
+    fn pack(name: &str, kind: &str, patterns: &[&str]) -> KeywordPack {
+        KeywordPack {
+            name: name.to_string(),
+            patterns: BTreeMap::from([(kind.to_string(), patterns.iter().map(|p| p.to_string()).collect())]),
+        }
+    }
+
+    #[test]
+    fn custom_pack_classifies_non_english_errors() {
+        let note = "Erreur de connexion : le serveur ne répond plus après la mise à jour";
+        assert_eq!(confidence(note).0, KnowledgeType::Concept);
+
+        let packs = [pack("français", "DebugPattern", &["ERREUR", "répond plus"])];
+        let result = categorize_with_confidence(note, "", TagCase::Lower, KnowledgeType::Concept, false, &packs);
+        assert_eq!(result.knowledge_type, KnowledgeType::DebugPattern);
+        // Two distinct signals
+        assert_eq!(result.confidence, 0.65);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn explain_names_the_pack_that_decided() {
+        let packs = [pack("français", "DebugPattern", &["erreur"])];
+        let trace = explain("Erreur de connexion", "", KnowledgeType::Concept, &packs);
+        let winner = trace.iter().find(|rule| rule.won).unwrap();
+        assert_eq!(winner.knowledge_type, KnowledgeType::DebugPattern);
+        assert_eq!(winner.packs, ["français"]);
+    }
+
+    #[test]
+    fn packs_only_add_to_builtin_rules() {
+        // A pack for another type doesn't stop SQL from being recognized
+        let packs = [pack("notes", "Note", &["memo"])];
+        let (kind, _) = categorize_note("SELECT id FROM memo", "", TagCase::Lower, KnowledgeType::Concept, false, &packs);
+        assert_eq!(kind, KnowledgeType::SQLQuery);
+        let (kind, _) = categorize_note("memo to self", "", TagCase::Lower, KnowledgeType::Concept, false, &packs);
+        assert_eq!(kind, KnowledgeType::Note);
+    }
+
+    #[test]
+    fn pack_validation_rejects_bad_structure() {
+        assert!(pack("python", "DebugPattern", &["traceback", "raise"]).validate().is_ok());
+        assert_eq!(pack("x", "Bug", &["oops"]).validate().unwrap_err(), "unknown knowledge type 'Bug'");
+        assert_eq!(pack("x", "Snippet", &["fn", " "]).validate().unwrap_err(), "blank pattern for Snippet");
+        assert!(pack(" ", "Snippet", &["fn"]).validate().is_err());
+    }
+
+    #[test]
+    fn detects_rust_and_python_from_syntax() {
+        let rust = "pub fn parse(input: &str) -> Result<u32, String> {\n    let mut total = 0;\n}";
+        let python = "def parse(self, text):\n    if not text:\n        return None\n    print(text)";
+        assert_eq!(detect_language(rust).as_deref(), Some("rust"));
+        assert_eq!(detect_language(python).as_deref(), Some("python"));
+        // One marker, or a tie, isn't enough to go on
+        assert_eq!(detect_language("just some prose with -> an arrow"), None);
+    }
+
+    #[test]
+    fn explicit_fence_language_beats_heuristics() {
+        let python_looking = "```rs\ndef not_python(self):\n    print(self.x)\n```";
+        assert_eq!(detect_language(python_looking).as_deref(), Some("rust"));
+        assert_eq!(detect_language("~~~ {.Python3}\nx = 1\n~~~").as_deref(), Some("python"));
+        // An unlabelled fence falls back to the markers
+        assert_eq!(detect_language("```\nfunc main() {\n    fmt.Println(x)\n}\n```").as_deref(), Some("go"));
+        assert_eq!(normalize_language(" TS "), "typescript");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn trace_shows_sql_rule_winning_before_the_default() {
+        let trace = explain("SELECT id FROM users WHERE active = 1", "Active users", KnowledgeType::Note, &[]);
+        let rules: Vec<(&str, bool, bool)> = trace.iter().map(|r| (r.rule, r.matched, r.won)).collect();
+        assert_eq!(rules.first(), Some(&("sql_keywords", true, true)));
+        assert_eq!(rules.last(), Some(&("default_type", true, false)));
+        assert_eq!(trace.iter().filter(|r| r.won).count(), 1);
+
+        let sql = &trace[0];
+        assert_eq!(sql.signals, ["select", "from ", "where "]);
+        assert_eq!(sql.confidence, 0.8);
+        // Rules that didn't match score nothing, and the trace agrees with the categorizer
+        assert!(trace.iter().filter(|r| !r.matched).all(|r| r.confidence == 0.0));
+        let categorized = categorize_with_confidence(
+            "SELECT id FROM users WHERE active = 1",
+            "Active users",
+            TagCase::Lower,
+            KnowledgeType::Note,
+            false,
+            &[],
+        );
+        assert_eq!((categorized.knowledge_type, categorized.confidence), (sql.knowledge_type, sql.confidence));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn trace_falls_back_to_the_default_type() {
+        let trace = explain("just a thought", "", KnowledgeType::Note, &[]);
+        let winner = trace.iter().find(|rule| rule.won).unwrap();
+        assert_eq!((winner.rule, winner.knowledge_type, winner.confidence), ("default_type", KnowledgeType::Note, 0.2));
+        assert!(trace[..trace.len() - 1].iter().all(|rule| !rule.matched));
+    }
+}
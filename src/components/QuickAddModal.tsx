@@ -1,5 +1,9 @@
 import React, { useState, useEffect } from 'react';
 
+// Draft slot for the quick-add form; add_note clears it once the note is saved
+export const QUICK_ADD_DRAFT_KEY = 'quick-add';
+const DRAFT_DEBOUNCE_MS = 800;
+
 interface QuickAddModalProps {
   isOpen: boolean;
   onClose: () => void;
@@ -21,6 +25,30 @@ export const QuickAddModal: React.FC<QuickAddModalProps> = ({
     }
   }, [isOpen]);
 
+  // Restore whatever was being typed before a crash or accidental close
+  useEffect(() => {
+    if (!isOpen) return;
+    window.__TAURI__.invoke('load_draft', { key: QUICK_ADD_DRAFT_KEY })
+      .then((draft) => {
+        if (!draft) return;
+        const saved = JSON.parse(draft.content);
+        setTitle(saved.title ?? '');
+        setContent(saved.content ?? '');
+      })
+      .catch((error) => console.warn('Failed to load draft:', error));
+  }, [isOpen]);
+
+  useEffect(() => {
+    if (!isOpen || (!title && !content)) return;
+    const timer = setTimeout(() => {
+      window.__TAURI__.invoke('save_draft', {
+        key: QUICK_ADD_DRAFT_KEY,
+        content: JSON.stringify({ title, content }),
+      }).catch((error) => console.warn('Failed to save draft:', error));
+    }, DRAFT_DEBOUNCE_MS);
+    return () => clearTimeout(timer);
+  }, [isOpen, title, content]);
+
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
       if (!isOpen) return;
@@ -0,0 +1,645 @@
+//! User configuration, read from `config.json`
+//!
+//! The file is looked up in order: the path in `QUICKNOTE_CONFIG`, next to the executable
+//! (portable mode), then the per-user OS config folder. When none exists, defaults are
+//! written to the first of those locations that is writable.
+
+use crate::categorize::{categorize_note, KeywordPack, KnowledgeType};
+use crate::db::FtsMode;
+use crate::search::Weights;
+#[cfg(feature = "gui")]
+use crate::tags::extract_tags;
+use crate::tags::{merge_tags, normalize_tag};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+pub const DEFAULT_PORTABLE_DIR: &str = "data";
+
+/// Environment variable naming a config file to use instead of the usual locations
+pub const CONFIG_ENV: &str = "QUICKNOTE_CONFIG";
+const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub gui_mode: bool,
+    pub modules: Vec<String>,
+    pub encryption_enabled: bool,
+    /// Length of the content preview shown in list views, in characters
+    pub preview_chars: usize,
+    /// Largest note content accepted on add/update/append, in UTF-8 bytes
+    pub max_note_bytes: usize,
+    /// Largest file accepted by `attachments::add_attachment`, in bytes
+    pub max_attachment_bytes: usize,
+    /// Hard cap on search results, applied even when the caller asks for no limit
+    pub search_max_results: usize,
+    /// Also write a plain `notes.jsonl` and per-note Markdown next to unencrypted vault
+    /// backups, in case the ZIP gets corrupted
+    pub backup_plaintext_sidecar: bool,
+    /// bm25 weights of a search term found in the title vs. in the content; the default
+    /// ranks a title match well above the same word buried in a long note
+    pub title_weight: f64,
+    pub content_weight: f64,
+    /// Shortest query, in characters (not bytes), that search will run (shorter ones match
+    /// nothing)
+    pub min_query_len: usize,
+    /// chrono `strftime` pattern for the formatted dates in list responses (UTC);
+    /// `None` means ISO-8601
+    pub date_format: Option<String>,
+    /// Folder next to the executable that holds the vault in portable mode
+    pub portable_dir_name: String,
+    /// Spaced-repetition scheduler tuning
+    pub sm2: Sm2Params,
+    /// Let `next_review_card` pick at random among equally overdue cards instead of the
+    /// oldest note first
+    pub review_shuffle_ties: bool,
+    /// Whether `#SQL` and `#sql` are the same tag
+    pub tag_case: TagCase,
+    /// How the search index stores note text: `external` (no duplication, the default)
+    /// or `in_table` (a copy of the text, simpler to keep in sync). The vault is
+    /// migrated at startup when this changes; see `db::FtsMode`.
+    pub fts_mode: FtsMode,
+    /// Don't harvest `#words` inside code fences and inline code spans as tags
+    pub skip_code_tags: bool,
+    /// Keyword → tag rules for `auto_tag_all`, e.g. `{"docker": "containers"}`. Keywords
+    /// match whole words, case-insensitively; a trailing `*` (`dock*`) matches prefixes.
+    pub tag_keywords: BTreeMap<String, String>,
+    /// Extra categorizer keywords, e.g. `[{"name": "python", "patterns": {"DebugPattern":
+    /// ["traceback", "raise "]}}]`. Invalid packs are skipped with a warning.
+    pub keyword_packs: Vec<KeywordPack>,
+    /// Fail searches that hit a stored knowledge type QuickNote doesn't know, instead of
+    /// showing it as Concept; useful while checking a migration or import
+    pub strict_knowledge_types: bool,
+    /// Refuse to add a note whose title another note already has (ignoring case)
+    pub unique_titles: bool,
+    /// Words left out of `text_stats` top terms; unset uses a basic English list
+    pub stopwords: Option<Vec<String>>,
+    /// What a Ctrl+K quick capture is saved as
+    pub capture: CaptureConfig,
+    /// Knowledge type for notes no categorizer rule matches, e.g. `Note` or `Snippet`
+    pub default_type: String,
+    /// Force lean queries (no previews, fewer search results) on or off; unset switches
+    /// them on automatically once the vault reaches `large_vault_threshold` notes
+    pub performance_mode: Option<bool>,
+    pub large_vault_threshold: u64,
+    /// Default log filter (`error`, `warn`, `info`, `debug`, `trace`); `RUST_LOG` overrides it
+    pub log_level: String,
+    /// Problems found while loading, for the caller to log once logging is set up
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+    /// File the config was read from or written to; `None` if defaults couldn't be saved
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            gui_mode: true,
+            modules: vec!["search".to_string(), "categorize".to_string()],
+            encryption_enabled: false,
+            preview_chars: 140,
+            max_note_bytes: 1024 * 1024,
+            max_attachment_bytes: 5 * 1024 * 1024,
+            search_max_results: 500,
+            min_query_len: 2,
+            title_weight: 10.0,
+            content_weight: 1.0,
+            backup_plaintext_sidecar: false,
+            date_format: None,
+            portable_dir_name: DEFAULT_PORTABLE_DIR.to_string(),
+            sm2: Sm2Params::default(),
+            review_shuffle_ties: false,
+            tag_case: TagCase::default(),
+            fts_mode: FtsMode::default(),
+            skip_code_tags: true,
+            tag_keywords: BTreeMap::new(),
+            keyword_packs: Vec::new(),
+            strict_knowledge_types: false,
+            unique_titles: false,
+            stopwords: None,
+            capture: CaptureConfig::default(),
+            default_type: KnowledgeType::Concept.as_db_str().to_string(),
+            performance_mode: None,
+            large_vault_threshold: 10_000,
+            log_level: "info".to_string(),
+            warnings: Vec::new(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load `config.json` (see the module docs for where from), falling back to defaults
+    pub fn load() -> Self {
+        let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()));
+        let dirs: Vec<PathBuf> = exe_dir.into_iter().chain(os_config_dir()).collect();
+        Self::load_from(env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()).map(PathBuf::from), &dirs)
+    }
+
+    /// `load` with the lookup locations spelled out: an explicit file, else `config.json`
+    /// in each of `dirs`, in order
+    fn load_from(env_path: Option<PathBuf>, dirs: &[PathBuf]) -> Self {
+        let candidates: Vec<PathBuf> = match env_path {
+            Some(path) => vec![path],
+            None => dirs.iter().map(|dir| dir.join(CONFIG_FILE)).collect(),
+        };
+
+        let mut config = match candidates.iter().find(|path| path.is_file()) {
+            Some(path) => {
+                let mut config: Self = match fs::read_to_string(path) {
+                    Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                        let mut config = Self::default();
+                        config.warnings.push(format!("Invalid config.json ({}), using defaults", e));
+                        config
+                    }),
+                    Err(_) => Self::default(),
+                };
+                config.path = Some(path.clone());
+                config
+            }
+            None => Self::write_default(&candidates),
+        };
+
+        if let Err(e) = config.sm2.validate() {
+            config.warnings.push(format!("Invalid sm2 settings in config.json ({}), using defaults", e));
+            config.sm2 = Sm2Params::default();
+        }
+        if KnowledgeType::from_db_str(&config.default_type).is_none() {
+            config.warnings.push(format!("Invalid default_type '{}' in config.json, using Concept", config.default_type));
+            config.default_type = KnowledgeType::Concept.as_db_str().to_string();
+        }
+        let weights = [config.title_weight, config.content_weight];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().all(|w| *w == 0.0) {
+            config.warnings.push(format!(
+                "Invalid title_weight/content_weight in config.json ({}, {}): weights must be non-negative and not both zero, using defaults",
+                config.title_weight, config.content_weight
+            ));
+            let defaults = Self::default();
+            config.title_weight = defaults.title_weight;
+            config.content_weight = defaults.content_weight;
+        }
+        if let Some(kind) = config.capture.knowledge_type.as_deref().filter(|k| KnowledgeType::from_db_str(k).is_none()) {
+            config.warnings.push(format!("Invalid capture.knowledge_type '{}' in config.json, ignoring it", kind));
+            config.capture.knowledge_type = None;
+        }
+        let tag_case = config.tag_case;
+        let mut capture_tags: Vec<String> = Vec::new();
+        for raw in std::mem::take(&mut config.capture.tags) {
+            match normalize_tag(&raw, tag_case) {
+                Some(tag) if !capture_tags.contains(&tag) => capture_tags.push(tag),
+                Some(_) => {}
+                None => config.warnings.push(format!("Skipping capture tag '{}' in config.json (not a valid tag)", raw)),
+            }
+        }
+        config.capture.tags = capture_tags;
+
+        let mut names = Vec::new();
+        config.keyword_packs.retain(|pack| {
+            let checked = pack.validate().and_then(|_| {
+                if names.contains(&pack.name) {
+                    Err("duplicate pack name".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+            match checked {
+                Ok(()) => {
+                    names.push(pack.name.clone());
+                    true
+                }
+                Err(e) => {
+                    config.warnings.push(format!("Skipping keyword pack '{}' in config.json ({})", pack.name, e));
+                    false
+                }
+            }
+        });
+        config
+    }
+
+    /// Defaults, saved to the first of `candidates` that can be written
+    fn write_default(candidates: &[PathBuf]) -> Self {
+        let mut config = Self::default();
+        let json = match serde_json::to_string_pretty(&config) {
+            Ok(json) => json,
+            Err(_) => return config,
+        };
+
+        let mut errors = Vec::new();
+        for path in candidates {
+            let written = match path.parent() {
+                Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(path, &json)),
+                None => fs::write(path, &json),
+            };
+            match written {
+                Ok(()) => {
+                    config.path = Some(path.clone());
+                    return config;
+                }
+                Err(e) => errors.push(format!("{} ({})", path.display(), e)),
+            }
+        }
+        config.warnings.push(format!("Could not save a default config.json: {}", errors.join(", ")));
+        config
+    }
+
+    /// Knowledge type and tags for a note added from the composer or the CLI: the
+    /// categorizer's type, and the `explicit` tags followed by the `#tags` in the content
+    pub fn note_fields(&self, title: &str, content: &str, explicit: &[String]) -> (KnowledgeType, Vec<String>) {
+        let (kind, extracted) = categorize_note(content, title, self.tag_case, self.default_knowledge_type(), self.skip_code_tags, &self.keyword_packs);
+        (kind, merge_tags(explicit, extracted, self.tag_case))
+    }
+
+    /// `title_weight`/`content_weight` for `search::search_notes`
+    pub fn search_weights(&self) -> Weights {
+        Weights { title: self.title_weight, content: self.content_weight }
+    }
+
+    /// Knowledge type and tags for a quick capture: the composer's `explicit` tags, then
+    /// `capture.tags`, then the `#tags` in the content. With `capture.auto_categorize` the
+    /// categorizer picks the type; otherwise it's `capture.knowledge_type`, if set.
+    #[cfg(feature = "gui")]
+    pub fn capture_fields(&self, title: &str, content: &str, explicit: &[String]) -> (Option<KnowledgeType>, Vec<String>) {
+        let explicit: Vec<String> = explicit.iter().chain(&self.capture.tags).cloned().collect();
+        let (kind, extracted) = if self.capture.auto_categorize {
+            let (kind, tags) = categorize_note(content, title, self.tag_case, self.default_knowledge_type(), self.skip_code_tags, &self.keyword_packs);
+            (Some(kind), tags)
+        } else {
+            let kind = self.capture.knowledge_type.as_deref().and_then(KnowledgeType::from_db_str);
+            (kind, extract_tags(content, self.tag_case, self.skip_code_tags))
+        };
+        (kind, merge_tags(&explicit, extracted, self.tag_case))
+    }
+
+    /// `default_type` as a knowledge type (validated by `load`)
+    pub fn default_knowledge_type(&self) -> KnowledgeType {
+        KnowledgeType::from_db_str(&self.default_type).unwrap_or(KnowledgeType::Concept)
+    }
+}
+
+/// Per-user config folder: `%APPDATA%\QuickNote` on Windows, `~/Library/Application
+/// Support/QuickNote` on macOS, else `$XDG_CONFIG_HOME/quicknote` (`~/.config/quicknote`)
+fn os_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("QuickNote"))
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support/QuickNote"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("quicknote"))
+    }
+}
+
+/// Tag casing: `lower` folds tags to lowercase on extraction and lookup (and existing
+/// mixed-case tags are folded at startup); `preserve` keeps them as typed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagCase {
+    Preserve,
+    #[default]
+    Lower,
+}
+
+/// Quick-capture defaults. Out of the box a capture keeps no type (so it waits for
+/// recategorization, like any inbox note) and only the tags typed into it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Knowledge type given to captures when `auto_categorize` is off, e.g. `Note`
+    pub knowledge_type: Option<String>,
+    /// Tags added to every capture, e.g. `["inbox"]` (normalized like typed tags)
+    pub tags: Vec<String>,
+    /// Run the categorizer on captures instead of using `knowledge_type`
+    pub auto_categorize: bool,
+}
+
+/// Largest `Sm2Params::interval_fuzz_percent`; more would make the schedule meaningless
+pub const MAX_INTERVAL_FUZZ_PERCENT: f64 = 25.0;
+
+/// SM-2 constants. After each review the easiness factor changes by
+/// `easiness_bonus - (5 - q) * (penalty_linear + (5 - q) * penalty_quadratic)`
+/// for response quality `q`, and never drops below `easiness_floor`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sm2Params {
+    pub easiness_floor: f64,
+    pub easiness_bonus: f64,
+    pub penalty_linear: f64,
+    pub penalty_quadratic: f64,
+    /// Interval in days after the first and second successful reviews
+    pub first_interval: i64,
+    pub second_interval: i64,
+    /// Suspend a card once it has lapsed this many times; unset never suspends
+    pub leech_lapses: Option<i64>,
+    /// Jitter each new interval by up to ± this percentage so cards rated together don't
+    /// all come due on the same day; 0 (the default) schedules exactly
+    pub interval_fuzz_percent: f64,
+}
+
+impl Default for Sm2Params {
+    fn default() -> Self {
+        Self {
+            easiness_floor: 1.3,
+            easiness_bonus: 0.1,
+            penalty_linear: 0.08,
+            penalty_quadratic: 0.02,
+            first_interval: 1,
+            second_interval: 6,
+            leech_lapses: None,
+            interval_fuzz_percent: 0.0,
+        }
+    }
+}
+
+impl Sm2Params {
+    /// Reject values that would stall or reverse scheduling. NaN and infinities fail every
+    /// check: NaN compares false against any bound, so it has to be ruled out explicitly.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.easiness_floor.is_finite() || self.easiness_floor < 1.0 {
+            return Err(format!("easiness_floor must be at least 1.0, got {}", self.easiness_floor));
+        }
+        let steps = [self.easiness_bonus, self.penalty_linear, self.penalty_quadratic];
+        if steps.iter().any(|step| !step.is_finite() || *step < 0.0) {
+            return Err("easiness_bonus and penalties must be finite and not negative".to_string());
+        }
+        if self.first_interval < 1 || self.second_interval < self.first_interval {
+            return Err(format!(
+                "intervals must satisfy 1 <= first_interval <= second_interval, got {} and {}",
+                self.first_interval, self.second_interval
+            ));
+        }
+        if self.leech_lapses.is_some_and(|lapses| lapses < 1) {
+            return Err("leech_lapses must be at least 1".to_string());
+        }
+        if !(0.0..=MAX_INTERVAL_FUZZ_PERCENT).contains(&self.interval_fuzz_percent) {
+            return Err(format!(
+                "interval_fuzz_percent must be between 0 and {}, got {}",
+                MAX_INTERVAL_FUZZ_PERCENT, self.interval_fuzz_percent
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a config.json with `json` from a fresh scratch folder
+    fn load_json(name: &str, json: &str) -> Config {
+        let dir = scratch_dir(name);
+        fs::write(dir.join(CONFIG_FILE), json).unwrap();
+        let config = Config::load_from(None, std::slice::from_ref(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+        config
+    }
+
+    #[test]
+    fn portable_dir_name_is_read_from_config() {
+        assert_eq!(load_json("portable", r#"{"portable_dir_name": "quicknote-data"}"#).portable_dir_name, "quicknote-data");
+        assert_eq!(load_json("portable-default", "{}").portable_dir_name, DEFAULT_PORTABLE_DIR);
+    }
+
+    #[test]
+    fn sm2_defaults_are_valid() {
+        assert!(Sm2Params::default().validate().is_ok());
+    }
+
+    #[test]
+    fn sm2_rejects_out_of_range_and_non_finite_values() {
+        let invalid = [
+            Sm2Params { easiness_floor: 0.9, ..Sm2Params::default() },
+            Sm2Params { easiness_floor: f64::NAN, ..Sm2Params::default() },
+            Sm2Params { easiness_floor: f64::INFINITY, ..Sm2Params::default() },
+            Sm2Params { easiness_bonus: -0.1, ..Sm2Params::default() },
+            Sm2Params { easiness_bonus: f64::NAN, ..Sm2Params::default() },
+            Sm2Params { penalty_linear: f64::NAN, ..Sm2Params::default() },
+            Sm2Params { penalty_quadratic: f64::INFINITY, ..Sm2Params::default() },
+            Sm2Params { first_interval: 0, ..Sm2Params::default() },
+            Sm2Params { first_interval: 7, second_interval: 6, ..Sm2Params::default() },
+            Sm2Params { leech_lapses: Some(0), ..Sm2Params::default() },
+            Sm2Params { interval_fuzz_percent: -1.0, ..Sm2Params::default() },
+            Sm2Params { interval_fuzz_percent: MAX_INTERVAL_FUZZ_PERCENT + 1.0, ..Sm2Params::default() },
+            Sm2Params { interval_fuzz_percent: f64::NAN, ..Sm2Params::default() },
+        ];
+        for params in invalid {
+            assert!(params.validate().is_err(), "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn invalid_sm2_config_falls_back_to_defaults() {
+        let config = load_json("sm2-invalid", r#"{"sm2": {"easiness_floor": 0.5}}"#);
+        assert_eq!(config.sm2.easiness_floor, Sm2Params::default().easiness_floor);
+        assert!(config.warnings[0].contains("sm2"), "{:?}", config.warnings);
+    }
+
+    #[test]
+    fn unreadable_config_uses_default_portable_dir() {
+        let config = load_json("portable-broken", "{ not json");
+        assert_eq!(config.portable_dir_name, DEFAULT_PORTABLE_DIR);
+        assert_eq!(config.warnings.len(), 1);
+    }
+
+    #[test]
+    fn default_type_is_validated() {
+        let config = load_json("default-type", r#"{"default_type": "Snippet"}"#);
+        assert_eq!(config.default_knowledge_type(), KnowledgeType::Snippet);
+        assert!(config.warnings.is_empty());
+
+        let config = load_json("default-type-bad", r#"{"default_type": "Bogus"}"#);
+        assert_eq!(config.default_knowledge_type(), KnowledgeType::Concept);
+        assert_eq!(config.warnings.len(), 1);
+    }
+
+    #[test]
+    fn ambiguous_content_adopts_default_but_rules_win() {
+        let config = load_json("default-type-rules", r#"{"default_type": "Note"}"#);
+        let categorize = |content: &str, packs: &[KeywordPack]| {
+            crate::categorize::categorize_note(content, "", config.tag_case, config.default_knowledge_type(), false, packs).0
+        };
+        let packs = [KeywordPack {
+            name: "tasks".to_string(),
+            patterns: BTreeMap::from([("Checklist".to_string(), vec!["todo".to_string()])]),
+        }];
+
+        assert_eq!(categorize("an idle thought #idea", &[]), KnowledgeType::Note);
+        assert_eq!(categorize("SELECT 1 FROM dual", &[]), KnowledgeType::SQLQuery);
+        assert_eq!(categorize("todo: buy milk", &packs), KnowledgeType::Checklist);
+    }
+
+    #[test]
+    fn code_tags_are_skipped_by_default() {
+        assert!(load_json("skip-code-default", "{}").skip_code_tags);
+        assert!(!load_json("skip-code-off", r#"{"skip_code_tags": false}"#).skip_code_tags);
+    }
+
+    /// A fresh scratch folder for the lookup-chain tests
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("quicknote-config-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn env_override_wins_over_both_folders() {
+        let dir = scratch_dir("env");
+        let (exe_dir, os_dir) = (dir.join("exe"), dir.join("os"));
+        for folder in [&exe_dir, &os_dir] {
+            fs::create_dir_all(folder).unwrap();
+            fs::write(folder.join(CONFIG_FILE), r#"{"large_vault_threshold": 1}"#).unwrap();
+        }
+        let custom = dir.join("custom.json");
+        fs::write(&custom, r#"{"large_vault_threshold": 7}"#).unwrap();
+
+        let config = Config::load_from(Some(custom.clone()), &[exe_dir, os_dir]);
+        assert_eq!(config.large_vault_threshold, 7);
+        assert_eq!(config.path, Some(custom));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exe_folder_is_preferred_over_os_folder() {
+        let dir = scratch_dir("portable");
+        let (exe_dir, os_dir) = (dir.join("exe"), dir.join("os"));
+        for (folder, threshold) in [(&exe_dir, 2), (&os_dir, 3)] {
+            fs::create_dir_all(folder).unwrap();
+            fs::write(folder.join(CONFIG_FILE), format!(r#"{{"large_vault_threshold": {}}}"#, threshold)).unwrap();
+        }
+
+        let config = Config::load_from(None, &[exe_dir.clone(), os_dir]);
+        assert_eq!(config.large_vault_threshold, 2);
+        assert_eq!(config.path, Some(exe_dir.join(CONFIG_FILE)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn os_folder_is_used_when_exe_folder_has_no_config() {
+        let dir = scratch_dir("installed");
+        let (exe_dir, os_dir) = (dir.join("exe"), dir.join("os"));
+        fs::create_dir_all(&exe_dir).unwrap();
+        fs::create_dir_all(&os_dir).unwrap();
+        fs::write(os_dir.join(CONFIG_FILE), r#"{"large_vault_threshold": 3}"#).unwrap();
+
+        let config = Config::load_from(None, &[exe_dir.clone(), os_dir.clone()]);
+        assert_eq!(config.large_vault_threshold, 3);
+        assert_eq!(config.path, Some(os_dir.join(CONFIG_FILE)));
+        // Nothing is written next to the executable when a config was found
+        assert!(!exe_dir.join(CONFIG_FILE).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn defaults_are_written_to_first_writable_folder() {
+        let dir = scratch_dir("write");
+        // A file where the exe folder should be makes that location unwritable
+        let read_only = dir.join("exe");
+        fs::write(&read_only, "").unwrap();
+        let os_dir = dir.join("os");
+
+        let config = Config::load_from(None, &[read_only, os_dir.clone()]);
+        assert_eq!(config.path, Some(os_dir.join(CONFIG_FILE)));
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        let saved: Config = serde_json::from_str(&fs::read_to_string(os_dir.join(CONFIG_FILE)).unwrap()).unwrap();
+        assert_eq!(saved.large_vault_threshold, Config::default().large_vault_threshold);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_writable_keeps_defaults_with_a_warning() {
+        let dir = scratch_dir("unwritable");
+        let blocker = dir.join("blocker");
+        fs::write(&blocker, "").unwrap();
+
+        let config = Config::load_from(None, &[blocker.join("a"), blocker.join("b")]);
+        assert_eq!(config.path, None);
+        assert_eq!(config.large_vault_threshold, Config::default().large_vault_threshold);
+        assert!(config.warnings.iter().any(|w| w.starts_with("Could not save a default config.json")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_keyword_packs_are_skipped_with_warnings() {
+        let config = load_json(
+            "packs",
+            r#"{"keyword_packs": [
+                {"name": "python", "patterns": {"DebugPattern": ["traceback", "raise"]}},
+                {"name": "bad-type", "patterns": {"Bug": ["oops"]}},
+                {"name": "python", "patterns": {"Snippet": ["def "]}}
+            ]}"#,
+        );
+        let names: Vec<&str> = config.keyword_packs.iter().map(|pack| pack.name.as_str()).collect();
+        assert_eq!(names, ["python"]);
+        assert_eq!(config.warnings.len(), 2, "{:?}", config.warnings);
+        assert!(config.warnings[1].contains("duplicate pack name"));
+
+        // A pack with unexpected keys isn't a pack; the whole file is rejected
+        let config = load_json("packs-unknown-key", r#"{"keyword_packs": [{"name": "x", "patterns": {}, "extra": 1}]}"#);
+        assert!(config.keyword_packs.is_empty());
+        assert!(config.warnings[0].starts_with("Invalid config.json"));
+    }
+
+    #[test]
+    fn added_notes_are_categorized_with_explicit_tags_first() {
+        let config = Config::default();
+        let (kind, tags) = config.note_fields("Active users", "SELECT id FROM users WHERE active = 1 #sql", &["Reporting".to_string()]);
+        assert_eq!(kind, KnowledgeType::SQLQuery);
+        assert_eq!(tags, vec!["reporting", "sql"]);
+        assert_eq!(config.note_fields("", "plain", &[]), (KnowledgeType::Concept, Vec::new()));
+    }
+
+    #[test]
+    fn search_weights_are_validated() {
+        let defaults = Config::default().search_weights();
+        assert_eq!(defaults, Weights { title: 10.0, content: 1.0 });
+
+        let tuned = load_json("weights", r#"{"title_weight": 2.5, "content_weight": 0}"#);
+        assert_eq!(tuned.search_weights(), Weights { title: 2.5, content: 0.0 });
+        assert!(tuned.warnings.is_empty());
+
+        for json in [r#"{"title_weight": -1}"#, r#"{"title_weight": 0, "content_weight": 0}"#] {
+            let config = load_json("weights-invalid", json);
+            assert_eq!(config.search_weights(), defaults, "{}", json);
+            assert!(config.warnings.iter().any(|w| w.contains("title_weight")), "{}", json);
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn capture_defaults_apply_when_not_auto_categorizing() {
+        let config = load_json("capture", r##"{"capture": {"knowledge_type": "Note", "tags": ["#Inbox", "inbox", "🙂"]}}"##);
+        assert_eq!(config.capture.tags, vec!["inbox"]);
+        assert!(config.warnings.iter().any(|w| w.contains("capture tag")));
+
+        // SQL content would categorize as SQLQuery, but auto-categorization is off
+        let (kind, tags) = config.capture_fields("Query", "SELECT id FROM users #db", &["urgent".to_string()]);
+        assert_eq!(kind, Some(KnowledgeType::Note));
+        assert_eq!(tags, vec!["urgent", "inbox", "db"]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn capture_auto_categorize_or_invalid_type() {
+        let auto = load_json("capture-auto", r#"{"capture": {"knowledge_type": "Note", "auto_categorize": true}}"#);
+        let (kind, _) = auto.capture_fields("Query", "SELECT id FROM users WHERE active = 1", &[]);
+        assert_eq!(kind, Some(KnowledgeType::SQLQuery));
+
+        let invalid = load_json("capture-invalid", r#"{"capture": {"knowledge_type": "Bogus"}}"#);
+        assert!(invalid.capture.knowledge_type.is_none());
+        assert!(invalid.warnings.iter().any(|w| w.contains("capture.knowledge_type")));
+        assert_eq!(Config::default().capture_fields("", "plain", &[]), (None, Vec::new()));
+    }
+
+    #[test]
+    fn fts_mode_is_read_from_config() {
+        assert_eq!(load_json("fts-mode", r#"{"fts_mode": "in_table"}"#).fts_mode, FtsMode::InTable);
+        assert_eq!(load_json("fts-mode-default", "{}").fts_mode, FtsMode::External);
+    }
+}
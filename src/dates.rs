@@ -0,0 +1,59 @@
+//! Human-readable timestamps for API responses
+//!
+//! Timestamps are stored as UTC epoch seconds and formatted in UTC, so the same note
+//! renders identically on every machine; the raw epoch is always returned alongside.
+
+#[cfg(feature = "gui")]
+use chrono::format::{Item, StrftimeItems};
+#[cfg(feature = "gui")]
+use chrono::{DateTime, SecondsFormat};
+
+/// Format `epoch` with a chrono `strftime` pattern. An invalid pattern (or no pattern)
+/// falls back to ISO-8601 rather than failing the whole response.
+#[cfg(feature = "gui")]
+pub fn format_epoch(epoch: i64, pattern: Option<&str>) -> String {
+    let Some(time) = DateTime::from_timestamp(epoch, 0) else {
+        return epoch.to_string();
+    };
+
+    match pattern {
+        Some(pattern) if is_valid_pattern(pattern) => time.format(pattern).to_string(),
+        _ => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// chrono only reports a bad specifier when the formatter runs (as a `fmt::Error` panic
+/// in `to_string`), so check the parsed items up front
+#[cfg(feature = "gui")]
+fn is_valid_pattern(pattern: &str) -> bool {
+    !StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error))
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+
+    // 2024-03-09T23:30:00Z: already March 10 east of UTC
+    const LATE_EVENING: i64 = 1_710_027_000;
+
+    #[test]
+    fn custom_pattern_formats_in_utc() {
+        assert_eq!(format_epoch(LATE_EVENING, Some("%d %b %Y %H:%M")), "09 Mar 2024 23:30");
+        assert_eq!(format_epoch(LATE_EVENING, Some("%Z")), "UTC");
+    }
+
+    #[test]
+    fn default_is_iso_8601() {
+        assert_eq!(format_epoch(LATE_EVENING, None), "2024-03-09T23:30:00Z");
+    }
+
+    #[test]
+    fn invalid_pattern_falls_back_to_iso_8601() {
+        assert_eq!(format_epoch(LATE_EVENING, Some("%Q oops")), "2024-03-09T23:30:00Z");
+    }
+
+    #[test]
+    fn out_of_range_epoch_is_returned_raw() {
+        assert_eq!(format_epoch(i64::MAX, None), i64::MAX.to_string());
+    }
+}
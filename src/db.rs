@@ -0,0 +1,755 @@
+//! Vault schema shared by the CLI and the Tauri backend
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+/// Open the vault database file. A failure is logged at error level before it is returned.
+pub fn open(path: &std::path::Path) -> Result<rusqlite::Connection, rusqlite::Error> {
+    rusqlite::Connection::open(path).inspect_err(|e| tracing::error!("Failed to open database at {:?}: {}", path, e))
+}
+
+/// Create the notes table, FTS index and sync triggers if they don't exist yet
+pub fn init_schema(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    // Create notes table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            knowledge_type TEXT CHECK(knowledge_type IN
+                ('Concept', 'Snippet', 'Checklist', 'Note', 'Process', 'SQLQuery', 'DebugPattern')),
+            tags TEXT DEFAULT '[]',
+            created_at INTEGER DEFAULT (strftime('%s', 'now')),
+            updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+
+    // Create FTS5 virtual table for full-text search. Some system SQLite builds lack
+    // the FTS5 module; the vault still works then, with search falling back to LIKE.
+    // New vaults start in external-content mode; `set_fts_mode` switches afterwards.
+    match create_fts_table(conn, FtsMode::External) {
+        Ok(()) => create_fts_triggers(conn, FtsMode::External)?,
+        Err(e) => tracing::warn!("FTS5 unavailable ({}), falling back to LIKE search", e),
+    }
+
+    migrate(conn)
+}
+
+/// How the FTS index holds note text.
+///
+/// `External` (`content='notes'`) stores only the index and reads text back from
+/// `notes`, so nothing is duplicated; but every delete must hand FTS5 the exact text
+/// that was indexed, and a row that drifted out of sync (written with the triggers
+/// missing, say) corrupts the index until it's rebuilt. `InTable` keeps its own copy
+/// of each title and body, roughly doubling the text on disk, and in exchange deletes
+/// and updates go by rowid alone, so a stale or missing row can't poison the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtsMode {
+    #[default]
+    External,
+    InTable,
+}
+
+fn create_fts_table(conn: &rusqlite::Connection, mode: FtsMode) -> Result<(), rusqlite::Error> {
+    let options = match mode {
+        FtsMode::External => ",\n            content='notes',\n            content_rowid='id'",
+        FtsMode::InTable => "",
+    };
+    conn.execute(
+        &format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+            title, content{}
+        )",
+            options
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Triggers to keep the FTS index in sync with `notes`
+fn create_fts_triggers(conn: &rusqlite::Connection, mode: FtsMode) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS notes_ai AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END",
+        [],
+    )?;
+
+    // External-content FTS needs the old row removed, with its old text, before the
+    // new one is indexed; an in-table index can drop the row by id
+    let remove_old = match mode {
+        FtsMode::External => "INSERT INTO notes_fts(notes_fts, rowid, title, content) VALUES ('delete', old.id, old.title, old.content);",
+        FtsMode::InTable => "DELETE FROM notes_fts WHERE rowid = old.id;",
+    };
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS notes_au AFTER UPDATE OF title, content ON notes BEGIN
+            {}
+            INSERT INTO notes_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END",
+            remove_old
+        ),
+        [],
+    )?;
+
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS notes_ad AFTER DELETE ON notes BEGIN
+            {}
+        END",
+            remove_old
+        ),
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Current FTS mode, or `None` when the vault has no FTS index
+pub fn fts_mode(conn: &rusqlite::Connection) -> Result<Option<FtsMode>, rusqlite::Error> {
+    let sql: Option<String> = conn
+        .query_row("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'notes_fts'", [], |row| row.get(0))
+        .optional()?;
+    Ok(sql.map(|sql| if sql.contains("content='notes'") { FtsMode::External } else { FtsMode::InTable }))
+}
+
+/// Recreate the FTS index, and its triggers, in `mode` and re-index every note. Returns
+/// false, changing nothing, when the index is already in that mode or there is none
+/// (no FTS5 module).
+pub fn set_fts_mode(conn: &mut rusqlite::Connection, mode: FtsMode) -> Result<bool, rusqlite::Error> {
+    if fts_mode(conn)?.is_none_or(|current| current == mode) {
+        return Ok(false);
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "DROP TRIGGER IF EXISTS notes_ai;
+         DROP TRIGGER IF EXISTS notes_au;
+         DROP TRIGGER IF EXISTS notes_ad;
+         DROP TABLE notes_fts;",
+    )?;
+    create_fts_table(&tx, mode)?;
+    create_fts_triggers(&tx, mode)?;
+    rebuild_fts(&tx)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Re-index every note from `notes`, for writes made behind the triggers' back. A no-op
+/// without an FTS index.
+pub fn rebuild_fts(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    if search_backend(conn)? != SearchBackend::Fts5 {
+        return Ok(());
+    }
+    match fts_mode(conn)? {
+        Some(FtsMode::External) => conn.execute_batch("INSERT INTO notes_fts(notes_fts) VALUES ('rebuild');"),
+        // 'rebuild' would re-read the index's own copy, not `notes`
+        Some(FtsMode::InTable) => conn.execute_batch(
+            "DELETE FROM notes_fts;
+             INSERT INTO notes_fts(rowid, title, content) SELECT id, title, content FROM notes;",
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Which implementation `search_notes` uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SearchBackend {
+    Fts5,
+    Like,
+}
+
+/// FTS5 when the index exists and the linked SQLite has the module, LIKE otherwise
+pub fn search_backend(conn: &rusqlite::Connection) -> Result<SearchBackend, rusqlite::Error> {
+    let has_index: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'notes_fts'",
+        [],
+        |row| row.get(0),
+    )?;
+    let has_module: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_compile_options WHERE compile_options = 'ENABLE_FTS5'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(if has_index && has_module { SearchBackend::Fts5 } else { SearchBackend::Like })
+}
+
+pub fn note_count(conn: &rusqlite::Connection) -> Result<u64, rusqlite::Error> {
+    conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))
+}
+
+/// Size class of the vault, used to pick lean query defaults for big vaults
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VaultTier {
+    Normal,
+    Large,
+}
+
+#[cfg(feature = "gui")]
+pub fn vault_tier(note_count: u64, large_threshold: u64) -> VaultTier {
+    if note_count >= large_threshold {
+        VaultTier::Large
+    } else {
+        VaultTier::Normal
+    }
+}
+
+/// Compact the FTS index and refresh the query planner's statistics
+#[cfg(feature = "gui")]
+pub fn optimize(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    if search_backend(conn)? == SearchBackend::Fts5 {
+        conn.execute("INSERT INTO notes_fts(notes_fts) VALUES ('optimize')", [])?;
+    }
+    conn.execute_batch("ANALYZE; PRAGMA optimize;")
+}
+
+/// Runtime facts about the vault, for support and the settings screen
+#[cfg(feature = "gui")]
+pub fn diagnostics(conn: &rusqlite::Connection) -> Result<serde_json::Value, rusqlite::Error> {
+    let sqlite_version: String = conn.query_row("SELECT sqlite_version()", [], |row| row.get(0))?;
+    let note_count = note_count(conn)?;
+
+    Ok(serde_json::json!({
+        "sqlite_version": sqlite_version,
+        "search_backend": search_backend(conn)?,
+        "fts_mode": fts_mode(conn)?,
+        "note_count": note_count,
+    }))
+}
+
+/// SQL for epoch milliseconds within the epoch second `seconds`: the current millisecond
+/// if that second is now (`'now'` is fixed for the whole statement), else its start
+fn epoch_ms(seconds: &str) -> String {
+    format!(
+        "{s} * 1000 + CASE WHEN {s} = CAST(strftime('%s', 'now') AS INTEGER)
+                          THEN CAST(ROUND(strftime('%f', 'now') * 1000) AS INTEGER) % 1000
+                          ELSE 0 END",
+        s = seconds
+    )
+}
+
+/// Bring an existing vault up to the current schema
+pub fn migrate(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    // User-chosen types survive re-categorization
+    add_column_if_missing(conn, "notes", "type_locked", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // SM-2 review state; a NULL review_due means the note isn't enrolled in review
+    add_column_if_missing(conn, "notes", "review_due", "INTEGER")?;
+    add_column_if_missing(conn, "notes", "review_interval", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "notes", "review_streak", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "notes", "review_easiness", "REAL NOT NULL DEFAULT 2.5")?;
+    add_column_if_missing(conn, "notes", "review_suspended", "INTEGER NOT NULL DEFAULT 0")?;
+    // Times the card was rated "again", for spotting notes that need rewriting
+    add_column_if_missing(conn, "notes", "review_lapses", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Secret notes: content holds ciphertext, so responses only ever expose the title
+    add_column_if_missing(conn, "notes", "encrypted", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Provenance (book, URL, conversation); free text, NULL when unknown
+    add_column_if_missing(conn, "notes", "source", "TEXT")?;
+
+    // Read-only reference notes; unrelated to type_locked, which only pins the category
+    add_column_if_missing(conn, "notes", "is_locked", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Archived notes stay in the vault but drop out of default listings, search and review
+    add_column_if_missing(conn, "notes", "is_archived", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Triage: hotkey captures (`quick_capture`) wait in the inbox until processed;
+    // everything else, including existing notes, starts out processed
+    add_column_if_missing(conn, "notes", "is_processed", "INTEGER NOT NULL DEFAULT 1")?;
+
+    // Canonical references (e.g. a team runbook) listed before ranked results in any
+    // search they match
+    add_column_if_missing(conn, "notes", "always_top", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Triage priority: 0 none, 1 low, 2 medium, 3 high (see notes::set_priority)
+    add_column_if_missing(conn, "notes", "priority", "INTEGER NOT NULL DEFAULT 0 CHECK(priority BETWEEN 0 AND 3)")?;
+
+    // Access counter: bumped whenever the app opens a note (notes::record_open), so
+    // stale_notes can tell old-but-still-read notes from forgotten ones
+    add_column_if_missing(conn, "notes", "open_count", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "notes", "last_opened_at", "INTEGER")?;
+
+    // One-off "follow up by" reminders (epoch seconds), unrelated to review_due
+    add_column_if_missing(conn, "notes", "remind_at", "INTEGER")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_remind_at ON notes(remind_at) WHERE remind_at IS NOT NULL", [])?;
+
+    // Per-note derived facts as a JSON object, e.g. {"sql_dialect": "postgres"}
+    add_column_if_missing(conn, "notes", "metadata", "TEXT NOT NULL DEFAULT '{}'")?;
+
+    // Millisecond created/updated times, so notes written within the same second still
+    // sort in the order they were written. created_at/updated_at stay the source of truth
+    // (every write sets those); triggers derive these from them, using the current
+    // milliseconds when the second is "now" and the start of that second otherwise
+    // (imports, backfilled rows).
+    add_column_if_missing(conn, "notes", "created_at_ms", "INTEGER")?;
+    add_column_if_missing(conn, "notes", "updated_at_ms", "INTEGER")?;
+    conn.execute("UPDATE notes SET created_at_ms = created_at * 1000 WHERE created_at_ms IS NULL", [])?;
+    conn.execute("UPDATE notes SET updated_at_ms = updated_at * 1000 WHERE updated_at_ms IS NULL", [])?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS notes_ms_ai AFTER INSERT ON notes
+             WHEN new.created_at_ms IS NULL OR new.updated_at_ms IS NULL BEGIN
+                UPDATE notes SET created_at_ms = COALESCE(new.created_at_ms, {}),
+                                 updated_at_ms = COALESCE(new.updated_at_ms, {})
+                WHERE id = new.id;
+            END",
+            epoch_ms("new.created_at"),
+            epoch_ms("new.updated_at")
+        ),
+        [],
+    )?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS notes_created_ms_au AFTER UPDATE OF created_at ON notes
+             WHEN new.created_at IS NOT old.created_at BEGIN
+                UPDATE notes SET created_at_ms = {} WHERE id = new.id;
+            END",
+            epoch_ms("new.created_at")
+        ),
+        [],
+    )?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS notes_updated_ms_au AFTER UPDATE OF updated_at ON notes BEGIN
+                UPDATE notes SET updated_at_ms = {} WHERE id = new.id;
+            END",
+            epoch_ms("new.updated_at")
+        ),
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_updated_at_ms ON notes(updated_at_ms)", [])?;
+
+    // Exact-title lookups (find_by_title, link resolution) match case-insensitively
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_title ON notes(title COLLATE NOCASE)", [])?;
+
+    // [[wiki-links]]; to_id is NULL while no note has the target title
+    let links_exist: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'note_links'",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_links (
+            from_id INTEGER NOT NULL,
+            to_title TEXT NOT NULL,
+            to_id INTEGER,
+            PRIMARY KEY (from_id, to_title)
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_note_links_to_id ON note_links(to_id)", [])?;
+    create_link_triggers(conn)?;
+    if !links_exist {
+        crate::links::rebuild_links(conn)?;
+    }
+
+    // Normalized tag index mirroring each note's `tags` list, kept in step by triggers;
+    // `notes.tags` stays the source of truth and `rebuild_note_tags` recovers from drift
+    let note_tags_exist: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'note_tags'",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_tags (
+            note_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (note_id, tag_id)
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_note_tags_tag_id ON note_tags(tag_id)", [])?;
+    create_tag_triggers(conn)?;
+    if !note_tags_exist {
+        rebuild_note_tags(conn)?;
+    }
+
+    // Compose buffers autosaved by the frontend; deliberately outside notes/FTS
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS drafts (
+            key TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            saved_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    // What the draft will be saved as under the capture config, so a restored draft
+    // shows it
+    add_column_if_missing(conn, "drafts", "knowledge_type", "TEXT")?;
+    add_column_if_missing(conn, "drafts", "tags", "TEXT NOT NULL DEFAULT '[]'")?;
+
+    // Files attached to notes; blobs stay out of notes/FTS
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id INTEGER NOT NULL,
+            filename TEXT NOT NULL,
+            mime TEXT NOT NULL,
+            data BLOB NOT NULL,
+            size INTEGER NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_attachments_note_id ON attachments(note_id)", [])?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS attachments_note_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM attachments WHERE note_id = old.id;
+        END",
+        [],
+    )?;
+
+    // Hand-ordered "read later" queue (see read_later.rs); positions stay dense because
+    // every removal, including a deleted note's, shifts the notes behind it forward
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS read_later (
+            note_id INTEGER PRIMARY KEY,
+            position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS read_later_ad AFTER DELETE ON read_later BEGIN
+            UPDATE read_later SET position = position - 1 WHERE position > old.position;
+        END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS read_later_note_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM read_later WHERE note_id = old.id;
+        END",
+        [],
+    )?;
+
+    // One row per rating, with the schedule it produced, for per-card history charts
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS review_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id INTEGER NOT NULL,
+            reviewed_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            rating TEXT NOT NULL,
+            easiness REAL NOT NULL,
+            interval INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_review_log_note_id ON review_log(note_id, reviewed_at)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_review_log_reviewed_at ON review_log(reviewed_at)", [])?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS review_log_note_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM review_log WHERE note_id = old.id;
+        END",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Keep `note_links.to_id` pointing at the note currently holding each target title
+fn create_link_triggers(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS note_links_ai AFTER INSERT ON notes BEGIN
+            UPDATE note_links SET to_id = new.id WHERE to_id IS NULL AND to_title = new.title COLLATE NOCASE;
+        END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS note_links_au AFTER UPDATE OF title ON notes BEGIN
+            UPDATE note_links
+            SET to_id = (SELECT id FROM notes WHERE title = note_links.to_title COLLATE NOCASE ORDER BY id LIMIT 1)
+            WHERE to_id = old.id OR (to_id IS NULL AND to_title = new.title COLLATE NOCASE);
+        END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS note_links_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM note_links WHERE from_id = old.id;
+            UPDATE note_links
+            SET to_id = (SELECT id FROM notes WHERE title = note_links.to_title COLLATE NOCASE ORDER BY id LIMIT 1)
+            WHERE to_id = old.id;
+        END",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// A note's `tags` column as something `json_each` can walk: the list itself, or `[]`
+/// when the value is NULL, not JSON, or not a list
+fn tag_list(tags: &str) -> String {
+    format!("CASE WHEN NOT json_valid({tags}) THEN '[]' WHEN json_type({tags}) = 'array' THEN {tags} ELSE '[]' END")
+}
+
+/// Statements linking `note`'s tags into `tags`/`note_tags`, for use in a trigger body
+/// (`note` is `new`) or over every note (`note` is `notes`)
+fn link_tags_sql(note: &str) -> String {
+    let list = tag_list(&format!("{note}.tags"));
+    format!(
+        "INSERT OR IGNORE INTO tags (name)
+            SELECT t.value FROM {from} json_each({list}) AS t WHERE t.type = 'text';
+         INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+            SELECT {note}.id, tags.id FROM {from} json_each({list}) AS t JOIN tags ON tags.name = t.value
+            WHERE t.type = 'text';",
+        from = if note == "notes" { "notes," } else { "" },
+    )
+}
+
+/// Keep `note_tags` in step with each note's `tags` list
+fn create_tag_triggers(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        &format!("CREATE TRIGGER IF NOT EXISTS note_tags_ai AFTER INSERT ON notes BEGIN {} END", link_tags_sql("new")),
+        [],
+    )?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS note_tags_au AFTER UPDATE OF tags ON notes BEGIN
+                DELETE FROM note_tags WHERE note_id = old.id;
+                {}
+            END",
+            link_tags_sql("new")
+        ),
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS note_tags_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM note_tags WHERE note_id = old.id;
+        END",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Empty `tags`/`note_tags` and refill them from every note's `tags` list, dropping tags
+/// no note uses any more. Returns the number of note-tag links created.
+pub fn rebuild_note_tags(conn: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    conn.execute_batch("DELETE FROM note_tags; DELETE FROM tags;")?;
+    conn.execute_batch(&link_tags_sql("notes"))?;
+    conn.query_row("SELECT COUNT(*) FROM note_tags", [], |row| row.get(0))
+}
+
+/// `ALTER TABLE ... ADD COLUMN` guarded by a `table_info` lookup, since SQLite has no IF NOT EXISTS for columns
+fn add_column_if_missing(conn: &rusqlite::Connection, table: &str, column: &str, definition: &str) -> Result<(), rusqlite::Error> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        rusqlite::params![table, column],
+        |row| row.get(0),
+    )?;
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+/// Fresh in-memory vault with the full schema, for tests
+#[cfg(test)]
+pub fn test_vault() -> rusqlite::Connection {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    init_schema(&conn).unwrap();
+    conn
+}
+
+/// Insert a bare note (no type, no tags) into a test vault, returning its id
+#[cfg(test)]
+pub fn test_note(conn: &rusqlite::Connection, title: &str, content: &str) -> u64 {
+    conn.execute("INSERT INTO notes (title, content) VALUES (?1, ?2)", [title, content]).unwrap();
+    conn.last_insert_rowid() as u64
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vault_tier_flips_at_threshold() {
+        assert_eq!(vault_tier(0, 5_000), VaultTier::Normal);
+        assert_eq!(vault_tier(4_999, 5_000), VaultTier::Normal);
+        assert_eq!(vault_tier(5_000, 5_000), VaultTier::Large);
+        assert_eq!(vault_tier(10, 10), VaultTier::Large);
+    }
+
+    #[test]
+    fn note_count_feeds_the_tier() {
+        let conn = test_vault();
+        test_note(&conn, "one", "");
+        test_note(&conn, "two", "");
+
+        assert_eq!(vault_tier(note_count(&conn).unwrap(), 3), VaultTier::Normal);
+        test_note(&conn, "three", "");
+        assert_eq!(vault_tier(note_count(&conn).unwrap(), 3), VaultTier::Large);
+    }
+
+    /// Tags linked to a note through the normalized tables, alphabetically
+    fn linked_tags(conn: &rusqlite::Connection, id: u64) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = ?1 ORDER BY t.name")
+            .unwrap();
+        let names = stmt.query_map([id], |row| row.get(0)).unwrap();
+        names.collect::<Result<_, _>>().unwrap()
+    }
+
+    fn set_tags(conn: &rusqlite::Connection, id: u64, tags: &str) {
+        conn.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![tags, id]).unwrap();
+    }
+
+    #[test]
+    fn note_tags_follow_each_tag_list() {
+        let conn = test_vault();
+        conn.execute("INSERT INTO notes (title, content, tags) VALUES ('a', '', '[\"rust\",\"db\",\"rust\"]')", [])
+            .unwrap();
+        let id = conn.last_insert_rowid() as u64;
+        assert_eq!(linked_tags(&conn, id), ["db", "rust"]);
+
+        set_tags(&conn, id, r#"["perf"]"#);
+        assert_eq!(linked_tags(&conn, id), ["perf"]);
+
+        // Corrupt or non-list values link nothing instead of failing the write
+        for bad in ["not json", r#""perf""#, r#"{"a": "b"}"#] {
+            set_tags(&conn, id, bad);
+            assert!(linked_tags(&conn, id).is_empty(), "{}", bad);
+        }
+
+        set_tags(&conn, id, r#"["perf", 7]"#);
+        assert_eq!(linked_tags(&conn, id), ["perf"]);
+        conn.execute("DELETE FROM notes WHERE id = ?1", [id]).unwrap();
+        let links: i64 = conn.query_row("SELECT COUNT(*) FROM note_tags", [], |row| row.get(0)).unwrap();
+        assert_eq!(links, 0);
+    }
+
+    #[test]
+    fn migration_indexes_existing_tags_once() {
+        let conn = test_vault();
+        let id = test_note(&conn, "old", "");
+        set_tags(&conn, id, r#"["legacy", "ops"]"#);
+        // A vault from before the tag tables existed
+        conn.execute_batch("DROP TABLE note_tags; DROP TABLE tags;").unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(linked_tags(&conn, id), ["legacy", "ops"]);
+        // Later startups leave the tables alone
+        conn.execute("DELETE FROM note_tags", []).unwrap();
+        migrate(&conn).unwrap();
+        assert!(linked_tags(&conn, id).is_empty());
+        assert_eq!(rebuild_note_tags(&conn).unwrap(), 2);
+    }
+
+    fn times_ms(conn: &rusqlite::Connection, id: u64) -> (i64, i64, i64, i64) {
+        conn.query_row(
+            "SELECT created_at, created_at_ms, updated_at, updated_at_ms FROM notes WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn millisecond_times_follow_the_second_columns() {
+        let conn = test_vault();
+        let now = test_note(&conn, "now", "");
+        let (created, created_ms, updated, updated_ms) = times_ms(&conn, now);
+        assert_eq!((created_ms / 1000, updated_ms / 1000), (created, updated));
+
+        // A second that isn't "now" (an import, say) maps to the start of that second
+        conn.execute("INSERT INTO notes (title, content, created_at, updated_at) VALUES ('old', '', 1000, 2000)", [])
+            .unwrap();
+        let old = conn.last_insert_rowid() as u64;
+        assert_eq!(times_ms(&conn, old), (1000, 1_000_000, 2000, 2_000_000));
+        conn.execute("UPDATE notes SET updated_at = 3000, created_at = 1500 WHERE id = ?1", [old]).unwrap();
+        assert_eq!(times_ms(&conn, old), (1500, 1_500_000, 3000, 3_000_000));
+    }
+
+    #[test]
+    fn migration_backfills_missing_millisecond_times() {
+        let conn = test_vault();
+        let id = test_note(&conn, "legacy", "");
+        conn.execute("UPDATE notes SET created_at = 1000, updated_at = 2000 WHERE id = ?1", [id]).unwrap();
+        conn.execute("UPDATE notes SET created_at_ms = NULL, updated_at_ms = NULL WHERE id = ?1", [id]).unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(times_ms(&conn, id), (1000, 1_000_000, 2000, 2_000_000));
+    }
+
+    #[test]
+    fn notes_written_in_the_same_second_sort_deterministically() {
+        let conn = test_vault();
+        let first = test_note(&conn, "first", "");
+        let second = test_note(&conn, "second", "");
+        // Same second and even the same millisecond: the id decides, newest first
+        conn.execute("UPDATE notes SET updated_at = 5000", []).unwrap();
+        let order = || -> Vec<u64> {
+            crate::notes::recent_notes(&conn, 10, 0, None, false).unwrap().iter().map(|n| n.id).collect()
+        };
+        assert_eq!(order(), vec![second, first]);
+        assert_eq!(order(), order());
+
+        // A later millisecond within that second wins over the id
+        conn.execute("UPDATE notes SET updated_at_ms = 5000001 WHERE id = ?1", [first]).unwrap();
+        assert_eq!(order(), vec![first, second]);
+    }
+
+    /// Note ids the FTS index matches for `query`, ascending
+    fn fts_matches(conn: &rusqlite::Connection, query: &str) -> Vec<u64> {
+        conn.prepare("SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?1 ORDER BY rowid")
+            .unwrap()
+            .query_map([query], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn search_stays_correct_in_both_fts_modes() {
+        for mode in [FtsMode::External, FtsMode::InTable] {
+            let mut conn = test_vault();
+            let kept = test_note(&conn, "Kept", "apples before the switch");
+            assert_eq!(set_fts_mode(&mut conn, mode).unwrap(), mode != FtsMode::External);
+            assert!(!set_fts_mode(&mut conn, mode).unwrap(), "already in {:?}", mode);
+            assert_eq!(fts_mode(&conn).unwrap(), Some(mode));
+            assert_eq!(fts_matches(&conn, "apples"), vec![kept], "{:?} re-indexes existing notes", mode);
+
+            let edited = test_note(&conn, "Edited", "apples and pears");
+            let deleted = test_note(&conn, "Deleted", "apples only");
+            assert_eq!(fts_matches(&conn, "apples"), vec![kept, edited, deleted], "{:?} after insert", mode);
+
+            conn.execute("UPDATE notes SET content = 'just pears' WHERE id = ?1", [edited]).unwrap();
+            conn.execute("DELETE FROM notes WHERE id = ?1", [deleted]).unwrap();
+            assert_eq!(fts_matches(&conn, "apples"), vec![kept], "{:?} after update and delete", mode);
+            assert_eq!(fts_matches(&conn, "pears"), vec![edited], "{:?} after update", mode);
+            assert_eq!(fts_matches(&conn, "title:edited"), vec![edited], "{:?} indexes titles", mode);
+
+            // A write behind the triggers' back is picked up by a rebuild in either mode
+            conn.execute_batch("DROP TRIGGER notes_au").unwrap();
+            conn.execute("UPDATE notes SET content = 'plums' WHERE id = ?1", [kept]).unwrap();
+            rebuild_fts(&conn).unwrap();
+            assert_eq!(fts_matches(&conn, "plums"), vec![kept], "{:?} after rebuild", mode);
+            assert!(fts_matches(&conn, "apples").is_empty(), "{:?} after rebuild", mode);
+        }
+    }
+
+    #[test]
+    fn fts_mode_switches_back_to_external() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Round trip", "quinces");
+        assert!(set_fts_mode(&mut conn, FtsMode::InTable).unwrap());
+        assert!(set_fts_mode(&mut conn, FtsMode::External).unwrap());
+        assert_eq!(fts_mode(&conn).unwrap(), Some(FtsMode::External));
+        assert_eq!(fts_matches(&conn, "quinces"), vec![id]);
+        conn.execute("DELETE FROM notes WHERE id = ?1", [id]).unwrap();
+        assert!(fts_matches(&conn, "quinces").is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Unsaved compose buffers, kept so a crash mid-capture doesn't lose text
+//!
+//! Drafts live in their own table, so they never show up in search or note lists.
+
+use crate::categorize::KnowledgeType;
+use crate::error::QuickNoteError;
+use crate::tags;
+use rusqlite::Connection;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Draft {
+    pub key: String,
+    pub content: String,
+    /// Type and tags the capture will be saved with (see `Config::capture_fields`)
+    pub knowledge_type: Option<String>,
+    pub tags: Vec<String>,
+    pub saved_at: i64,
+}
+
+/// Store the latest content for `key`, with the type and tags it would be captured
+/// with, replacing any earlier draft
+pub fn save_draft(
+    conn: &Connection,
+    key: &str,
+    content: &str,
+    knowledge_type: Option<KnowledgeType>,
+    tags: &[String],
+    max_bytes: usize,
+) -> Result<(), QuickNoteError> {
+    crate::notes::check_note_size(content, max_bytes)?;
+
+    conn.execute(
+        "INSERT INTO drafts (key, content, knowledge_type, tags, saved_at) VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'))
+         ON CONFLICT(key) DO UPDATE SET content = excluded.content, knowledge_type = excluded.knowledge_type,
+             tags = excluded.tags, saved_at = excluded.saved_at",
+        rusqlite::params![key, content, knowledge_type.map(|k| k.as_db_str()), serde_json::to_string(tags)?],
+    )?;
+    Ok(())
+}
+
+pub fn load_draft(conn: &Connection, key: &str) -> Result<Option<Draft>, QuickNoteError> {
+    let mut stmt = conn.prepare("SELECT key, content, knowledge_type, tags, saved_at FROM drafts WHERE key = ?1")?;
+    let mut rows = stmt.query([key])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(Draft {
+            key: row.get(0)?,
+            content: row.get(1)?,
+            knowledge_type: row.get(2)?,
+            tags: tags::stored_tags(row.get(3)?),
+            saved_at: row.get(4)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Drop a draft; clearing a key that has none is not an error
+pub fn clear_draft(conn: &Connection, key: &str) -> Result<(), QuickNoteError> {
+    conn.execute("DELETE FROM drafts WHERE key = ?1", [key])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_vault;
+    use crate::search::{search_notes, Weights};
+
+    #[test]
+    fn draft_round_trips_and_replaces() {
+        let conn = test_vault();
+        save_draft(&conn, "composer", "first", None, &[], 1024).unwrap();
+        save_draft(&conn, "composer", "second", None, &[], 1024).unwrap();
+
+        let draft = load_draft(&conn, "composer").unwrap().unwrap();
+        assert_eq!((draft.key.as_str(), draft.content.as_str()), ("composer", "second"));
+
+        clear_draft(&conn, "composer").unwrap();
+        assert!(load_draft(&conn, "composer").unwrap().is_none());
+        clear_draft(&conn, "composer").unwrap();
+    }
+
+    #[test]
+    fn drafts_stay_out_of_search_and_notes() {
+        let conn = test_vault();
+        save_draft(&conn, "composer", "half-written zeppelin idea", None, &[], 1024).unwrap();
+
+        let results = search_notes(&conn, "zeppelin", None, 100, 2, true, Weights { title: 1.0, content: 1.0 }, false).unwrap();
+        assert!(results.notes.is_empty());
+        assert_eq!(crate::db::note_count(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn oversized_draft_is_refused() {
+        let conn = test_vault();
+        assert!(matches!(save_draft(&conn, "composer", "12345", None, &[], 4), Err(QuickNoteError::NoteTooLarge { .. })));
+        assert!(load_draft(&conn, "composer").unwrap().is_none());
+    }
+
+    #[test]
+    fn draft_keeps_its_capture_type_and_tags() {
+        let conn = test_vault();
+        let tags = vec!["inbox".to_string()];
+        save_draft(&conn, "capture", "idea", Some(KnowledgeType::Note), &tags, 1024).unwrap();
+
+        let draft = load_draft(&conn, "capture").unwrap().unwrap();
+        assert_eq!((draft.knowledge_type.as_deref(), draft.tags), (Some("Note"), tags));
+
+        // The next save replaces them along with the content
+        save_draft(&conn, "capture", "idea, refined", None, &[], 1024).unwrap();
+        let draft = load_draft(&conn, "capture").unwrap().unwrap();
+        assert_eq!((draft.knowledge_type, draft.tags), (None, Vec::<String>::new()));
+    }
+}
@@ -0,0 +1,234 @@
+//! Offline semantic search, built with the `semantic` feature
+//!
+//! Notes are embedded with feature hashing: words and character trigrams are hashed into a
+//! fixed-size vector, so no model has to be downloaded or bundled. That finds notes using
+//! different forms of the same words (`index`, `indexes`, `indexing`) which FTS tokens
+//! miss, though not true synonyms; `embed` is the one place to swap in a real model.
+//!
+//! Embeddings are refreshed lazily: `sync_embeddings` re-embeds notes whose title or
+//! content changed since they were last embedded, so write paths don't need to know
+//! about this module.
+
+use rusqlite::{Connection, OptionalExtension};
+
+/// Length of every embedding vector
+pub const DIMENSIONS: usize = 1024;
+
+/// Words too common to say anything about a note
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "can", "do", "does", "for", "from", "how", "i", "in", "is", "it",
+    "my", "of", "on", "or", "that", "the", "this", "to", "what", "when", "why", "with",
+];
+
+/// Words this long or longer also contribute their first `STEM_CHARS` characters, a crude
+/// stem that maps `indexes`, `indexed` and `indexing` onto `index`
+const STEM_CHARS: usize = 5;
+
+/// Create the `embeddings` table; rows go away with their note
+pub fn init(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS embeddings (
+            note_id INTEGER PRIMARY KEY,
+            fingerprint INTEGER NOT NULL,
+            vector BLOB NOT NULL
+        );
+        CREATE TRIGGER IF NOT EXISTS embeddings_note_ad AFTER DELETE ON notes BEGIN
+            DELETE FROM embeddings WHERE note_id = old.id;
+        END;",
+    )
+}
+
+/// L2-normalized hashed bag of words and stems (weight 1) and character trigrams (0.25)
+pub fn embed(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; DIMENSIONS];
+    let lower = text.to_lowercase();
+
+    for word in lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty() && !STOPWORDS.contains(w)) {
+        let chars: Vec<char> = word.chars().collect();
+        add_feature(&mut vector, word.as_bytes(), 1.0);
+        if chars.len() >= STEM_CHARS {
+            add_feature(&mut vector, format!("{}~", chars[..STEM_CHARS].iter().collect::<String>()).as_bytes(), 1.0);
+        }
+
+        let padded: Vec<char> = [' '].into_iter().chain(chars).chain([' ']).collect();
+        for trigram in padded.windows(3) {
+            add_feature(&mut vector, trigram.iter().collect::<String>().as_bytes(), 0.25);
+        }
+    }
+
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+/// Signed feature hashing: the top bit picks the sign so collisions tend to cancel out
+fn add_feature(vector: &mut [f32], feature: &[u8], weight: f32) {
+    let hash = fnv1a(feature);
+    let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+    vector[(hash % DIMENSIONS as u64) as usize] += sign * weight;
+}
+
+/// FNV-1a, used because it's stable across Rust versions (unlike `DefaultHasher`), which
+/// matters for the fingerprints stored in the vault
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn fingerprint(title: &str, content: &str) -> i64 {
+    fnv1a(format!("{}\n{}", title, content).as_bytes()) as i64
+}
+
+/// Embed notes that are new or changed since their last embedding. Encrypted notes are
+/// skipped (their content is ciphertext) and lose any embedding they had.
+/// Returns the number of notes embedded.
+pub fn sync_embeddings(conn: &mut Connection) -> Result<usize, rusqlite::Error> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM embeddings WHERE note_id IN (SELECT id FROM notes WHERE encrypted)",
+        [],
+    )?;
+
+    let mut embedded = 0;
+    {
+        let mut stored = tx.prepare("SELECT fingerprint FROM embeddings WHERE note_id = ?1")?;
+        let mut upsert = tx.prepare(
+            "INSERT INTO embeddings (note_id, fingerprint, vector) VALUES (?1, ?2, ?3)
+             ON CONFLICT(note_id) DO UPDATE SET fingerprint = excluded.fingerprint, vector = excluded.vector",
+        )?;
+        let mut notes = tx.prepare("SELECT id, title, content FROM notes WHERE NOT encrypted")?;
+        let mut rows = notes.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let (id, title, content): (u64, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+            let fingerprint = fingerprint(&title, &content);
+            if stored.query_row([id], |r| r.get::<_, i64>(0)).optional()? == Some(fingerprint) {
+                continue;
+            }
+
+            let bytes: Vec<u8> = embed(&format!("{}\n{}", title, content)).iter().flat_map(|v| v.to_le_bytes()).collect();
+            upsert.execute(rusqlite::params![id, fingerprint, bytes])?;
+            embedded += 1;
+        }
+    }
+
+    tx.commit()?;
+    Ok(embedded)
+}
+
+/// The `k` active notes closest to `query` by cosine similarity, best first, each with
+/// its `score` (-1 to 1). Notes scoring zero or less are unrelated and left out.
+pub fn semantic_search(conn: &mut Connection, query: &str, k: usize) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    sync_embeddings(conn)?;
+    let query = embed(query);
+
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.knowledge_type, e.vector FROM embeddings e
+         JOIN notes n ON n.id = e.note_id
+         WHERE n.is_archived = 0",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut scored: Vec<(f32, u64, String, Option<String>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let bytes: Vec<u8> = row.get(3)?;
+        let score: f32 = bytes
+            .chunks_exact(4)
+            .zip(&query)
+            .map(|(chunk, q)| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) * q)
+            .sum();
+        if score > 0.0 {
+            scored.push((score, row.get(0)?, row.get(1)?, row.get(2)?));
+        }
+    }
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    Ok(scored
+        .into_iter()
+        .take(k)
+        .map(|(score, id, title, knowledge_type)| {
+            serde_json::json!({
+                "id": id,
+                "title": title,
+                "knowledge_type": knowledge_type,
+                "score": score,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    fn vault() -> Connection {
+        let conn = test_vault();
+        init(&conn).unwrap();
+        conn
+    }
+
+    fn ids(results: &[serde_json::Value]) -> Vec<u64> {
+        results.iter().map(|r| r["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn related_wording_ranks_above_unrelated_notes() {
+        let mut conn = vault();
+        let indexing = test_note(&conn, "Speeding up lookups", "Indexing the customers table made the slow report fast");
+        test_note(&conn, "Grocery list", "milk, eggs, bread and coffee");
+        test_note(&conn, "Standup", "talked about the release calendar");
+
+        // "indexes" never appears in the note, so FTS tokens wouldn't match it
+        let results = semantic_search(&mut conn, "database indexes for customer reports", 3).unwrap();
+        assert_eq!(ids(&results).first(), Some(&indexing));
+        let score = results[0]["score"].as_f64().unwrap();
+        assert!(score > 0.0 && score <= 1.0, "{}", score);
+    }
+
+    #[test]
+    fn embeddings_are_normalized_and_stable() {
+        let vector = embed("Rust borrow checker lifetimes");
+        assert_eq!(vector.len(), DIMENSIONS);
+        let norm: f32 = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert_eq!(vector, embed("rust BORROW checker lifetimes"));
+        // Stopwords alone carry nothing
+        assert!(embed("the and of").iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn sync_embeds_only_new_or_changed_notes() {
+        let mut conn = vault();
+        let id = test_note(&conn, "first", "alpha");
+        test_note(&conn, "second", "beta");
+        assert_eq!(sync_embeddings(&mut conn).unwrap(), 2);
+        assert_eq!(sync_embeddings(&mut conn).unwrap(), 0);
+
+        conn.execute("UPDATE notes SET content = 'gamma' WHERE id = ?1", [id]).unwrap();
+        assert_eq!(sync_embeddings(&mut conn).unwrap(), 1);
+
+        // Deleting the note takes its embedding with it
+        conn.execute("DELETE FROM notes WHERE id = ?1", [id]).unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn encrypted_and_archived_notes_are_not_returned() {
+        let mut conn = vault();
+        let secret = test_note(&conn, "vault keys", "rotate the vault keys monthly");
+        let archived = test_note(&conn, "old keys", "rotate keys yearly");
+        let active = test_note(&conn, "key rotation", "rotate api keys weekly");
+        sync_embeddings(&mut conn).unwrap();
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [secret]).unwrap();
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        let results = semantic_search(&mut conn, "rotate keys", 10).unwrap();
+        assert_eq!(ids(&results), vec![active]);
+        let embedded: i64 =
+            conn.query_row("SELECT COUNT(*) FROM embeddings WHERE note_id = ?1", [secret], |row| row.get(0)).unwrap();
+        assert_eq!(embedded, 0);
+    }
+}
@@ -0,0 +1,80 @@
+//! Error type for vault operations
+
+// Some variants are only raised by GUI-side operations
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+#[derive(Debug)]
+pub enum QuickNoteError {
+    Database(rusqlite::Error),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+    NotFound(u64),
+    InvalidInput(String),
+    /// Note content over the configured `max_note_bytes` (sizes in UTF-8 bytes)
+    NoteTooLarge { size: usize, limit: usize },
+    /// Attachment over the configured `max_attachment_bytes`
+    AttachmentTooLarge { size: usize, limit: usize },
+    /// Note is locked against edits (see `notes::toggle_lock`)
+    Locked(u64),
+    /// Title already used by this note, with `unique_titles` on
+    DuplicateTitle(u64),
+    /// A bulk rewrite left the vault inconsistent and was rolled back
+    IntegrityCheck(String),
+    /// Priority outside `0..=notes::MAX_PRIORITY`
+    InvalidPriority(i64),
+    /// Stored `knowledge_type` that isn't a known type (only raised in strict mode)
+    UnknownKnowledgeType { id: u64, value: String },
+}
+
+impl std::fmt::Display for QuickNoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Database(e) => write!(f, "Database error: {}", e),
+            Self::Json(e) => write!(f, "JSON error: {}", e),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::NotFound(id) => write!(f, "Note {} not found", id),
+            Self::InvalidInput(msg) => write!(f, "{}", msg),
+            Self::NoteTooLarge { size, limit } => {
+                write!(f, "Note is too large: {} bytes (limit is {} bytes)", size, limit)
+            }
+            Self::AttachmentTooLarge { size, limit } => {
+                write!(f, "Attachment is too large: {} bytes (limit is {} bytes)", size, limit)
+            }
+            Self::Locked(id) => write!(f, "Note {} is locked; unlock it to edit", id),
+            Self::DuplicateTitle(id) => write!(f, "Note {} already has this title", id),
+            Self::IntegrityCheck(msg) => write!(f, "Integrity check failed, nothing was changed: {}", msg),
+            Self::InvalidPriority(level) => {
+                write!(f, "Priority must be between 0 and {}, got {}", crate::notes::MAX_PRIORITY, level)
+            }
+            Self::UnknownKnowledgeType { id, value } => write!(f, "Note {} has unknown knowledge type '{}'", id, value),
+        }
+    }
+}
+
+impl std::error::Error for QuickNoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QuickNoteError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for QuickNoteError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<std::io::Error> for QuickNoteError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
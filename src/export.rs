@@ -0,0 +1,1084 @@
+//! Vault exporters
+
+use crate::{db, notes, review, tags};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Seek, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipWriter};
+
+/// Entry names inside a vault backup ZIP (see `export_vault`)
+pub const VAULT_MANIFEST: &str = "manifest.json";
+pub const VAULT_NOTES: &str = "notes.jsonl";
+
+/// One exported note, including its review state so a backup is complete
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedNote {
+    /// Id in the exporting vault; imports always allocate a new one
+    pub id: u64,
+    pub title: String,
+    pub content: String,
+    pub knowledge_type: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default = "empty_object")]
+    pub metadata: serde_json::Value,
+    pub review_due: Option<i64>,
+    pub review_interval: i64,
+    pub review_streak: i64,
+    pub review_easiness: f64,
+    #[serde(default)]
+    pub review_lapses: i64,
+    #[serde(default)]
+    pub review_suspended: bool,
+    /// `content` is then ciphertext, restored as-is
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub is_archived: bool,
+    #[serde(default)]
+    pub is_locked: bool,
+}
+
+fn empty_object() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+/// Columns read by `read_exported`, in order
+const EXPORTED_COLUMNS: &str = "id, title, content, knowledge_type, tags, created_at, updated_at, source, metadata, \
+     review_due, review_interval, review_streak, review_easiness, review_lapses, review_suspended, encrypted, is_archived, is_locked";
+
+fn read_exported(row: &rusqlite::Row) -> Result<ExportedNote, Box<dyn std::error::Error>> {
+    Ok(ExportedNote {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        content: row.get(2)?,
+        knowledge_type: row.get(3)?,
+        tags: tags::stored_tags(row.get(4)?),
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        source: row.get(7)?,
+        metadata: serde_json::from_str(&row.get::<_, String>(8)?)?,
+        review_due: row.get(9)?,
+        review_interval: row.get(10)?,
+        review_streak: row.get(11)?,
+        review_easiness: row.get(12)?,
+        review_lapses: row.get(13)?,
+        review_suspended: row.get(14)?,
+        encrypted: row.get(15)?,
+        is_archived: row.get(16)?,
+        is_locked: row.get(17)?,
+    })
+}
+
+/// Notes written between calls to an export's `on_progress`
+pub const PROGRESS_EVERY: usize = 250;
+
+/// Stream the vault as JSON Lines, one note per line, without materializing the full set.
+/// `on_progress(done, total)` is called every `PROGRESS_EVERY` notes and once all are
+/// written. Returns the number of notes written.
+pub fn export_jsonl<W: Write>(
+    conn: &Connection,
+    mut writer: W,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let total: usize = conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM notes ORDER BY id", EXPORTED_COLUMNS))?;
+    let mut rows = stmt.query([])?;
+
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+        serde_json::to_writer(&mut writer, &read_exported(row)?)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+        if written % PROGRESS_EVERY == 0 && written < total {
+            on_progress(written, total);
+        }
+    }
+    on_progress(written, total);
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// A note without its review schedule, for syncing content between devices
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentNote {
+    pub id: u64,
+    pub title: String,
+    pub content: String,
+    pub knowledge_type: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default = "empty_object")]
+    pub metadata: serde_json::Value,
+}
+
+/// One card's review schedule, keyed by note id in `export_review_state`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewState {
+    pub review_due: Option<i64>,
+    pub review_interval: i64,
+    pub review_streak: i64,
+    pub review_easiness: f64,
+    #[serde(default)]
+    pub review_suspended: bool,
+    #[serde(default)]
+    pub review_lapses: i64,
+}
+
+/// Notes as JSON Lines without any review columns. Together with `export_review_state`
+/// this splits a vault into content that can be synced (e.g. committed to git) and
+/// per-device review schedules that stay local; `import::import_content` and
+/// `import::import_review_state` put them back together. Returns the number of notes.
+pub fn export_content_only<W: Write>(conn: &Connection, mut writer: W) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, content, knowledge_type, tags, created_at, updated_at, source, metadata
+         FROM notes ORDER BY id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+        let note = ContentNote {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            knowledge_type: row.get(3)?,
+            tags: tags::stored_tags(row.get(4)?),
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+            source: row.get(7)?,
+            metadata: serde_json::from_str(&row.get::<_, String>(8)?)?,
+        };
+
+        serde_json::to_writer(&mut writer, &note)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Review schedules of enrolled notes as a JSON object keyed by note id.
+/// Returns the number of cards written.
+pub fn export_review_state<W: Write>(conn: &Connection, mut writer: W) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, review_due, review_interval, review_streak, review_easiness, review_suspended, review_lapses
+         FROM notes WHERE review_due IS NOT NULL ORDER BY id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut states = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let state = ReviewState {
+            review_due: row.get(1)?,
+            review_interval: row.get(2)?,
+            review_streak: row.get(3)?,
+            review_easiness: row.get(4)?,
+            review_suspended: row.get(5)?,
+            review_lapses: row.get(6)?,
+        };
+        states.insert(row.get::<_, u64>(0)?.to_string(), state);
+    }
+
+    serde_json::to_writer_pretty(&mut writer, &states)?;
+    writer.flush()?;
+    Ok(states.len())
+}
+
+/// Describes a vault backup. Stored unencrypted so a reader can tell whether to ask for
+/// the passphrase before touching the notes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultManifest {
+    pub version: u32,
+    pub encrypted: bool,
+    pub note_count: usize,
+    pub exported_at: i64,
+    /// A share bundle (see `export_share_bundle`) rather than a backup
+    #[serde(default)]
+    pub share: bool,
+}
+
+/// Write the whole vault as a ZIP backup: `notes.jsonl` (as from `export_jsonl`) plus
+/// `manifest.json`. With a `passphrase` the notes entry is AES-256 encrypted, whether or
+/// not the vault itself is. `on_progress` is as for `export_jsonl`. Returns the number
+/// of notes written.
+pub fn export_vault<W: Write + Seek>(
+    conn: &Connection,
+    writer: W,
+    passphrase: Option<&str>,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    write_bundle(conn, writer, passphrase, false, |zip| export_jsonl(conn, zip, on_progress))
+}
+
+/// Write the notes `ids` as a share bundle for someone else's vault: a backup ZIP, as
+/// from `export_vault`, whose manifest marks it as a share so `import::import_vault`
+/// files the notes away archived and read-only. Review schedules are left behind.
+/// Encrypted notes can't be shared. Returns the number of notes written.
+pub fn export_share_bundle<W: Write + Seek>(
+    conn: &Connection,
+    writer: W,
+    ids: &[u64],
+    passphrase: Option<&str>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM notes WHERE id = ?1", EXPORTED_COLUMNS))?;
+    let mut shared = Vec::new();
+    for id in ids {
+        if shared.iter().any(|note: &ExportedNote| note.id == *id) {
+            continue;
+        }
+        let mut rows = stmt.query([id])?;
+        let note = read_exported(rows.next()?.ok_or_else(|| format!("Note {} not found", id))?)?;
+        if note.encrypted {
+            return Err(format!("Note {} is encrypted and can't be shared", id).into());
+        }
+        shared.push(ExportedNote {
+            review_due: None,
+            review_interval: 0,
+            review_streak: 0,
+            review_easiness: review::STARTING_EASINESS,
+            review_lapses: 0,
+            review_suspended: false,
+            ..note
+        });
+    }
+
+    write_bundle(conn, writer, passphrase, true, |zip| {
+        for note in &shared {
+            serde_json::to_writer(&mut *zip, note)?;
+            zip.write_all(b"\n")?;
+        }
+        Ok(shared.len())
+    })
+}
+
+/// ZIP with `notes.jsonl`, written by `write_notes` (AES-256 encrypted with a
+/// `passphrase`), and an unencrypted `manifest.json`
+fn write_bundle<W: Write + Seek>(
+    conn: &Connection,
+    writer: W,
+    passphrase: Option<&str>,
+    share: bool,
+    write_notes: impl FnOnce(&mut ZipWriter<W>) -> Result<usize, Box<dyn std::error::Error>>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let passphrase = passphrase.filter(|p| !p.is_empty());
+    let mut zip = ZipWriter::new(writer);
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let notes_options = match passphrase {
+        Some(passphrase) => options.with_aes_encryption(AesMode::Aes256, passphrase),
+        None => options,
+    };
+    zip.start_file(VAULT_NOTES, notes_options)?;
+    let note_count = write_notes(&mut zip)?;
+
+    let manifest = VaultManifest {
+        version: 1,
+        encrypted: passphrase.is_some(),
+        note_count,
+        exported_at: conn.query_row("SELECT CAST(strftime('%s', 'now') AS INTEGER)", [], |row| row.get(0))?,
+        share,
+    };
+    zip.start_file(VAULT_MANIFEST, options)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+
+    zip.finish()?;
+    Ok(note_count)
+}
+
+/// `export_vault` into a new file at `path`. With `plaintext_sidecar`, a readable copy
+/// from `export_plaintext_sidecar` goes next to it (`backup.zip` gets `backup.plain/`),
+/// except for encrypted backups, where it would defeat the encryption.
+pub fn export_vault_file(
+    conn: &Connection,
+    path: &Path,
+    passphrase: Option<&str>,
+    plaintext_sidecar: bool,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(path)?;
+    let count = export_vault(conn, std::io::BufWriter::new(file), passphrase, on_progress)?;
+
+    if plaintext_sidecar {
+        if passphrase.is_some_and(|p| !p.is_empty()) {
+            tracing::warn!("Skipping plaintext sidecar for encrypted backup {:?}", path);
+        } else {
+            export_plaintext_sidecar(conn, &path.with_extension("plain"))?;
+        }
+    }
+    Ok(count)
+}
+
+/// Human-readable copy of the vault for disaster recovery, written into `dir`:
+/// `notes.jsonl` plus one Markdown file per note under `markdown/`.
+/// Returns the number of notes written.
+pub fn export_plaintext_sidecar(conn: &Connection, dir: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir.join("markdown"))?;
+
+    let jsonl = std::fs::File::create(dir.join(VAULT_NOTES))?;
+    let count = export_jsonl(conn, std::io::BufWriter::new(jsonl), |_, _| {})?;
+
+    let mut stmt = conn.prepare("SELECT id, title FROM notes ORDER BY id")?;
+    let notes = stmt
+        .query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (id, title) in notes {
+        let markdown = export_markdown_combined(conn, &[id])?;
+        std::fs::write(dir.join("markdown").join(format!("{}-{}.md", id, slug(&title))), markdown)?;
+    }
+
+    Ok(count)
+}
+
+/// Filesystem-safe, lowercase form of a title (at most 50 characters)
+fn slug(title: &str) -> String {
+    let slug = title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let slug: String = slug.chars().take(50).collect();
+    match slug.trim_end_matches('-') {
+        "" => "note".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// Write the review cards among `ids` (enrolled in review and not encrypted, since the
+/// recipient has no key) to a new vault database at `path`, with their review state,
+/// history and attachments, so a study deck can be shared and opened as a vault. `path`
+/// must not exist yet; a failed export removes the partial file. Returns the number of
+/// cards written.
+pub fn export_deck(conn: &Connection, ids: &[u64], path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()).into());
+    }
+    let mut cards = Vec::new();
+    for &id in ids {
+        let is_card: Option<bool> = conn
+            .query_row("SELECT review_due IS NOT NULL AND encrypted = 0 FROM notes WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        if is_card == Some(true) {
+            cards.push(id);
+        }
+    }
+    cards.sort_unstable();
+    cards.dedup();
+
+    let written = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut deck = Connection::open(path)?;
+        db::init_schema(&deck)?;
+        notes::copy_notes(conn, &mut deck, &cards)?;
+        db::rebuild_fts(&deck)?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(path);
+        return Err(e);
+    }
+    Ok(cards.len())
+}
+
+/// The notes `ids`, in that order, as one Markdown document: an `##` section per note
+/// with its tags on a closing line, sections separated by `---`. Encrypted notes keep
+/// their heading but not their content.
+pub fn export_markdown_combined(conn: &Connection, ids: &[u64]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT title, content, tags, encrypted FROM notes WHERE id = ?1")?;
+
+    let mut sections = Vec::new();
+    for id in ids {
+        let row: Option<(String, String, Option<String>, bool)> = stmt
+            .query_row([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .optional()?;
+        let Some((title, content, tags, encrypted)) = row else {
+            return Err(format!("Note {} not found", id).into());
+        };
+        let tags = tags::stored_tags(tags);
+
+        let mut section = format!("## {}\n\n", title.trim());
+        if encrypted {
+            section.push_str("*Encrypted note; content not exported.*\n");
+        } else {
+            section.push_str(content.trim_end());
+            section.push('\n');
+        }
+        if !tags.is_empty() {
+            let tag_line: Vec<String> = tags.iter().map(|t| format!("#{}", t)).collect();
+            section.push_str(&format!("\nTags: {}\n", tag_line.join(" ")));
+        }
+        sections.push(section);
+    }
+
+    Ok(sections.join("\n---\n\n"))
+}
+
+/// Output format for `export_notes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A JSON array of notes, shaped like `export_jsonl` lines
+    Json,
+    /// One document, see `export_markdown_combined`
+    Markdown,
+    /// `id,title,knowledge_type,tags,created_at,updated_at,source,content` with a header
+    Csv,
+}
+
+/// The notes `ids`, in that order, in `format`; e.g. the results of a search
+pub fn export_notes(conn: &Connection, ids: &[u64], format: ExportFormat) -> Result<String, Box<dyn std::error::Error>> {
+    match format {
+        ExportFormat::Markdown => export_markdown_combined(conn, ids),
+        ExportFormat::Json => {
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM notes WHERE id = ?1", EXPORTED_COLUMNS))?;
+            let mut notes = Vec::new();
+            for id in ids {
+                let mut rows = stmt.query([id])?;
+                let row = rows.next()?.ok_or_else(|| format!("Note {} not found", id))?;
+                notes.push(read_exported(row)?);
+            }
+            Ok(serde_json::to_string_pretty(&notes)?)
+        }
+        ExportFormat::Csv => export_csv(conn, ids),
+    }
+}
+
+/// CSV (RFC 4180, CRLF line endings) with tags space-separated. Encrypted notes are
+/// exported with empty content.
+fn export_csv(conn: &Connection, ids: &[u64]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, COALESCE(knowledge_type, 'Concept'), tags, created_at, updated_at,
+                COALESCE(source, ''), CASE WHEN encrypted THEN '' ELSE content END
+         FROM notes WHERE id = ?1",
+    )?;
+
+    // title, knowledge_type, tags, created_at, updated_at, source, content
+    type CsvRow = (String, String, Option<String>, i64, i64, String, String);
+
+    let mut csv = String::from("id,title,knowledge_type,tags,created_at,updated_at,source,content\r\n");
+    for id in ids {
+        let row: Option<CsvRow> = stmt
+            .query_row([id], |row| {
+                Ok((row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+            })
+            .optional()?;
+        let Some((title, knowledge_type, tags, created_at, updated_at, source, content)) = row else {
+            return Err(format!("Note {} not found", id).into());
+        };
+        let tags = tags::stored_tags(tags);
+
+        let fields = [
+            id.to_string(),
+            csv_field(&title),
+            knowledge_type,
+            csv_field(&tags.join(" ")),
+            created_at.to_string(),
+            updated_at.to_string(),
+            csv_field(&source),
+            csv_field(&content),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    Ok(csv)
+}
+
+/// Quote a field if it holds a comma, quote or line break, doubling inner quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Upcoming reviews as an iCalendar file: one all-day VEVENT per future, active card
+pub fn export_review_ics(conn: &Connection) -> Result<String, Box<dyn std::error::Error>> {
+    let dtstamp: String = conn.query_row("SELECT strftime('%Y%m%dT%H%M%SZ', 'now')", [], |row| row.get(0))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, title, COALESCE(knowledge_type, 'Concept'), review_due,
+                strftime('%Y%m%d', review_due, 'unixepoch'),
+                strftime('%Y%m%d', review_due, 'unixepoch', '+1 day')
+         FROM notes
+         WHERE review_due > strftime('%s', 'now') AND review_suspended = 0 AND is_archived = 0
+         ORDER BY review_due, id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//QuickNote//Review Schedule//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    while let Some(row) = rows.next()? {
+        let id: u64 = row.get(0)?;
+        let title: String = row.get(1)?;
+        let knowledge_type: String = row.get(2)?;
+        let due: i64 = row.get(3)?;
+        let start: String = row.get(4)?;
+        let end: String = row.get(5)?;
+
+        lines.push("BEGIN:VEVENT".to_string());
+        // Stable per card and due date, so re-importing an updated export replaces events
+        lines.push(format!("UID:review-{}-{}@quicknote", id, due));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", start));
+        lines.push(format!("DTEND;VALUE=DATE:{}", end));
+        lines.push(format!("SUMMARY:Review: {}", ics_escape(&title)));
+        lines.push(format!("CATEGORIES:{}", ics_escape(&knowledge_type)));
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        ics.push_str(&ics_fold(&line));
+        ics.push_str("\r\n");
+    }
+    Ok(ics)
+}
+
+/// Escape TEXT values per RFC 5545
+fn ics_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Fold content lines longer than 75 octets without splitting a UTF-8 character
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+const SITE_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:52rem;margin:2rem auto;padding:0 1rem;color:#222}\
+pre{white-space:pre-wrap;background:#f6f6f6;padding:1rem;border-radius:4px}\
+.meta{color:#666;font-size:.9rem}.tag{margin-right:.5rem}li.hidden{display:none}";
+
+/// Script for index.html: filters the note list by search text and tag using SEARCH_INDEX
+const SITE_SCRIPT: &str = r#"const search = document.getElementById('search');
+const tag = document.getElementById('tag');
+function applyFilter() {
+  const q = search.value.trim().toLowerCase();
+  const t = tag.value;
+  for (const entry of SEARCH_INDEX) {
+    const visible = (!q || entry.text.includes(q)) && (!t || entry.tags.includes(t));
+    document.getElementById('note-' + entry.id).classList.toggle('hidden', !visible);
+  }
+}
+search.addEventListener('input', applyFilter);
+tag.addEventListener('change', applyFilter);
+"#;
+
+/// Write a read-only static site into `dir`: `index.html` (notes grouped by type, with
+/// search and a tag filter), one page per note under `notes/`, and the search index.
+/// Encrypted and archived notes are left out. Returns the number of HTML pages written.
+pub fn export_html_site(conn: &Connection, dir: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir.join("notes"))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, title, content, COALESCE(knowledge_type, 'Concept'), tags,
+                strftime('%Y-%m-%d', updated_at, 'unixepoch')
+         FROM notes WHERE encrypted = 0 AND is_archived = 0
+         ORDER BY title COLLATE NOCASE, id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut pages = 0;
+    let mut by_type: BTreeMap<String, Vec<(u64, String)>> = BTreeMap::new();
+    let mut all_tags = BTreeSet::new();
+    let mut index = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: u64 = row.get(0)?;
+        let title: String = row.get(1)?;
+        let content: String = row.get(2)?;
+        let knowledge_type: String = row.get(3)?;
+        let tags = tags::stored_tags(row.get(4)?);
+        let updated: String = row.get(5)?;
+
+        let tag_list: String = tags.iter().map(|t| format!("<span class=\"tag\">#{}</span>", html_escape(t))).collect();
+        let page = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title><style>{style}</style></head>\n\
+             <body><p><a href=\"../index.html\">&larr; All notes</a></p>\n<h1>{title}</h1>\n\
+             <p class=\"meta\">{kind} &middot; updated {updated}</p>\n<p>{tags}</p>\n<pre>{content}</pre>\n</body></html>\n",
+            title = html_escape(&title),
+            style = SITE_STYLE,
+            kind = html_escape(&knowledge_type),
+            updated = updated,
+            tags = tag_list,
+            content = html_escape(&content),
+        );
+        std::fs::write(dir.join("notes").join(format!("{}.html", id)), page)?;
+        pages += 1;
+
+        index.push(serde_json::json!({
+            "id": id,
+            "tags": tags,
+            "text": format!("{}\n{}", title, content).to_lowercase(),
+        }));
+        all_tags.extend(tags);
+        by_type.entry(knowledge_type).or_default().push((id, title));
+    }
+
+    let mut body = String::new();
+    for (knowledge_type, notes) in &by_type {
+        body.push_str(&format!("<h2>{}</h2>\n<ul>\n", html_escape(knowledge_type)));
+        for (id, title) in notes {
+            body.push_str(&format!(
+                "<li id=\"note-{id}\"><a href=\"notes/{id}.html\">{}</a></li>\n",
+                html_escape(title),
+                id = id
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+    let tag_options: String = all_tags
+        .iter()
+        .map(|t| format!("<option value=\"{0}\">#{0}</option>", html_escape(t)))
+        .collect();
+    let index_page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>QuickNote</title><style>{style}</style></head>\n\
+         <body><h1>QuickNote</h1>\n<p><input id=\"search\" type=\"search\" placeholder=\"Search notes\">\n\
+         <select id=\"tag\"><option value=\"\">All tags</option>{options}</select></p>\n{body}\
+         <script src=\"search-index.js\"></script>\n<script>{script}</script>\n</body></html>\n",
+        style = SITE_STYLE,
+        options = tag_options,
+        body = body,
+        script = SITE_SCRIPT,
+    );
+    std::fs::write(dir.join("index.html"), index_page)?;
+    pages += 1;
+
+    // A script rather than a .json file so the site also works from file:// without fetch()
+    std::fs::write(
+        dir.join("search-index.js"),
+        format!("const SEARCH_INDEX = {};\n", serde_json::to_string(&index)?),
+    )?;
+
+    Ok(pages)
+}
+
+/// Escape text for HTML element content and quoted attribute values
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categorize::KnowledgeType;
+    use crate::config::TagCase;
+    use crate::db::{test_note, test_vault};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Counts lines as they arrive and keeps nothing else
+    struct LineCounter(Rc<Cell<usize>>);
+
+    impl Write for LineCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.set(self.0.get() + buf.iter().filter(|b| **b == b'\n').count());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn jsonl_writes_one_note_per_line() {
+        let conn = test_vault();
+        test_note(&conn, "First", "line one\nline two");
+        test_note(&conn, "Second", "");
+
+        let mut out = Vec::new();
+        assert_eq!(export_jsonl(&conn, &mut out, |_, _| {}).unwrap(), 2);
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["title"], "First");
+        assert_eq!(lines[0]["content"], "line one\nline two");
+        assert_eq!(lines[1]["title"], "Second");
+    }
+
+    #[test]
+    fn jsonl_streams_large_vault_row_by_row() {
+        let mut conn = test_vault();
+        let tx = conn.transaction().unwrap();
+        tx.execute(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 50000)
+             INSERT INTO notes (title, content) SELECT 'Note ' || i, 'body ' || i FROM n",
+            [],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        // Each progress report must find exactly that many lines already handed to the
+        // writer, i.e. nothing is buffered up before writing
+        let lines = Rc::new(Cell::new(0));
+        let mut reports = 0;
+        let written = export_jsonl(&conn, LineCounter(lines.clone()), |done, total| {
+            assert_eq!(total, 50_000);
+            assert_eq!(lines.get(), done);
+            reports += 1;
+        })
+        .unwrap();
+
+        assert_eq!(written, 50_000);
+        assert_eq!(lines.get(), 50_000);
+        assert_eq!(reports, 50_000 / PROGRESS_EVERY);
+    }
+
+    fn schedule(conn: &Connection, title: &str, due: i64, suspended: bool) -> u64 {
+        let id = test_note(conn, title, "body");
+        conn.execute(
+            "UPDATE notes SET review_due = ?1, review_suspended = ?2, knowledge_type = 'Process' WHERE id = ?3",
+            rusqlite::params![due, suspended, id],
+        )
+        .unwrap();
+        id
+    }
+
+    #[test]
+    fn ics_has_one_all_day_event_per_future_card() {
+        let conn = test_vault();
+        // 2100-01-01 and 2100-01-02, both midnight UTC
+        let first = schedule(&conn, "Backups, restores; drills", 4_102_444_800, false);
+        schedule(&conn, "Second", 4_102_531_200, false);
+        schedule(&conn, "Overdue", 1_000, false);
+        schedule(&conn, "Suspended", 4_102_444_800, true);
+
+        let ics = export_review_ics(&conn).unwrap();
+        let lines: Vec<&str> = ics.split_terminator("\r\n").collect();
+
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert!(lines.contains(&"VERSION:2.0"));
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| **l == "END:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("DTSTAMP:") && l.ends_with('Z')).count(), 2);
+
+        let event: Vec<&str> = lines.iter().skip_while(|l| **l != "BEGIN:VEVENT").take(8).copied().collect();
+        assert_eq!(
+            event,
+            [
+                "BEGIN:VEVENT",
+                &format!("UID:review-{}-4102444800@quicknote", first),
+                event[2],
+                "DTSTART;VALUE=DATE:21000101",
+                "DTEND;VALUE=DATE:21000102",
+                "SUMMARY:Review: Backups\\, restores\\; drills",
+                "CATEGORIES:Process",
+                "END:VEVENT",
+            ]
+        );
+    }
+
+    #[test]
+    fn ics_folds_long_lines() {
+        let conn = test_vault();
+        schedule(&conn, &"x".repeat(200), 4_102_444_800, false);
+
+        let ics = export_review_ics(&conn).unwrap();
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        assert!(ics.contains("\r\n x"));
+    }
+
+    /// Empty scratch folder for file exports
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("quicknote-export-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn html_site_escapes_content_and_skips_secret_notes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "<b>XSS</b> & co", "<script>alert('x')</script>");
+        conn.execute(r#"UPDATE notes SET tags = '["a\"b"]' WHERE id = ?1"#, [id]).unwrap();
+        let secret = test_note(&conn, "Secret", "c1phertext");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [secret]).unwrap();
+        let dir = scratch_dir("html");
+
+        assert_eq!(export_html_site(&conn, &dir).unwrap(), 2);
+
+        let page = std::fs::read_to_string(dir.join("notes").join(format!("{}.html", id))).unwrap();
+        assert!(page.contains("<title>&lt;b&gt;XSS&lt;/b&gt; &amp; co</title>"));
+        assert!(page.contains("<pre>&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;</pre>"));
+        assert!(page.contains("#a&quot;b"));
+        assert!(!page.contains("<script>"));
+        assert!(!dir.join("notes").join(format!("{}.html", secret)).exists());
+
+        let index = std::fs::read_to_string(dir.join("index.html")).unwrap();
+        assert!(index.contains(&format!("<a href=\"notes/{}.html\">&lt;b&gt;XSS&lt;/b&gt; &amp; co</a>", id)));
+        assert!(!index.contains("Secret"));
+        let search = std::fs::read_to_string(dir.join("search-index.js")).unwrap();
+        assert!(search.starts_with("const SEARCH_INDEX = ["));
+        assert!(!search.contains("c1phertext"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn combined_markdown_follows_id_order_with_tag_footers() {
+        let conn = test_vault();
+        let first = test_note(&conn, "First", "one\n\n");
+        let second = test_note(&conn, "Second", "two");
+        conn.execute("UPDATE notes SET tags = '[\"db\",\"db/postgres\"]' WHERE id = ?1", [second]).unwrap();
+
+        let markdown = export_markdown_combined(&conn, &[second, first]).unwrap();
+        assert_eq!(markdown, "## Second\n\ntwo\n\nTags: #db #db/postgres\n\n---\n\n## First\n\none\n");
+    }
+
+    #[test]
+    fn combined_markdown_hides_encrypted_content_and_rejects_missing_notes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Secret", "c1phertext");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [id]).unwrap();
+
+        let markdown = export_markdown_combined(&conn, &[id]).unwrap();
+        assert!(markdown.starts_with("## Secret\n"));
+        assert!(!markdown.contains("c1phertext"));
+        assert_eq!(export_markdown_combined(&conn, &[id, 42]).unwrap_err().to_string(), "Note 42 not found");
+    }
+
+    #[test]
+    fn backup_with_sidecar_writes_zip_and_plaintext_copy() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Pool sizing", "size per core");
+        let dir = scratch_dir("sidecar");
+        std::fs::create_dir_all(&dir).unwrap();
+        let zip_path = dir.join("backup.zip");
+
+        assert_eq!(export_vault_file(&conn, &zip_path, None, true, |_, _| {}).unwrap(), 1);
+        let manifest = crate::import::read_vault_manifest(std::fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(manifest.note_count, 1);
+
+        let sidecar = dir.join("backup.plain");
+        let jsonl = std::fs::read_to_string(sidecar.join("notes.jsonl")).unwrap();
+        assert!(jsonl.contains("size per core"));
+        let markdown = std::fs::read_to_string(sidecar.join("markdown").join(format!("{}-pool-sizing.md", id))).unwrap();
+        assert!(markdown.starts_with("## Pool sizing"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_skips_sidecar_when_off_or_encrypted() {
+        let conn = test_vault();
+        test_note(&conn, "Note", "body");
+        let dir = scratch_dir("no-sidecar");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        export_vault_file(&conn, &dir.join("plain.zip"), None, false, |_, _| {}).unwrap();
+        export_vault_file(&conn, &dir.join("secret.zip"), Some("pass"), true, |_, _| {}).unwrap();
+        assert!(dir.join("plain.zip").exists() && dir.join("secret.zip").exists());
+        assert!(!dir.join("plain.plain").exists());
+        assert!(!dir.join("secret.plain").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Notes matching `query` (and optionally a type and tag), as the GUI's "export these
+    /// results" button selects them
+    fn filtered(conn: &Connection, query: &str, kind: Option<KnowledgeType>, tag: Option<&str>) -> Vec<u64> {
+        crate::search::filter_ids(conn, query, kind, tag, TagCase::Lower, false).unwrap()
+    }
+
+    #[test]
+    fn filtered_export_contains_exactly_the_search_results() {
+        let conn = test_vault();
+        let pool = test_note(&conn, "Pool sizing", "connection pool per core");
+        let pg = test_note(&conn, "Postgres pool", "pgbouncer, transaction mode");
+        let other = test_note(&conn, "Standup", "nothing about databases");
+        conn.execute("UPDATE notes SET updated_at = 100 WHERE id = ?1", [pool]).unwrap();
+
+        let ids = filtered(&conn, "pool", None, None);
+        assert_eq!(ids, vec![pg, pool]);
+
+        let json: Vec<serde_json::Value> = serde_json::from_str(&export_notes(&conn, &ids, ExportFormat::Json).unwrap()).unwrap();
+        let titles: Vec<&str> = json.iter().map(|note| note["title"].as_str().unwrap()).collect();
+        assert_eq!(titles, ["Postgres pool", "Pool sizing"]);
+
+        let csv = export_notes(&conn, &ids, ExportFormat::Csv).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains("Pool sizing") && !csv.contains("Standup"));
+
+        let markdown = export_notes(&conn, &ids, ExportFormat::Markdown).unwrap();
+        assert!(markdown.contains("Postgres pool") && !markdown.contains("Standup"));
+        assert!(filtered(&conn, "", None, None).contains(&other));
+    }
+
+    #[test]
+    fn filters_narrow_by_type_and_tag_hierarchy() {
+        let conn = test_vault();
+        let pg = test_note(&conn, "pg", "index tuning");
+        let mysql = test_note(&conn, "mysql", "index hints");
+        let plain = test_note(&conn, "plain", "index cards");
+        conn.execute("UPDATE notes SET tags = '[\"db/postgres\"]', knowledge_type = 'SQLQuery' WHERE id = ?1", [pg]).unwrap();
+        conn.execute("UPDATE notes SET tags = '[\"db\"]' WHERE id = ?1", [mysql]).unwrap();
+
+        assert_eq!(filtered(&conn, "index", None, Some("#DB")), vec![mysql, pg]);
+        assert_eq!(filtered(&conn, "index", Some(KnowledgeType::SQLQuery), Some("db")), vec![pg]);
+        // Untyped notes count as Concept
+        assert_eq!(filtered(&conn, "index", Some(KnowledgeType::Concept), None), vec![plain, mysql]);
+        assert!(crate::search::filter_ids(&conn, "index", None, Some("🙂"), TagCase::Lower, false).is_err());
+    }
+
+    #[test]
+    fn csv_quotes_awkward_fields() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Commas, \"quotes\"", "line one\nline two");
+        let csv = export_notes(&conn, &[id], ExportFormat::Csv).unwrap();
+        let row = csv.split("\r\n").nth(1).unwrap();
+        assert!(row.starts_with(&format!("{},\"Commas, \"\"quotes\"\"\",Concept,,", id)), "{}", row);
+        assert!(row.ends_with(",\"line one\nline two\""), "{}", row);
+        assert!(export_notes(&conn, &[id + 1], ExportFormat::Csv).is_err());
+    }
+
+    fn bulk_notes(conn: &mut Connection, count: usize) {
+        let tx = conn.transaction().unwrap();
+        tx.execute(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?1)
+             INSERT INTO notes (title, content) SELECT 'Note ' || i, 'body ' || i FROM n",
+            [count],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn progress_is_reported_in_order_up_to_the_total() {
+        let mut conn = test_vault();
+        let mut events = Vec::new();
+        export_jsonl(&conn, std::io::sink(), |done, total| events.push((done, total))).unwrap();
+        assert_eq!(events, vec![(0, 0)], "an empty vault still reports completion");
+
+        bulk_notes(&mut conn, 2 * PROGRESS_EVERY + 100);
+        let total = 2 * PROGRESS_EVERY + 100;
+        let expected = vec![(PROGRESS_EVERY, total), (2 * PROGRESS_EVERY, total), (total, total)];
+
+        let mut events = Vec::new();
+        export_jsonl(&conn, std::io::sink(), |done, total| events.push((done, total))).unwrap();
+        assert_eq!(events, expected);
+
+        // A full multiple of PROGRESS_EVERY reports 100% once, not twice
+        conn.execute("DELETE FROM notes WHERE id > ?1", [2 * PROGRESS_EVERY]).unwrap();
+        let mut events = Vec::new();
+        export_jsonl(&conn, std::io::sink(), |done, total| events.push((done, total))).unwrap();
+        assert_eq!(events, vec![(PROGRESS_EVERY, 2 * PROGRESS_EVERY), (2 * PROGRESS_EVERY, 2 * PROGRESS_EVERY)]);
+    }
+
+    #[test]
+    fn vault_backup_relays_progress() {
+        let mut conn = test_vault();
+        bulk_notes(&mut conn, PROGRESS_EVERY + 1);
+
+        let mut events = Vec::new();
+        let written = export_vault(&conn, std::io::Cursor::new(Vec::new()), None, |done, total| {
+            events.push((done, total))
+        })
+        .unwrap();
+        assert_eq!(written, PROGRESS_EVERY + 1);
+        assert_eq!(events, vec![(PROGRESS_EVERY, PROGRESS_EVERY + 1), (PROGRESS_EVERY + 1, PROGRESS_EVERY + 1)]);
+    }
+
+    #[test]
+    fn corrupt_tags_cell_exports_as_untagged() {
+        let conn = test_vault();
+        let good = test_note(&conn, "Good", "fine");
+        conn.execute(r#"UPDATE notes SET tags = '["ops"]' WHERE id = ?1"#, [good]).unwrap();
+        let corrupt = test_note(&conn, "Corrupt", "still exported");
+        conn.execute(r#"UPDATE notes SET tags = '["ops"' WHERE id = ?1"#, [corrupt]).unwrap();
+        let ids = [good, corrupt];
+
+        let mut out = Vec::new();
+        assert_eq!(export_jsonl(&conn, &mut out, |_, _| {}).unwrap(), 2);
+        let lines: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!((lines[0]["tags"].clone(), lines[1]["tags"].clone()), (serde_json::json!(["ops"]), serde_json::json!([])));
+
+        let mut out = Vec::new();
+        assert_eq!(export_content_only(&conn, &mut out).unwrap(), 2);
+        assert!(String::from_utf8(out).unwrap().contains(r#""title":"Corrupt","content":"still exported""#));
+
+        let csv = export_notes(&conn, &ids, ExportFormat::Csv).unwrap();
+        assert!(csv.contains(&format!("\r\n{},Corrupt,Concept,,", corrupt)), "{}", csv);
+        let markdown = export_markdown_combined(&conn, &ids).unwrap();
+        assert!(markdown.contains("## Corrupt\n\nstill exported\n"), "{}", markdown);
+
+        let dir = scratch_dir("html-corrupt");
+        assert_eq!(export_html_site(&conn, &dir).unwrap(), 3);
+        assert!(dir.join("notes").join(format!("{}.html", corrupt)).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filtered_deck_opens_as_a_working_vault() {
+        let conn = test_vault();
+        let card = test_note(&conn, "Index scans", "btree lookups");
+        conn.execute(
+            "UPDATE notes SET review_due = 500, review_interval = 6, review_streak = 2, review_easiness = 2.3 WHERE id = ?1",
+            [card],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO review_log (note_id, reviewed_at, rating, easiness, interval) VALUES (?1, 400, 4, 2.3, 6)", [card])
+            .unwrap();
+        let not_enrolled = test_note(&conn, "Heap scans", "btree pages");
+        let other = test_note(&conn, "Unrelated card", "gardening");
+        conn.execute("UPDATE notes SET review_due = 500 WHERE id = ?1", [other]).unwrap();
+
+        let ids = filtered(&conn, "btree", None, None);
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&not_enrolled));
+
+        let dir = scratch_dir("deck");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("deck.db");
+        assert_eq!(export_deck(&conn, &ids, &path).unwrap(), 1);
+        assert!(export_deck(&conn, &ids, &path).is_err(), "an existing file is never overwritten");
+
+        let deck = Connection::open(&path).unwrap();
+        db::init_schema(&deck).unwrap();
+        let (title, due, interval, streak): (String, i64, i64, i64) = deck
+            .query_row("SELECT title, review_due, review_interval, review_streak FROM notes", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((title.as_str(), due, interval, streak), ("Index scans", 500, 6, 2));
+        let logged: i64 = deck.query_row("SELECT COUNT(*) FROM review_log WHERE rating = 4", [], |row| row.get(0)).unwrap();
+        assert_eq!(logged, 1);
+        assert_eq!(filtered(&deck, "btree", None, None).len(), 1, "the deck's search index is rebuilt");
+        drop(deck);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
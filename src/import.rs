@@ -0,0 +1,609 @@
+//! Vault importers
+
+use crate::categorize::{categorize_note, KeywordPack, KnowledgeType};
+use crate::config::TagCase;
+use crate::db;
+use crate::export::{ContentNote, ExportedNote, ReviewState, VaultManifest, VAULT_MANIFEST, VAULT_NOTES};
+use crate::links;
+use crate::notes;
+use crate::tags::{normalize_tag, stored_tags};
+use rusqlite::Connection;
+use std::io::{BufRead, BufReader, Read, Seek};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Import notes written by `export::export_content_only`, keeping their ids: a note
+/// that already exists is overwritten in place (its review schedule is left alone), and
+/// a new one is inserted unenrolled. Returns the number of notes written.
+pub fn import_content<R: BufRead>(conn: &mut Connection, reader: R, tag_case: TagCase) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+
+    let mut imported = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let note: ContentNote = serde_json::from_str(&line)?;
+        let mut tags: Vec<String> = Vec::new();
+        for tag in note.tags.iter().filter_map(|t| normalize_tag(t, tag_case)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO notes (id, title, content, knowledge_type, tags, created_at, updated_at, source, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(id) DO UPDATE SET
+                 title = excluded.title, content = excluded.content, knowledge_type = excluded.knowledge_type,
+                 tags = excluded.tags, created_at = excluded.created_at, updated_at = excluded.updated_at,
+                 source = excluded.source, metadata = excluded.metadata",
+            rusqlite::params![
+                note.id,
+                note.title,
+                note.content,
+                note.knowledge_type,
+                serde_json::to_string(&tags)?,
+                note.created_at,
+                note.updated_at,
+                note.source,
+                note.metadata.to_string(),
+            ],
+        )?;
+        links::sync_links(&tx, note.id)?;
+        notes::refresh_metadata(&tx, note.id)?;
+        imported += 1;
+    }
+
+    tx.commit()?;
+    Ok(imported)
+}
+
+/// Merge review schedules written by `export::export_review_state` back onto notes by
+/// id. Ids with no matching note are skipped. Returns the number of notes updated.
+pub fn import_review_state<R: Read>(conn: &mut Connection, reader: R) -> Result<usize, Box<dyn std::error::Error>> {
+    let states: std::collections::BTreeMap<String, ReviewState> = serde_json::from_reader(reader)?;
+    let tx = conn.transaction()?;
+
+    let mut updated = 0;
+    for (id, state) in states {
+        let id: u64 = id.parse().map_err(|_| format!("Invalid note id '{}' in review state", id))?;
+        updated += tx.execute(
+            "UPDATE notes
+             SET review_due = ?1, review_interval = ?2, review_streak = ?3, review_easiness = ?4, review_suspended = ?5,
+                 review_lapses = ?6
+             WHERE id = ?7",
+            rusqlite::params![
+                state.review_due,
+                state.review_interval,
+                state.review_streak,
+                state.review_easiness,
+                state.review_suspended,
+                state.review_lapses,
+                id
+            ],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(updated)
+}
+
+/// Read the manifest of a backup written by `export::export_vault`, e.g. to find out
+/// whether to prompt for a passphrase
+pub fn read_vault_manifest<R: Read + Seek>(reader: R) -> Result<VaultManifest, Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let manifest = archive.by_name(VAULT_MANIFEST)?;
+    Ok(serde_json::from_reader(manifest)?)
+}
+
+/// Import a backup written by `export::export_vault`, adding its notes to the vault the
+/// same way `import_jsonl` does. Encrypted backups need their `passphrase`. Notes from a
+/// share bundle (`export::export_share_bundle`) are archived and locked, keeping them
+/// out of the user's own notes and read-only. Returns the number of notes imported.
+pub fn import_vault<R: Read + Seek>(
+    conn: &mut Connection,
+    reader: R,
+    passphrase: Option<&str>,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let manifest: VaultManifest = serde_json::from_reader(archive.by_name(VAULT_MANIFEST)?)?;
+
+    let notes = if manifest.encrypted {
+        let passphrase = passphrase.filter(|p| !p.is_empty()).ok_or("This backup is encrypted; a passphrase is required")?;
+        match archive.by_name_decrypt(VAULT_NOTES, passphrase.as_bytes()) {
+            Err(ZipError::InvalidPassword) => return Err("Wrong passphrase for this backup".into()),
+            result => result?,
+        }
+    } else {
+        archive.by_name(VAULT_NOTES)?
+    };
+
+    let tx = conn.transaction()?;
+    let since_id = next_id(&tx)?;
+    let imported = insert_exported(&tx, BufReader::new(notes), false, tag_case, default_type, skip_code_tags, packs)?;
+    if manifest.share {
+        tx.execute("UPDATE notes SET is_archived = 1, is_locked = 1 WHERE id >= ?1", [since_id])?;
+    }
+    tx.commit()?;
+    Ok(imported)
+}
+
+/// Import notes from a JSON Lines export, all in one transaction. Notes always get fresh
+/// ids, so nothing already in the vault is overwritten; review state travels with each
+/// row, and links between imported notes are re-pointed at the imported copies. With
+/// `reindex` the imported rows are re-indexed and re-categorized instead of trusting the
+/// source's tags/types. Returns the number of notes imported.
+pub fn import_jsonl<R: BufRead>(
+    conn: &mut Connection,
+    reader: R,
+    reindex: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let imported = insert_exported(&tx, reader, reindex, tag_case, default_type, skip_code_tags, packs)?;
+    tx.commit()?;
+    Ok(imported)
+}
+
+/// `import_jsonl` within the caller's transaction
+fn insert_exported<R: BufRead>(
+    tx: &Connection,
+    reader: R,
+    reindex: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let since_id = next_id(tx)?;
+
+    let mut imported = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let note: ExportedNote = serde_json::from_str(&line)?;
+        let mut tags: Vec<String> = Vec::new();
+        for tag in note.tags.iter().filter_map(|t| normalize_tag(t, tag_case)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO notes (title, content, knowledge_type, tags, created_at, updated_at, source,
+                                metadata, review_due, review_interval, review_streak, review_easiness, review_lapses,
+                                review_suspended, encrypted, is_archived, is_locked)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            rusqlite::params![
+                note.title,
+                note.content,
+                note.knowledge_type,
+                serde_json::to_string(&tags)?,
+                note.created_at,
+                note.updated_at,
+                note.source,
+                note.metadata.to_string(),
+                note.review_due,
+                note.review_interval,
+                note.review_streak,
+                note.review_easiness,
+                note.review_lapses,
+                note.review_suspended,
+                note.encrypted,
+                note.is_archived,
+                note.is_locked,
+            ],
+        )?;
+        let id = tx.last_insert_rowid() as u64;
+        links::sync_links(tx, id)?;
+        notes::refresh_metadata(tx, id)?;
+        imported += 1;
+    }
+    links::relink_within(tx, since_id)?;
+
+    if reindex {
+        reindex_rows(tx, since_id, true, tag_case, default_type, skip_code_tags, packs)?;
+    }
+
+    Ok(imported)
+}
+
+/// Rebuild FTS and optionally re-run categorization for notes with `id >= since_id`,
+/// e.g. after rows were bulk-loaded by another tool. Returns the number of notes touched.
+pub fn reindex_imported(
+    conn: &mut Connection,
+    since_id: u64,
+    recategorize: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let count = reindex_rows(&tx, since_id, recategorize, tag_case, default_type, skip_code_tags, packs)?;
+    tx.commit()?;
+    Ok(count)
+}
+
+fn reindex_rows(
+    conn: &Connection,
+    since_id: u64,
+    recategorize: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut touched = 0;
+
+    if recategorize {
+        // Encrypted notes are ciphertext; there is nothing to categorize
+        let mut stmt = conn.prepare("SELECT id, title, content, tags, type_locked FROM notes WHERE id >= ?1 AND encrypted = 0")?;
+        let rows = stmt.query_map([since_id], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, bool>(4)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (id, title, content, tags, type_locked) = row?;
+            let (knowledge_type, extracted) = categorize_note(&content, &title, tag_case, default_type, skip_code_tags, packs);
+
+            // Keep tags that came with the source data, add any found in the content
+            let mut tags = stored_tags(tags);
+            for tag in extracted {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+
+            if type_locked {
+                conn.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![serde_json::to_string(&tags)?, id])?;
+            } else {
+                conn.execute(
+                    "UPDATE notes SET knowledge_type = ?1, tags = ?2 WHERE id = ?3",
+                    rusqlite::params![knowledge_type.as_db_str(), serde_json::to_string(&tags)?, id],
+                )?;
+            }
+            notes::refresh_metadata(conn, id)?;
+            touched += 1;
+        }
+    } else {
+        touched = conn.query_row("SELECT COUNT(*) FROM notes WHERE id >= ?1", [since_id], |row| row.get(0))?;
+    }
+
+    let mut stmt = conn.prepare("SELECT id FROM notes WHERE id >= ?1")?;
+    let ids = stmt.query_map([since_id], |row| row.get::<_, u64>(0))?.collect::<Result<Vec<_>, _>>()?;
+    for id in ids {
+        links::sync_links(conn, id)?;
+    }
+
+    // Rows loaded behind the triggers' back may not be indexed at all, so per-row
+    // 'delete' commands aren't safe on an external-content table; rebuild instead
+    db::rebuild_fts(conn)?;
+
+    Ok(touched)
+}
+
+/// First id the next insert will receive (AUTOINCREMENT never reuses ids)
+fn next_id(conn: &Connection) -> Result<u64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'notes'), 0) + 1",
+        [],
+        |row| row.get(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    /// A source-tool export line with no `tags` and a stale type
+    const UNTAGGED: &str = r#"{"id": 7, "title": "Pool sizing", "content": "Size the pool per core #postgres #perf",
+        "knowledge_type": "Note", "created_at": 1, "updated_at": 1, "review_due": null,
+        "review_interval": 0, "review_streak": 0, "review_easiness": 2.5}"#;
+
+    fn import(conn: &mut Connection, reindex: bool) -> u64 {
+        let line = UNTAGGED.replace('\n', " ");
+        assert_eq!(import_jsonl(conn, line.as_bytes(), reindex, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 1);
+        conn.query_row("SELECT MAX(id) FROM notes", [], |row| row.get(0)).unwrap()
+    }
+
+    fn tags_of(conn: &Connection, id: u64) -> String {
+        conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn import_trusts_source_data_by_default() {
+        let mut conn = test_vault();
+        let id = import(&mut conn, false);
+        assert_eq!(tags_of(&conn, id), "[]");
+    }
+
+    #[test]
+    fn import_with_reindex_populates_missing_tags() {
+        let mut conn = test_vault();
+        let id = import(&mut conn, true);
+        assert_eq!(tags_of(&conn, id), r#"["postgres","perf"]"#);
+    }
+
+    #[test]
+    fn reindex_imported_only_touches_new_rows() {
+        let mut conn = test_vault();
+        let old = test_note(&conn, "Old", "untouched #legacy");
+        let since = import(&mut conn, false);
+
+        let touched = reindex_imported(&mut conn, since, true, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        assert_eq!(touched, 1);
+        assert_eq!(tags_of(&conn, since), r#"["postgres","perf"]"#);
+        assert_eq!(tags_of(&conn, old), "[]");
+    }
+
+    #[test]
+    fn reindex_imported_indexes_rows_loaded_behind_the_triggers() {
+        let mut conn = test_vault();
+        conn.execute_batch("DROP TRIGGER notes_ai").unwrap();
+        let id = test_note(&conn, "Bulk loaded", "unindexedword");
+        crate::db::init_schema(&conn).unwrap();
+
+        let fts_hits = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM notes_fts WHERE notes_fts MATCH 'unindexedword'", [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(fts_hits(&conn), 0);
+
+        reindex_imported(&mut conn, id, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+        assert_eq!(fts_hits(&conn), 1);
+    }
+
+    /// Export `conn` as a backup, with `passphrase` if given
+    fn backup(conn: &Connection, passphrase: Option<&str>) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        crate::export::export_vault(conn, &mut out, passphrase, |_, _| {}).unwrap();
+        out.into_inner()
+    }
+
+    fn restore(bytes: &[u8], passphrase: Option<&str>) -> Result<Connection, Box<dyn std::error::Error>> {
+        let mut conn = test_vault();
+        import_vault(&mut conn, std::io::Cursor::new(bytes), passphrase, TagCase::Lower, KnowledgeType::Concept, false, &[])?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn encrypted_backup_round_trips_with_the_right_passphrase() {
+        let conn = test_vault();
+        test_note(&conn, "Secret plan", "plaintext vault, encrypted backup");
+        let bytes = backup(&conn, Some("hunter2"));
+
+        let manifest = read_vault_manifest(std::io::Cursor::new(&bytes)).unwrap();
+        assert!(manifest.encrypted);
+        assert_eq!(manifest.note_count, 1);
+        assert!(!bytes.windows(11).any(|w| w == b"Secret plan"));
+
+        let restored = restore(&bytes, Some("hunter2")).unwrap();
+        let title: String = restored.query_row("SELECT title FROM notes", [], |row| row.get(0)).unwrap();
+        assert_eq!(title, "Secret plan");
+    }
+
+    #[test]
+    fn encrypted_backup_rejects_wrong_or_missing_passphrase() {
+        let conn = test_vault();
+        test_note(&conn, "Secret plan", "body");
+        let bytes = backup(&conn, Some("hunter2"));
+
+        let wrong = restore(&bytes, Some("letmein")).err().unwrap();
+        assert_eq!(wrong.to_string(), "Wrong passphrase for this backup");
+        let missing = restore(&bytes, None).err().unwrap();
+        assert!(missing.to_string().contains("passphrase is required"));
+    }
+
+    #[test]
+    fn unencrypted_backup_needs_no_passphrase() {
+        let conn = test_vault();
+        test_note(&conn, "Open", "body");
+        let bytes = backup(&conn, Some(""));
+
+        assert!(!read_vault_manifest(std::io::Cursor::new(&bytes)).unwrap().encrypted);
+        assert!(restore(&bytes, None).is_ok());
+    }
+
+    #[test]
+    fn backup_keeps_encrypted_archived_locked_and_suspended_state() {
+        let source = test_vault();
+        let id = test_note(&source, "Vault keys", "c1pher #notatag SELECT");
+        source
+            .execute(
+                "UPDATE notes SET encrypted = 1, is_archived = 1, is_locked = 1, review_due = 500, review_suspended = 1
+                 WHERE id = ?1",
+                [id],
+            )
+            .unwrap();
+        test_note(&source, "Plain", "body");
+
+        let mut restored = restore(&backup(&source, None), None).unwrap();
+        assert_eq!(full_rows(&restored), full_rows(&source));
+        let flags = |conn: &Connection, title: &str| -> (bool, bool, bool, bool) {
+            conn.query_row(
+                "SELECT encrypted, is_archived, is_locked, review_suspended FROM notes WHERE title = ?1",
+                [title],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(flags(&restored, "Vault keys"), (true, true, true, true));
+        assert_eq!(flags(&restored, "Plain"), (false, false, false, false));
+
+        // Re-indexing the restored rows leaves the ciphertext uncategorized
+        reindex_imported(&mut restored, id, true, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+        let (kind, tags): (Option<String>, String) = restored
+            .query_row("SELECT knowledge_type, tags FROM notes WHERE title = 'Vault keys'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((kind, tags.as_str()), (None, "[]"));
+    }
+
+    #[test]
+    fn import_with_overlapping_ids_gets_fresh_ids_and_remaps_links() {
+        let source = test_vault();
+        let alpha = test_note(&source, "Alpha", "see [[Beta]]");
+        let beta = test_note(&source, "Beta", "the source's beta");
+        source.execute("UPDATE notes SET review_due = 500 WHERE id = ?1", [beta]).unwrap();
+        let bytes = backup(&source, None);
+
+        let mut target = test_vault();
+        let old_beta = test_note(&target, "Beta", "the target's own beta");
+        let gamma = test_note(&target, "Gamma", "untouched");
+        assert_eq!((old_beta, gamma), (alpha, beta));
+        import_vault(&mut target, std::io::Cursor::new(&bytes), None, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        let rows: Vec<(u64, String, String)> = target
+            .prepare("SELECT id, title, content FROM notes ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], (old_beta, "Beta".to_string(), "the target's own beta".to_string()));
+        assert_eq!(rows[1], (gamma, "Gamma".to_string(), "untouched".to_string()));
+        let (new_alpha, new_beta) = (rows[2].0, rows[3].0);
+        assert!(new_alpha > gamma && new_beta > new_alpha);
+
+        let to_id: u64 = target
+            .query_row("SELECT to_id FROM note_links WHERE from_id = ?1", [new_alpha], |row| row.get(0))
+            .unwrap();
+        assert_eq!(to_id, new_beta);
+        let due: Option<i64> = target.query_row("SELECT review_due FROM notes WHERE id = ?1", [new_beta], |row| row.get(0)).unwrap();
+        assert_eq!(due, Some(500));
+    }
+
+    const FULL_ROW: &str = "SELECT id, title, content, knowledge_type, tags, created_at, updated_at, source, metadata,
+                                   review_due, review_interval, review_streak, review_easiness, review_suspended, review_lapses
+                            FROM notes ORDER BY id";
+
+    fn full_rows(conn: &Connection) -> Vec<Vec<rusqlite::types::Value>> {
+        let mut stmt = conn.prepare(FULL_ROW).unwrap();
+        let rows = stmt.query_map([], |row| (0..15).map(|i| row.get(i)).collect::<Result<Vec<_>, _>>()).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn content_then_review_state_import_rebuilds_the_vault() {
+        let source = test_vault();
+        test_note(&source, "Plain", "no schedule #plain");
+        let card = test_note(&source, "Card", "studied");
+        source
+            .execute(
+                "UPDATE notes SET tags = '[\"srs\"]', knowledge_type = 'Concept', source = 'https://example.com',
+                        review_due = 123, review_interval = 6, review_streak = 2, review_easiness = 2.36,
+                        review_suspended = 1, review_lapses = 1
+                 WHERE id = ?1",
+                [card],
+            )
+            .unwrap();
+        // Leave a gap in the ids, which must survive
+        let gone = test_note(&source, "Deleted", "");
+        source.execute("DELETE FROM notes WHERE id = ?1", [gone]).unwrap();
+        test_note(&source, "Last", "");
+
+        let mut content = Vec::new();
+        crate::export::export_content_only(&source, &mut content).unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("review"));
+        let mut review = Vec::new();
+        assert_eq!(crate::export::export_review_state(&source, &mut review).unwrap(), 1);
+
+        let mut target = test_vault();
+        assert_eq!(import_content(&mut target, content.as_slice(), TagCase::Lower).unwrap(), 3);
+        assert_eq!(import_review_state(&mut target, review.as_slice()).unwrap(), 1);
+        assert_eq!(full_rows(&target), full_rows(&source));
+    }
+
+    #[test]
+    fn content_reimport_keeps_local_review_schedule() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Card", "old text");
+        conn.execute("UPDATE notes SET review_due = 500, review_streak = 4 WHERE id = ?1", [id]).unwrap();
+
+        let line = format!(
+            r#"{{"id": {}, "title": "Card", "content": "new text", "knowledge_type": null, "tags": [], "created_at": 1, "updated_at": 2}}"#,
+            id
+        );
+        import_content(&mut conn, line.as_bytes(), TagCase::Lower).unwrap();
+        let (content, due, streak): (String, i64, i64) = conn
+            .query_row("SELECT content, review_due, review_streak FROM notes WHERE id = ?1", [id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((content.as_str(), due, streak), ("new text", 500, 4));
+    }
+
+    /// Share `ids` from `conn`, with `passphrase` if given
+    fn share(conn: &Connection, ids: &[u64], passphrase: Option<&str>) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        crate::export::export_share_bundle(conn, &mut out, ids, passphrase).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn share_bundle_imports_archived_and_read_only() {
+        let source = test_vault();
+        let shared = test_note(&source, "Shared tip", "vacuum after bulk deletes");
+        source.execute("UPDATE notes SET review_due = 500, review_streak = 4 WHERE id = ?1", [shared]).unwrap();
+        test_note(&source, "Private", "not for sharing");
+        let secret = test_note(&source, "Secret", "c1pher");
+        source.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [secret]).unwrap();
+
+        let mut out = std::io::Cursor::new(Vec::new());
+        assert!(crate::export::export_share_bundle(&source, &mut out, &[shared, secret], None).is_err());
+
+        for passphrase in [None, Some("hunter2")] {
+            let bytes = share(&source, &[shared, shared], passphrase);
+            let manifest = read_vault_manifest(std::io::Cursor::new(&bytes)).unwrap();
+            assert!(manifest.share);
+            assert_eq!((manifest.note_count, manifest.encrypted), (1, passphrase.is_some()));
+
+            let mut target = test_vault();
+            let own = test_note(&target, "My note", "mine");
+            import_vault(&mut target, std::io::Cursor::new(&bytes), passphrase, TagCase::Lower, KnowledgeType::Concept, false, &[])
+                .unwrap();
+            let rows: Vec<(String, bool, bool, Option<i64>)> = target
+                .prepare("SELECT title, is_archived, is_locked, review_due FROM notes ORDER BY id")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(
+                rows,
+                vec![("My note".to_string(), false, false, None), ("Shared tip".to_string(), true, true, None)],
+                "{:?}",
+                passphrase
+            );
+            let shared_copy = own + 1;
+            let edit = crate::notes::update_note(
+                &target, shared_copy, "Edited", "changed", None, 1 << 20, TagCase::Lower, KnowledgeType::Concept, false, &[],
+            );
+            assert!(matches!(edit, Err(crate::error::QuickNoteError::Locked(id)) if id == shared_copy), "shares are read-only");
+        }
+
+        // Ordinary backups still import as the user's own notes
+        let restored = restore(&backup(&source, None), None).unwrap();
+        let locked: i64 = restored.query_row("SELECT COUNT(*) FROM notes WHERE is_locked = 1 OR is_archived = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(locked, 0);
+    }
+}
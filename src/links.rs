@@ -0,0 +1,259 @@
+//! `[[wiki-links]]` between notes
+//!
+//! A note's outgoing links are stored in `note_links` whenever its content is written.
+//! Targets are matched by title, case-insensitively; `to_id` is NULL while no note has
+//! that title. Triggers (see `db::migrate`) re-resolve targets when notes are added,
+//! renamed or deleted, so callers only need `sync_links` after changing content.
+
+use rusqlite::Connection;
+
+/// Link targets in `content`, in order of first appearance. `[[Title|label]]` and
+/// `[[Title#heading]]` both point at `Title`.
+pub fn extract_links(content: &str) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else { break };
+        let inner = &rest[..end];
+        rest = &rest[end + 2..];
+
+        let target = inner.split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() && !inner.contains('\n') && !targets.iter().any(|t| t.eq_ignore_ascii_case(target)) {
+            targets.push(target.to_string());
+        }
+    }
+    targets
+}
+
+/// Re-read a note's content and replace its outgoing links. Encrypted notes have no
+/// readable links; a missing note just has its links cleared.
+pub fn sync_links(conn: &Connection, id: u64) -> Result<(), rusqlite::Error> {
+    let content: Option<String> = conn
+        .query_row(
+            "SELECT CASE WHEN encrypted THEN '' ELSE content END FROM notes WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })?;
+
+    conn.execute("DELETE FROM note_links WHERE from_id = ?1", [id])?;
+    for target in extract_links(content.as_deref().unwrap_or("")) {
+        conn.execute(
+            "INSERT INTO note_links (from_id, to_title, to_id)
+             VALUES (?1, ?2, (SELECT id FROM notes WHERE title = ?2 COLLATE NOCASE ORDER BY id LIMIT 1))",
+            rusqlite::params![id, target],
+        )?;
+    }
+    Ok(())
+}
+
+/// Point links from notes `id >= since_id` at targets in that same id range when one has
+/// the title, instead of an older note that happens to share it. Used after an import,
+/// so a merged vault's notes keep linking to each other. Returns the number of links moved.
+#[cfg(feature = "gui")]
+pub fn relink_within(conn: &Connection, since_id: u64) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "UPDATE note_links
+         SET to_id = (SELECT id FROM notes WHERE id >= ?1 AND title = note_links.to_title COLLATE NOCASE
+                      ORDER BY id LIMIT 1)
+         WHERE from_id >= ?1
+           AND EXISTS (SELECT 1 FROM notes WHERE id >= ?1 AND title = note_links.to_title COLLATE NOCASE)",
+        [since_id],
+    )
+}
+
+/// Rebuild `note_links` for every note, e.g. for a vault created before links existed.
+/// Returns the number of links stored.
+pub fn rebuild_links(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let ids = {
+        let mut stmt = conn.prepare("SELECT id FROM notes")?;
+        let ids = stmt.query_map([], |row| row.get::<_, u64>(0))?;
+        ids.collect::<Result<Vec<_>, _>>()?
+    };
+
+    conn.execute("DELETE FROM note_links", [])?;
+    for id in ids {
+        sync_links(conn, id)?;
+    }
+    conn.query_row("SELECT COUNT(*) FROM note_links", [], |row| row.get(0))
+}
+
+/// Notes linking to titles that don't exist, with the missing titles per note
+#[cfg(feature = "gui")]
+pub fn broken_links(conn: &Connection) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, l.to_title
+         FROM note_links l
+         JOIN notes n ON n.id = l.from_id
+         WHERE l.to_id IS NULL
+         ORDER BY n.title COLLATE NOCASE, n.id, l.to_title COLLATE NOCASE",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+    })?;
+
+    // Rows arrive grouped by note, so consecutive targets belong to the same entry
+    let mut report: Vec<(u64, String, Vec<String>)> = Vec::new();
+    for row in rows {
+        let (id, title, target) = row?;
+        match report.last_mut() {
+            Some((last_id, _, missing)) if *last_id == id => missing.push(target),
+            _ => report.push((id, title, vec![target])),
+        }
+    }
+
+    Ok(report
+        .into_iter()
+        .map(|(id, title, missing)| serde_json::json!({ "id": id, "title": title, "missing": missing }))
+        .collect())
+}
+
+/// Nodes (`id`, `title`, `knowledge_type`) and resolved links (`from_id`, `to_id`) for a
+/// graph view. With `root_id`, only notes within `depth` links of it (in either
+/// direction) are included. Archived notes are left out, and paths don't pass through them.
+#[cfg(feature = "gui")]
+pub fn note_graph(conn: &Connection, root_id: Option<u64>, depth: usize) -> Result<serde_json::Value, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE
+             active(id) AS (SELECT id FROM notes WHERE is_archived = 0),
+             edges(a, b) AS (
+                 SELECT from_id, to_id FROM note_links
+                 WHERE from_id IN active AND to_id IN active
+                 UNION
+                 SELECT to_id, from_id FROM note_links
+                 WHERE from_id IN active AND to_id IN active
+             ),
+             hood(id, depth) AS (
+                 SELECT id, 0 FROM active WHERE ?1 IS NULL OR id = ?1
+                 UNION
+                 SELECT e.b, h.depth + 1 FROM hood h JOIN edges e ON e.a = h.id
+                 WHERE ?1 IS NOT NULL AND h.depth < ?2
+             )
+         SELECT n.id, n.title, COALESCE(n.knowledge_type, 'Concept')
+         FROM notes n WHERE n.id IN (SELECT id FROM hood)
+         ORDER BY n.id",
+    )?;
+    let nodes = stmt
+        .query_map(rusqlite::params![root_id, depth as i64], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let ids: std::collections::HashSet<u64> = nodes.iter().map(|(id, _, _)| *id).collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT from_id, to_id FROM note_links WHERE to_id IS NOT NULL ORDER BY from_id, to_id",
+    )?;
+    let mut edges = stmt
+        .query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    edges.retain(|(from, to)| ids.contains(from) && ids.contains(to));
+
+    Ok(serde_json::json!({
+        "nodes": nodes
+            .into_iter()
+            .map(|(id, title, knowledge_type)| serde_json::json!({ "id": id, "title": title, "knowledge_type": knowledge_type }))
+            .collect::<Vec<_>>(),
+        "edges": edges
+            .into_iter()
+            .map(|(from_id, to_id)| serde_json::json!({ "from_id": from_id, "to_id": to_id }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    fn linking(conn: &Connection, title: &str, content: &str) -> u64 {
+        let id = test_note(conn, title, content);
+        sync_links(conn, id).unwrap();
+        id
+    }
+
+    #[test]
+    fn extracts_targets_once_ignoring_labels_and_headings() {
+        assert_eq!(
+            extract_links("see [[Indexes|the index note]], [[Vacuum#tuning]], [[indexes]] and [[ ]] or [[Open"),
+            vec!["Indexes", "Vacuum"]
+        );
+    }
+
+    #[test]
+    fn link_to_missing_title_is_reported() {
+        let conn = test_vault();
+        test_note(&conn, "Indexes", "");
+        let id = linking(&conn, "Postgres", "[[indexes]] and [[Vacuum]] and [[Bloat]]");
+
+        let report = broken_links(&conn).unwrap();
+        assert_eq!(report, vec![serde_json::json!({ "id": id, "title": "Postgres", "missing": ["Bloat", "Vacuum"] })]);
+    }
+
+    #[test]
+    fn creating_the_target_resolves_the_link() {
+        let conn = test_vault();
+        linking(&conn, "Postgres", "[[Vacuum]]");
+
+        test_note(&conn, "vacuum", "");
+        assert!(broken_links(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn deleting_the_target_breaks_the_link() {
+        let conn = test_vault();
+        let target = test_note(&conn, "Vacuum", "");
+        let id = linking(&conn, "Postgres", "[[Vacuum]]");
+
+        conn.execute("DELETE FROM notes WHERE id = ?1", [target]).unwrap();
+        assert_eq!(broken_links(&conn).unwrap()[0]["id"], id);
+    }
+
+    fn graph_ids(graph: &serde_json::Value, key: &str) -> Vec<u64> {
+        graph[key].as_array().unwrap().iter().map(|node| node["id"].as_u64().unwrap()).collect()
+    }
+
+    fn graph_edges(graph: &serde_json::Value) -> Vec<(u64, u64)> {
+        let edges = graph["edges"].as_array().unwrap();
+        edges.iter().map(|e| (e["from_id"].as_u64().unwrap(), e["to_id"].as_u64().unwrap())).collect()
+    }
+
+    #[test]
+    fn graph_edges_match_note_links() {
+        let conn = test_vault();
+        let a = linking(&conn, "A", "[[B]] [[C]] [[Missing]]");
+        let b = linking(&conn, "B", "[[C]]");
+        let c = linking(&conn, "C", "");
+        // note_links rows were written before B and C existed; the insert triggers resolve them
+        let archived = linking(&conn, "Old", "[[A]]");
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        let graph = note_graph(&conn, None, 0).unwrap();
+        assert_eq!(graph_ids(&graph, "nodes"), vec![a, b, c]);
+
+        let mut stmt = conn
+            .prepare("SELECT from_id, to_id FROM note_links WHERE to_id IS NOT NULL AND from_id != ?1 ORDER BY from_id, to_id")
+            .unwrap();
+        let links: Vec<(u64, u64)> =
+            stmt.query_map([archived], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(graph_edges(&graph), links);
+        assert_eq!(links, vec![(a, b), (a, c), (b, c)]);
+    }
+
+    #[test]
+    fn graph_neighbourhood_follows_links_both_ways_up_to_depth() {
+        let conn = test_vault();
+        let a = linking(&conn, "A", "[[B]]");
+        let b = linking(&conn, "B", "[[C]]");
+        let c = linking(&conn, "C", "[[D]]");
+        linking(&conn, "D", "");
+
+        let graph = note_graph(&conn, Some(b), 1).unwrap();
+        assert_eq!(graph_ids(&graph, "nodes"), vec![a, b, c]);
+        assert_eq!(graph_edges(&graph), vec![(a, b), (b, c)]);
+    }
+}
@@ -0,0 +1,91 @@
+//! Log setup shared by the CLI and the Tauri backend
+//!
+//! Verbosity, highest priority first: the `RUST_LOG` environment variable, the CLI's
+//! `--verbose` flag, then `log_level` in config.json (default `info`).
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+/// Install the global subscriber. Safe to call more than once; later calls are ignored.
+pub fn init(config_level: &str, verbose: bool) {
+    let level = config_level.parse::<LevelFilter>();
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| default_filter(level.clone().ok(), verbose));
+
+    let _ = tracing_subscriber::fmt().with_env_filter(filter).with_target(false).try_init();
+    if level.is_err() {
+        tracing::warn!("Invalid log_level '{}' in config.json, using info", config_level);
+    }
+}
+
+/// Filter used when `RUST_LOG` isn't set
+fn default_filter(level: Option<LevelFilter>, verbose: bool) -> EnvFilter {
+    let level = if verbose {
+        LevelFilter::DEBUG
+    } else {
+        level.unwrap_or(LevelFilter::INFO)
+    };
+    EnvFilter::default().add_directive(level.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Everything logged by `emit` under `filter`, formatted as `init` does
+    fn logged(filter: EnvFilter, emit: impl FnOnce()) -> String {
+        let out = Captured::default();
+        let writer = out.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_target(false)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, emit);
+        let bytes = out.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn emit_all() {
+        tracing::debug!("debug line");
+        tracing::info!("info line");
+    }
+
+    #[test]
+    fn failed_database_open_is_logged_at_error_level() {
+        let path = std::env::temp_dir().join("quicknote-no-such-dir").join("nested").join("vault.db");
+        let out = logged(default_filter(Some(LevelFilter::ERROR), false), || {
+            emit_all();
+            assert!(crate::db::open(&path).is_err());
+        });
+        let line = out.lines().find(|line| line.contains("Failed to open database")).unwrap();
+        assert!(line.contains("ERROR"), "{}", line);
+        assert!(line.contains("vault.db"), "{}", line);
+        assert!(!out.contains("info line"));
+    }
+
+    #[test]
+    fn default_level_is_info_and_verbose_adds_debug() {
+        let out = logged(default_filter(None, false), emit_all);
+        assert!(out.contains("info line"));
+        assert!(!out.contains("debug line"));
+
+        let out = logged(default_filter(Some(LevelFilter::WARN), true), emit_all);
+        assert!(out.contains("debug line"));
+    }
+}
@@ -1,269 +1,286 @@
 //! QuickNote — Portable Knowledge Pocket
 //! Single-binary, admin-free knowledge keeper
 
-use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+// Shared with the Tauri backend (src-tauri/main.rs); its extra operations sit behind the `gui` feature
+mod categorize;
+mod config;
+mod dates;
+mod db;
+mod error;
+mod links;
+mod logging;
+mod notes;
+mod search;
+mod tags;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Config {
-    gui_mode: bool,
-    modules: Vec<String>,
-    encryption_enabled: bool,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            gui_mode: true,
-            modules: vec!["search".to_string(), "categorize".to_string()],
-            encryption_enabled: false,
-        }
-    }
-}
-
-#[derive(Debug)]
-enum KnowledgeType {
-    Concept,
-    Snippet,
-    Checklist,
-    Note,
-    Process,
-    SQLQuery,
-    DebugPattern,
-}
+use config::Config;
+use search::search_notes;
+use std::path::{Path, PathBuf};
+use tracing::{error, info, warn};
 
-impl std::fmt::Display for KnowledgeType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Concept => write!(f, "Concept"),
-            Self::Snippet => write!(f, "Snippet"),
-            Self::Checklist => write!(f, "Checklist"),
-            Self::Note => write!(f, "Note"),
-            Self::Process => write!(f, "Process"),
-            Self::SQLQuery => write!(f, "SQL Query"),
-            Self::DebugPattern => write!(f, "Debug Pattern"),
-        }
-    }
+/// Portable mode detection — checks if data folder exists alongside executable.
+///
+/// The folder is named by `portable_dir_name` in config.json, so config is read first; if
+/// it's missing or unreadable, or the name isn't a plain folder name, the default `data`
+/// is used.
+fn detect_portable_mode(dir_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let exe_path = std::env::current_exe()?;
+    let app_dir = exe_path.parent().ok_or("Cannot determine app directory")?;
+    find_data_dir(app_dir, dir_name)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Note {
-    id: u64,
-    title: String,
-    content: String,
-    knowledge_type: KnowledgeType,
-    tags: Vec<String>,
-    created_at: i64,
-    updated_at: i64,
-}
+fn find_data_dir(app_dir: &Path, dir_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir_name = dir_name.trim();
+    let is_plain_name = !dir_name.is_empty() && dir_name != ".." && !dir_name.contains(['/', '\\']);
+    let dir_name = if is_plain_name { dir_name } else { config::DEFAULT_PORTABLE_DIR };
 
-/// Portable mode detection — checks if data folder exists alongside executable
-fn detect_portable_mode() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let exe_path = std::env::current_exe()?;
-    let app_dir = exe_path.parent().ok_or("Cannot determine app directory")?;
-    
     // Check if data folder exists (portable mode indicator)
-    let data_dir = app_dir.join("data");
+    let data_dir = app_dir.join(dir_name);
     
     if data_dir.exists() && data_dir.is_dir() {
         Ok(data_dir)
     } else {
-        Err("Not in portable mode — please create 'data' folder alongside executable".into())
+        Err(format!("Not in portable mode — please create '{}' folder alongside executable", dir_name).into())
     }
 }
 
-/// Auto-categorize note based on content patterns
-fn categorize_note(content: &str, title: &str) -> (KnowledgeType, Vec<String>) {
-    let mut tags = Vec::new();
-    
-    // Extract #tags from content
-    for word in content.split_whitespace() {
-        if word.starts_with('#') && !word.is_empty() {
-            tags.push(word[1..].to_string());
-        }
-    }
-    
-    // Pattern matching for knowledge type detection
-    let lower_content = content.to_lowercase();
-    let lower_title = title.to_lowercase();
-    
-    if lower_content.contains("select") || lower_content.contains("from ") || lower_content.contains("insert into") {
-        return (KnowledgeType::SQLQuery, tags);
-    }
-    
-    if lower_content.contains("error") || lower_content.contains("exception") || lower_content.contains("panic") {
-        return (KnowledgeType::DebugPattern, tags);
-    }
-    
-    if lower_title.starts_with(|c: char| c.is_ascii_digit()) && content.split('\n').count() > 3 {
-        return (KnowledgeType::Process, tags);
-    }
-    
-    // Default to Concept for most knowledge items
-    (KnowledgeType::Concept, tags)
-}
-
 /// Initialize SQLite database if not exists
 fn init_database(db_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     let conn = rusqlite::Connection::open(db_path)?;
+    db::init_schema(&conn)?;
     
-    // Create notes table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS notes (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            knowledge_type TEXT CHECK(knowledge_type IN 
-                ('Concept', 'Snippet', 'Checklist', 'Note', 'Process', 'SQLQuery', 'DebugPattern')),
-            tags TEXT DEFAULT '[]',
-            created_at INTEGER DEFAULT (strftime('%s', 'now')),
-            updated_at INTEGER DEFAULT (strftime('%s', 'now'))
-        )",
-        [],
-    )?;
-    
-    // Create FTS5 virtual table for full-text search
-    conn.execute(
-        "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
-            title, content,
-            content='notes',
-            content_rowid='id'
-        )",
-        [],
-    )?;
-    
-    // Triggers to keep FTS in sync
-    conn.execute(
-        "CREATE TRIGGER IF NOT EXISTS notes_ai AFTER INSERT ON notes BEGIN
-            INSERT INTO notes_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
-        END",
-        [],
-    )?;
-    
-    println!("✅ Database initialized at {:?}", db_path);
+    info!("✅ Database initialized at {:?}", db_path);
     Ok(())
 }
 
-/// Add a new note to the vault
-fn add_note(conn: &rusqlite::Connection, title: String, content: String) -> Result<u64, Box<dyn std::error::Error>> {
-    let (knowledge_type, tags) = categorize_note(&content, &title);
+/// Add a new note to the vault. `tags` are added to the `#tags` found in the content.
+fn add_note(
+    conn: &rusqlite::Connection,
+    title: String,
+    content: String,
+    tags: &[String],
+    config: &Config,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    notes::check_note_size(&content, config.max_note_bytes)?;
+    if config.unique_titles {
+        notes::ensure_title_available(conn, &title)?;
+    }
+    let (knowledge_type, tags) = config.note_fields(&title, &content, tags);
     
     // Insert note
-    let id = conn.query_row(
-        "INSERT INTO notes (title, content, knowledge_type, tags) VALUES (?, ?, ?, ?)",
-        rusqlite::params![title, content, knowledge_type.to_string(), serde_json::to_string(&tags)?],
-        |row| row.get::<_, u64>(0),
-    )?;
-    
-    // Update FTS index
     conn.execute(
-        "INSERT INTO notes_fts(rowid, title, content) VALUES (?, ?, ?)",
-        rusqlite::params![id, title, content],
+        "INSERT INTO notes (title, content, knowledge_type, tags) VALUES (?, ?, ?, ?)",
+        rusqlite::params![title, content, knowledge_type.as_db_str(), serde_json::to_string(&tags)?],
     )?;
+    let id = conn.last_insert_rowid() as u64;
+    links::sync_links(conn, id)?;
+    notes::refresh_metadata(conn, id)?;
+    // FTS index is updated by the notes_ai trigger (when FTS5 is available)
     
-    println!("✅ Note added: {} (ID: {})", title, id);
+    info!("✅ Note added: {} (ID: {})", title, id);
     Ok(id)
 }
 
-/// Search notes using FTS5
-fn search_notes(conn: &rusqlite::Connection, query: &str) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
-    let results = conn.query_map(
-        "SELECT n.id, n.title, n.content, n.knowledge_type, n.tags, n.created_at 
-         FROM notes n 
-         JOIN notes_fts f ON n.id = f.rowid 
-         WHERE notes_fts MATCH ?
-         ORDER BY n.updated_at DESC",
-        [query],
-        |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                content: row.get(2)?,
-                knowledge_type: match row.get::<_, String>(3)?.as_str() {
-                    "Concept" => KnowledgeType::Concept,
-                    "Snippet" => KnowledgeType::Snippet,
-                    "Checklist" => KnowledgeType::Checklist,
-                    "Note" => KnowledgeType::Note,
-                    "Process" => KnowledgeType::Process,
-                    "SQLQuery" => KnowledgeType::SQLQuery,
-                    "DebugPattern" => KnowledgeType::DebugPattern,
-                    _ => KnowledgeType::Concept, // fallback
-                },
-                tags: serde_json::from_str(row.get::<_, String>(4)?)?,
-                created_at: row.get(5)?,
-                updated_at: 0, // would need to query again for updated_at in real impl
-            })
-        },
-    )?;
-    
-    let notes: Result<Vec<Note>, _> = results.collect();
-    Ok(notes?)
+/// Log a fatal error with what was being attempted, then exit
+fn fail(context: &str, e: impl std::fmt::Display) -> ! {
+    error!("{}: {}", context, e);
+    std::process::exit(1);
 }
 
 fn main() {
-    println!("🚀 QuickNote — Portable Knowledge Pocket v0.1");
+    // Load config (before detection: it names the data folder, and sets the log level)
+    let config = Config::load();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let verbose = args.iter().any(|arg| arg == "--verbose" || arg == "-v");
+    let demo = args.iter().any(|arg| arg == "--demo");
+    logging::init(&config.log_level, verbose);
+    for warning in &config.warnings {
+        warn!("{}", warning);
+    }
+
+    info!("🚀 QuickNote — Portable Knowledge Pocket v0.1");
     
     // Detect portable mode
-    let data_dir = match detect_portable_mode() {
+    let data_dir = match detect_portable_mode(&config.portable_dir_name) {
         Ok(path) => {
-            println!("✅ Running in PORTABLE MODE from: {:?}", path);
+            info!("✅ Running in PORTABLE MODE from: {:?}", path);
             path
         }
-        Err(e) => {
-            eprintln!("❌ Error: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => fail("Cannot locate the vault", e),
     };
     
+    let conn = open_vault(&data_dir, &config, demo);
+    
+    info!("🎯 QuickNote is ready!");
+    info!("To start adding notes:");
+    info!("  - Launch GUI mode (if enabled) with `cargo tauri dev`");
+    info!("  - Or use CLI commands directly");
+    
+    if demo {
+        demo_search(&conn, &config);
+    }
+}
+
+/// Create (if needed), open and migrate the vault in `data_dir`. With `demo`, an empty
+/// vault gets a sample note.
+fn open_vault(data_dir: &Path, config: &Config, demo: bool) -> rusqlite::Connection {
     let db_path = data_dir.join("vault.db");
     
     // Initialize database if not exists
     if !db_path.exists() {
-        println!("📦 Initializing new vault...");
-        init_database(&db_path).unwrap();
+        info!("📦 Initializing new vault...");
+        if let Err(e) = init_database(&db_path) {
+            fail(&format!("Failed to initialize database at {:?}", db_path), e);
+        }
     }
     
-    // Load config
-    let config_path = std::env::current_exe().unwrap().parent().unwrap().join("config.json");
-    let config: Config = if config_path.exists() {
-        let content = fs::read_to_string(&config_path).unwrap();
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Config::default()
-    };
-    
-    println!("📋 Configuration loaded: {} modules active", config.modules.len());
+    info!("📋 Configuration loaded: {} modules active", config.modules.len());
+    if let Some(path) = &config.path {
+        tracing::debug!("Config file: {:?}", path);
+    }
     
     // Connect to database
-    let conn = rusqlite::Connection::open(&db_path).expect("Failed to open database");
-    
-    // Demo mode: add a sample note if vault is empty
-    let count: usize = conn.query_row(
-        "SELECT COUNT(*) FROM notes",
-        [],
-        |row| row.get(0),
-    ).unwrap();
-    
-    if count == 0 {
-        println!("📝 Adding demo note (delete via SQL to start fresh)...");
-        let _id = add_note(&conn, 
-            "Welcome to QuickNote!".to_string(), 
-            "This is your portable knowledge pocket. Press Ctrl+K to quickly capture thoughts.\n\n#sql query for finding duplicate emails:\nSELECT email, COUNT(*) FROM users GROUP BY email HAVING COUNT(*) > 1;".to_string()
-        ).unwrap();
+    // `db::open` has already logged the failure
+    let mut conn = db::open(&db_path).unwrap_or_else(|_| std::process::exit(1));
+    if let Err(e) = db::migrate(&conn) {
+        fail("Failed to migrate database", e);
+    }
+    match db::set_fts_mode(&mut conn, config.fts_mode) {
+        Ok(true) => info!("Rebuilt search index in {:?} mode", config.fts_mode),
+        Ok(false) => {}
+        Err(e) => fail("Failed to switch search index mode", e),
+    }
+    if config.tag_case == config::TagCase::Lower {
+        match tags::fold_tag_case(&mut conn) {
+            Ok(0) => {}
+            Ok(changed) => info!("Folded tag case on {} note(s)", changed),
+            // Tags stay as they were; not worth refusing to start over
+            Err(e) => warn!("Failed to fold tag case: {}", e),
+        }
     }
     
-    println!("🎯 QuickNote is ready!");
-    println!("\nTo start adding notes:");
-    println!("  - Launch GUI mode (if enabled) with `cargo tauri dev`");
-    println!("  - Or use CLI commands directly");
+    // With --demo, an empty vault gets a sample note to search
+    if demo {
+        let count = db::note_count(&conn).unwrap_or_else(|e| fail("Failed to count notes", e));
+        if count == 0 {
+            info!("📝 Adding demo note (delete via SQL to start fresh)...");
+            if let Err(e) = add_note(&conn, 
+                "Welcome to QuickNote!".to_string(), 
+                "This is your portable knowledge pocket. Press Ctrl+K to quickly capture thoughts.\n\n#sql query for finding duplicate emails:\nSELECT email, COUNT(*) FROM users GROUP BY email HAVING COUNT(*) > 1;".to_string(),
+                &[],
+                config,
+            ) {
+                fail("Failed to add demo note", e);
+            }
+        }
+    }
     
-    // Demo search
-    let demo_results = search_notes(&conn, "sql").unwrap();
-    if !demo_results.is_empty() {
-        println!("\n🔍 Search demo found {} note(s) matching 'sql':", demo_results.len());
-        for note in &demo_results {
-            println!("  - [{}] {}", note.knowledge_type, note.title);
+    conn
+}
+
+/// Show off search with `--demo`. Purely cosmetic: an empty vault or a failed search
+/// prints nothing.
+fn demo_search(conn: &rusqlite::Connection, config: &Config) {
+    if !matches!(db::note_count(conn), Ok(count) if count > 0) {
+        return;
+    }
+
+    let results = search_notes(
+        conn,
+        "sql",
+        None,
+        config.search_max_results,
+        config.min_query_len,
+        false,
+        config.search_weights(),
+        config.strict_knowledge_types,
+    );
+    match results {
+        Ok(results) if !results.notes.is_empty() => {
+            info!("🔍 Search demo found {} note(s) matching 'sql':", results.notes.len());
+            for note in &results.notes {
+                info!("  - [{}] {}", note.knowledge_type, note.title);
+            }
         }
+        Ok(_) => {}
+        Err(e) => tracing::debug!("Demo search failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty scratch folder standing in for the executable's directory
+    fn app_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("quicknote-cli-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_custom_named_data_folder() {
+        let dir = app_dir("custom");
+        std::fs::create_dir(dir.join("quicknote-data")).unwrap();
+
+        assert_eq!(find_data_dir(&dir, "quicknote-data").unwrap(), dir.join("quicknote-data"));
+        assert!(find_data_dir(&dir, "data").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unsafe_folder_name_falls_back_to_default() {
+        let dir = app_dir("fallback");
+        std::fs::create_dir(dir.join(config::DEFAULT_PORTABLE_DIR)).unwrap();
+
+        for name in ["", "  ", "..", "../elsewhere", "a\\b"] {
+            assert_eq!(find_data_dir(&dir, name).unwrap(), dir.join(config::DEFAULT_PORTABLE_DIR), "{:?}", name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_vault_starts_without_demo_note() {
+        let dir = app_dir("empty-vault");
+        let config = Config::default();
+
+        let conn = open_vault(&dir, &config, false);
+        assert_eq!(db::note_count(&conn).unwrap(), 0);
+        demo_search(&conn, &config);
+        drop(conn);
+
+        // Reopening the same vault, now with --demo, seeds it once
+        let conn = open_vault(&dir, &config, true);
+        assert_eq!(db::note_count(&conn).unwrap(), 1);
+        demo_search(&conn, &config);
+        drop(conn);
+        let conn = open_vault(&dir, &config, true);
+        assert_eq!(db::note_count(&conn).unwrap(), 1);
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn explicit_tags_come_first_without_duplicating_inline_ones() {
+        let conn = db::test_vault();
+        let explicit = ["#Rust".to_string(), "db//sqlite".to_string(), "rust".to_string(), "🙂".to_string()];
+        let id = add_note(&conn, "Pooling".to_string(), "#rust pool tuning #perf".to_string(), &explicit, &Config::default())
+            .unwrap();
+
+        let stored: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert_eq!(stored, r#"["rust","db/sqlite","perf"]"#);
+    }
+
+    #[test]
+    fn unique_titles_rejects_a_taken_title() {
+        let conn = db::test_vault();
+        let config = Config { unique_titles: true, ..Config::default() };
+        add_note(&conn, "Deploy".to_string(), "steps".to_string(), &[], &config).unwrap();
+
+        assert!(add_note(&conn, "deploy".to_string(), "again".to_string(), &[], &config).is_err());
+        assert_eq!(db::note_count(&conn).unwrap(), 1);
+        add_note(&conn, "deploy".to_string(), "again".to_string(), &[], &Config::default()).unwrap();
+        assert_eq!(db::note_count(&conn).unwrap(), 2);
     }
 }
@@ -0,0 +1,2803 @@
+//! Note operations shared by the CLI and the Tauri backend
+
+use crate::categorize::{detect_language, detect_sql_dialect, KnowledgeType};
+#[cfg(feature = "gui")]
+use crate::categorize::{
+    bullet_item, categorize_note, categorize_with_confidence, normalize_language, numbered_item, KeywordPack,
+    SqlDialect,
+};
+#[cfg(feature = "gui")]
+use crate::config::TagCase;
+#[cfg(feature = "gui")]
+use crate::dates::format_epoch;
+use crate::error::QuickNoteError;
+use crate::tags;
+#[cfg(feature = "gui")]
+use crate::{db, links};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Note {
+    pub id: u64,
+    pub title: String,
+    pub content: String,
+    pub knowledge_type: KnowledgeType,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Secret note: content is ciphertext and must not be rendered
+    pub encrypted: bool,
+    /// Where the note came from (book, URL, conversation)
+    pub source: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+    /// Listed first in every search it matches (see `set_always_top`)
+    #[serde(default)]
+    pub always_top: bool,
+    /// 0 (none) to `MAX_PRIORITY` (high)
+    #[serde(default)]
+    pub priority: u8,
+}
+
+/// Lightweight note listing entry (no content)
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct NoteSummary {
+    pub id: u64,
+    pub title: String,
+    pub knowledge_type: Option<String>,
+    pub tags: Vec<String>,
+    pub encrypted: bool,
+}
+
+/// Run a `SELECT id, title, knowledge_type, tags, encrypted` statement as summaries
+#[cfg(feature = "gui")]
+pub fn summaries(stmt: &mut rusqlite::Statement, params: impl rusqlite::Params) -> Result<Vec<NoteSummary>, QuickNoteError> {
+    let mut rows = stmt.query(params)?;
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next()? {
+        notes.push(NoteSummary {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            knowledge_type: row.get(2)?,
+            tags: tags::stored_tags(row.get(3)?),
+            encrypted: row.get(4)?,
+        });
+    }
+    Ok(notes)
+}
+
+/// Reject content larger than `limit` UTF-8 bytes
+pub fn check_note_size(content: &str, limit: usize) -> Result<(), QuickNoteError> {
+    let size = content.len();
+    if size > limit {
+        return Err(QuickNoteError::NoteTooLarge { size, limit });
+    }
+    Ok(())
+}
+
+/// Replace a note's title and content. A `source` of `None` keeps the current one;
+/// an empty string clears it. The note is re-categorized as on add (see `recategorize_edited`).
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn update_note(
+    conn: &Connection,
+    id: u64,
+    title: &str,
+    content: &str,
+    source: Option<&str>,
+    max_bytes: usize,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<(), QuickNoteError> {
+    check_note_size(content, max_bytes)?;
+    ensure_unlocked(conn, id)?;
+
+    let changed = conn.execute(
+        "UPDATE notes
+         SET title = ?1, content = ?2,
+             source = CASE WHEN ?3 IS NULL THEN source ELSE NULLIF(trim(?3), '') END,
+             updated_at = strftime('%s', 'now')
+         WHERE id = ?4",
+        rusqlite::params![title, content, source, id],
+    )?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    recategorize_edited(conn, id, tag_case, default_type, skip_code_tags, packs)?;
+    links::sync_links(conn, id)?;
+    refresh_metadata(conn, id)?;
+    Ok(())
+}
+
+/// Re-run the categorizer after a content edit, as adding the note does: a new type unless
+/// it's locked, and the content's `#tags` merged after the tags already on the note.
+/// Encrypted notes are left alone, since their content is ciphertext.
+#[cfg(feature = "gui")]
+fn recategorize_edited(
+    conn: &Connection,
+    id: u64,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<(), QuickNoteError> {
+    let (title, content, stored, encrypted): (String, String, Option<String>, bool) = conn.query_row(
+        "SELECT title, content, tags, encrypted FROM notes WHERE id = ?1",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    if encrypted {
+        return Ok(());
+    }
+
+    let (knowledge_type, extracted) = categorize_note(&content, &title, tag_case, default_type, skip_code_tags, packs);
+    let existing = tags::stored_tags(stored);
+    let tags = tags::merge_tags(&existing, extracted, tag_case);
+    conn.execute(
+        "UPDATE notes
+         SET knowledge_type = CASE WHEN type_locked THEN knowledge_type ELSE ?1 END, tags = ?2
+         WHERE id = ?3",
+        rusqlite::params![knowledge_type.as_db_str(), serde_json::to_string(&tags)?, id],
+    )?;
+    Ok(())
+}
+
+/// Editor changes to apply together with `apply_edits`; absent fields are left alone
+#[cfg(feature = "gui")]
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EditPatch {
+    pub title: Option<String>,
+    pub content: Option<String>,
+    /// Replaces the tag list (normalized like the composer's tag field)
+    pub tags: Option<Vec<String>>,
+    /// Also locks the type against re-categorization, as `reclassify` does
+    pub knowledge_type: Option<String>,
+    /// Empty string clears it
+    pub source: Option<String>,
+}
+
+/// Apply every field of `patch` to a note in one transaction: all of them or, if any is
+/// invalid or a write fails, none. Links and derived metadata are refreshed once, and a
+/// content change re-categorizes the note as `update_note` does.
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn apply_edits(
+    conn: &mut Connection,
+    id: u64,
+    patch: &EditPatch,
+    max_bytes: usize,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<(), QuickNoteError> {
+    if let Some(content) = &patch.content {
+        check_note_size(content, max_bytes)?;
+    }
+    let knowledge_type = match &patch.knowledge_type {
+        Some(kind) => Some(
+            KnowledgeType::from_db_str(kind)
+                .ok_or_else(|| QuickNoteError::InvalidInput(format!("Unknown knowledge type '{}'", kind)))?,
+        ),
+        None => None,
+    };
+    let tags = match &patch.tags {
+        Some(explicit) => Some(serde_json::to_string(&tags::merge_tags(explicit, Vec::new(), tag_case))?),
+        None => None,
+    };
+
+    let tx = conn.transaction()?;
+    ensure_unlocked(&tx, id)?;
+    tx.execute(
+        "UPDATE notes
+         SET title = COALESCE(?1, title),
+             content = COALESCE(?2, content),
+             tags = COALESCE(?3, tags),
+             knowledge_type = COALESCE(?4, knowledge_type),
+             type_locked = CASE WHEN ?4 IS NULL THEN type_locked ELSE 1 END,
+             source = CASE WHEN ?5 IS NULL THEN source ELSE NULLIF(trim(?5), '') END,
+             updated_at = strftime('%s', 'now')
+         WHERE id = ?6",
+        rusqlite::params![patch.title, patch.content, tags, knowledge_type.map(|k| k.as_db_str()), patch.source, id],
+    )?;
+    if patch.content.is_some() {
+        recategorize_edited(&tx, id, tag_case, default_type, skip_code_tags, packs)?;
+    }
+    links::sync_links(&tx, id)?;
+    refresh_metadata(&tx, id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Tidy a note's whitespace in place (see `tidy_content`). Returns whether anything
+/// changed; `updated_at` only moves when it did.
+#[cfg(feature = "gui")]
+pub fn tidy_note(conn: &Connection, id: u64) -> Result<bool, QuickNoteError> {
+    ensure_unlocked(conn, id)?;
+    let (content, encrypted): (String, bool) =
+        conn.query_row("SELECT content, encrypted FROM notes WHERE id = ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    if encrypted {
+        return Err(QuickNoteError::InvalidInput("Encrypted notes can't be tidied".to_string()));
+    }
+
+    let tidied = tidy_content(&content);
+    if tidied == content {
+        return Ok(false);
+    }
+    // FTS follows via the notes_au trigger
+    conn.execute(
+        "UPDATE notes SET content = ?1, updated_at = strftime('%s', 'now') WHERE id = ?2",
+        rusqlite::params![tidied, id],
+    )?;
+    Ok(true)
+}
+
+/// Turn a note into a `Checklist`: bulleted and numbered lines become `- [ ]` tasks,
+/// while existing tasks, prose and code fences are left alone. The type is locked like
+/// `reclassify` does. Returns the number of tasks created.
+#[cfg(feature = "gui")]
+pub fn convert_to_checklist(conn: &Connection, id: u64) -> Result<usize, QuickNoteError> {
+    ensure_unlocked(conn, id)?;
+    let (content, encrypted): (String, bool) =
+        conn.query_row("SELECT content, encrypted FROM notes WHERE id = ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    if encrypted {
+        return Err(QuickNoteError::InvalidInput("Encrypted notes can't be converted".to_string()));
+    }
+
+    let mut created = 0;
+    let mut in_fence = false;
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let item = bullet_item(trimmed).or_else(|| numbered_item(trimmed));
+        match item {
+            Some(item) if !in_fence && !is_task(item) => {
+                lines.push(format!("{}- [ ] {}", &line[..line.len() - trimmed.len()], item));
+                created += 1;
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    let mut converted = lines.join("\n");
+    if content.ends_with('\n') {
+        converted.push('\n');
+    }
+
+    // FTS follows via the notes_au trigger
+    conn.execute(
+        "UPDATE notes SET content = ?1, knowledge_type = ?2, type_locked = 1, updated_at = strftime('%s', 'now')
+         WHERE id = ?3",
+        rusqlite::params![converted, KnowledgeType::Checklist.as_db_str(), id],
+    )?;
+    Ok(created)
+}
+
+#[cfg(feature = "gui")]
+fn is_task(item: &str) -> bool {
+    ["[ ]", "[x]", "[X]"].iter().any(|checkbox| item.starts_with(checkbox))
+}
+
+/// Normalize line endings to `\n`, trim trailing whitespace and collapse runs of three or
+/// more blank lines into one. Lines inside ``` / ~~~ fences keep their whitespace.
+#[cfg(feature = "gui")]
+pub fn tidy_content(content: &str) -> String {
+    let content = content.replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut lines: Vec<&str> = Vec::new();
+    let mut blank_run = 0;
+    let mut in_fence = false;
+    for line in content.split('\n') {
+        let is_fence = line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~");
+        if in_fence && !is_fence {
+            lines.push(line);
+            continue;
+        }
+        if is_fence {
+            in_fence = !in_fence;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_run += 1;
+            continue;
+        }
+        let keep = if blank_run >= 3 { 1 } else { blank_run };
+        lines.extend(std::iter::repeat_n("", keep));
+        blank_run = 0;
+        lines.push(line);
+    }
+    // Trailing blank lines follow the same rule, so a final newline survives
+    let keep = if blank_run >= 3 { 1 } else { blank_run };
+    lines.extend(std::iter::repeat_n("", keep));
+
+    lines.join("\n")
+}
+
+/// Lock or unlock a note against edits and deletion. Returns the new state.
+#[cfg(feature = "gui")]
+pub fn toggle_lock(conn: &Connection, id: u64) -> Result<bool, QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET is_locked = NOT is_locked WHERE id = ?1", [id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(conn.query_row("SELECT is_locked FROM notes WHERE id = ?1", [id], |row| row.get(0))?)
+}
+
+/// The note titled `title`, ignoring case (the same matching `[[wiki-links]]` use). When
+/// several notes share the title, the most recently updated wins. Encrypted notes come
+/// back with empty content.
+pub fn find_by_title(conn: &Connection, title: &str) -> Result<Option<Note>, QuickNoteError> {
+    let row = conn
+        .query_row(
+            "SELECT id, title, CASE WHEN encrypted THEN '' ELSE content END, knowledge_type, tags,
+                    created_at, updated_at, encrypted, source, is_archived, always_top, priority
+             FROM notes WHERE title = ?1 COLLATE NOCASE
+             ORDER BY updated_at_ms DESC, id DESC LIMIT 1",
+            [title],
+            |row| {
+                Ok((
+                    Note {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        content: row.get(2)?,
+                        knowledge_type: row
+                            .get::<_, Option<String>>(3)?
+                            .as_deref()
+                            .and_then(KnowledgeType::from_db_str)
+                            .unwrap_or(KnowledgeType::Concept),
+                        tags: Vec::new(),
+                        created_at: row.get(5)?,
+                        updated_at: row.get(6)?,
+                        encrypted: row.get(7)?,
+                        source: row.get(8)?,
+                        archived: row.get(9)?,
+                        always_top: row.get(10)?,
+                        priority: row.get(11)?,
+                    },
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    match row {
+        Some((mut note, stored)) => {
+            note.tags = tags::stored_tags(stored);
+            Ok(Some(note))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Titles shared by more than one note, ignoring case as `find_by_title` does, so
+/// collisions can be merged or renamed before turning on `unique_titles`. Largest groups
+/// first; each lists its notes, archived ones included, oldest first.
+#[cfg(feature = "gui")]
+pub fn duplicate_titles(conn: &Connection) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, updated_at, is_archived, members FROM (
+            SELECT *, COUNT(*) OVER (PARTITION BY title COLLATE NOCASE) AS members FROM notes
+         )
+         WHERE members > 1
+         ORDER BY members DESC, title COLLATE NOCASE, id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut groups: Vec<(String, Vec<serde_json::Value>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let title: String = row.get(1)?;
+        let note = serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": title,
+            "updated_at": row.get::<_, i64>(2)?,
+            "archived": row.get::<_, bool>(3)?,
+        });
+        match groups.last_mut() {
+            Some((key, notes)) if key.eq_ignore_ascii_case(&title) => notes.push(note),
+            _ => groups.push((title, vec![note])),
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(title, notes)| serde_json::json!({ "title": title, "count": notes.len(), "notes": notes }))
+        .collect())
+}
+
+/// `DuplicateTitle` if another note already has `title` (see `find_by_title`); used on
+/// insert when `unique_titles` is on
+pub fn ensure_title_available(conn: &Connection, title: &str) -> Result<(), QuickNoteError> {
+    match find_by_title(conn, title)? {
+        Some(note) => Err(QuickNoteError::DuplicateTitle(note.id)),
+        None => Ok(()),
+    }
+}
+
+/// `NotFound` for a missing note, `Locked` for a locked one
+#[cfg(feature = "gui")]
+fn ensure_unlocked(conn: &Connection, id: u64) -> Result<(), QuickNoteError> {
+    let locked: bool = conn
+        .query_row("SELECT is_locked FROM notes WHERE id = ?1", [id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => QuickNoteError::NotFound(id),
+            e => e.into(),
+        })?;
+
+    if locked {
+        return Err(QuickNoteError::Locked(id));
+    }
+    Ok(())
+}
+
+/// Append a line to a note's content, e.g. for standup notes or debugging journals.
+/// `#tags` in the appended text are picked up as in `update_note`.
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn append_note(
+    conn: &Connection,
+    id: u64,
+    text: &str,
+    timestamp: bool,
+    max_bytes: usize,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<(), QuickNoteError> {
+    let line = if timestamp {
+        let now: String = conn.query_row("SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')", [], |row| row.get(0))?;
+        format!("[{}] {}", now, text)
+    } else {
+        text.to_string()
+    };
+
+    ensure_unlocked(conn, id)?;
+    let current: usize = conn.query_row("SELECT length(CAST(content AS BLOB)) FROM notes WHERE id = ?1", [id], |row| {
+        row.get(0)
+    })?;
+    let separator = if current == 0 { 0 } else { 1 };
+    let size = current + separator + line.len();
+    if size > max_bytes {
+        return Err(QuickNoteError::NoteTooLarge { size, limit: max_bytes });
+    }
+
+    // Single UPDATE so the append is atomic; the notes_au trigger re-syncs FTS
+    let changed = conn.execute(
+        "UPDATE notes
+         SET content = CASE WHEN content = '' THEN ?1 ELSE content || char(10) || ?1 END,
+             updated_at = strftime('%s', 'now')
+         WHERE id = ?2",
+        rusqlite::params![line, id],
+    )?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    recategorize_edited(conn, id, tag_case, default_type, skip_code_tags, packs)?;
+    links::sync_links(conn, id)?;
+    refresh_metadata(conn, id)?;
+    Ok(())
+}
+
+/// Re-run the categorizer over existing notes, skipping any whose type the user locked.
+/// Returns how many notes changed type.
+#[cfg(feature = "gui")]
+pub fn recategorize_all(
+    conn: &mut Connection,
+    only_default: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<usize, QuickNoteError> {
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+
+    {
+        // With `only_default`, just the notes that fell through to the fallback type
+        let mut stmt = tx.prepare(
+            "SELECT id, title, content, knowledge_type, tags FROM notes
+             WHERE type_locked = 0 AND encrypted = 0 AND (NOT ?1 OR knowledge_type IS NULL OR knowledge_type = ?2)",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![only_default, default_type.as_db_str()], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (id, title, content, old_type, old_tags) = row?;
+            let (knowledge_type, extracted) = categorize_note(&content, &title, tag_case, default_type, skip_code_tags, packs);
+            // Keep tags the user added by hand; an unreadable cell just contributes none
+            let existing = tags::stored_tags(old_tags);
+            let tags = tags::merge_tags(&existing, extracted, tag_case);
+
+            if old_type.as_deref() != Some(knowledge_type.as_db_str()) {
+                changed += 1;
+            }
+
+            tx.execute(
+                "UPDATE notes SET knowledge_type = ?1, tags = ?2 WHERE id = ?3",
+                rusqlite::params![knowledge_type.as_db_str(), serde_json::to_string(&tags)?, id],
+            )?;
+            refresh_metadata(&tx, id)?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Re-run the categorizer on one note's stored content. Found tags are merged into the
+/// existing ones; the type only changes when it isn't locked. Returns the resulting
+/// classification.
+#[cfg(feature = "gui")]
+pub fn recategorize_note(
+    conn: &Connection,
+    id: u64,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<serde_json::Value, QuickNoteError> {
+    let (title, content, old_type, stored, type_locked, encrypted): (String, String, Option<String>, Option<String>, bool, bool) = conn
+        .query_row(
+            "SELECT title, content, knowledge_type, tags, type_locked, encrypted FROM notes WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => QuickNoteError::NotFound(id),
+            e => e.into(),
+        })?;
+    if encrypted {
+        return Err(QuickNoteError::InvalidInput("Encrypted notes can't be recategorized".to_string()));
+    }
+
+    let categorization = categorize_with_confidence(&content, &title, tag_case, default_type, skip_code_tags, packs);
+    let knowledge_type = if type_locked {
+        old_type.unwrap_or_else(|| default_type.as_db_str().to_string())
+    } else {
+        categorization.knowledge_type.as_db_str().to_string()
+    };
+    let existing = tags::stored_tags(stored);
+    let tags = tags::merge_tags(&existing, categorization.tags, tag_case);
+
+    conn.execute(
+        "UPDATE notes SET knowledge_type = ?1, tags = ?2 WHERE id = ?3",
+        rusqlite::params![knowledge_type, serde_json::to_string(&tags)?, id],
+    )?;
+    refresh_metadata(conn, id)?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "knowledge_type": knowledge_type,
+        "tags": tags,
+        "confidence": categorization.confidence,
+        "type_locked": type_locked,
+    }))
+}
+
+/// Note entry for list views: metadata plus a short content preview
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct NoteListItem {
+    pub id: u64,
+    pub title: String,
+    pub knowledge_type: Option<String>,
+    pub tags: Vec<String>,
+    pub preview: String,
+    /// UTC epoch seconds, for sorting
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// `created_at`/`updated_at` formatted for display (see `dates::format_epoch`)
+    pub created: String,
+    pub updated: String,
+    pub encrypted: bool,
+    pub archived: bool,
+    pub priority: u8,
+}
+
+/// Highest note priority; levels are 0 none, 1 low, 2 medium, 3 high
+pub const MAX_PRIORITY: u8 = 3;
+
+/// All notes, newest first or, with `by_priority`, highest priority first (newest first
+/// within a level). `min_priority` keeps only notes at that level or above; archived
+/// notes only with `include_archived`.
+#[cfg(feature = "gui")]
+pub fn list_notes(
+    conn: &Connection,
+    preview_chars: usize,
+    date_format: Option<&str>,
+    include_archived: bool,
+    min_priority: Option<u8>,
+    by_priority: bool,
+) -> Result<Vec<NoteListItem>, QuickNoteError> {
+    let mut clause = String::new();
+    if let Some(level) = min_priority {
+        clause.push_str(&format!("AND priority >= {} ", check_priority(level.into())?));
+    }
+    clause.push_str(if by_priority { "ORDER BY priority DESC, id DESC" } else { "ORDER BY id DESC" });
+    query_list(conn, &clause, -1, preview_chars, date_format, include_archived)
+}
+
+/// Active high-priority notes, most recently updated first
+#[cfg(feature = "gui")]
+pub fn high_priority_notes(conn: &Connection, preview_chars: usize, date_format: Option<&str>) -> Result<Vec<NoteListItem>, QuickNoteError> {
+    let clause = format!("AND priority = {} ORDER BY updated_at_ms DESC, id DESC", MAX_PRIORITY);
+    query_list(conn, &clause, -1, preview_chars, date_format, false)
+}
+
+/// Set a note's priority, 0 (none) to `MAX_PRIORITY` (high)
+#[cfg(feature = "gui")]
+pub fn set_priority(conn: &Connection, id: u64, level: i64) -> Result<(), QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET priority = ?1 WHERE id = ?2", rusqlite::params![check_priority(level)?, id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gui")]
+fn check_priority(level: i64) -> Result<u8, QuickNoteError> {
+    u8::try_from(level).ok().filter(|level| *level <= MAX_PRIORITY).ok_or(QuickNoteError::InvalidPriority(level))
+}
+
+/// Unprocessed captures waiting for triage, oldest first (archived ones are left out)
+#[cfg(feature = "gui")]
+pub fn inbox(conn: &Connection, preview_chars: usize, date_format: Option<&str>) -> Result<Vec<NoteListItem>, QuickNoteError> {
+    query_list(conn, "AND is_processed = 0 ORDER BY created_at_ms, id", -1, preview_chars, date_format, false)
+}
+
+/// Flag a note to be listed ahead of ranked results in every search it matches
+#[cfg(feature = "gui")]
+pub fn set_always_top(conn: &Connection, id: u64, always_top: bool) -> Result<(), QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET always_top = ?1 WHERE id = ?2", rusqlite::params![always_top, id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Set a note's reminder to `at` (UTC epoch seconds), or clear it with `None`
+#[cfg(feature = "gui")]
+pub fn set_reminder(conn: &Connection, id: u64, at: Option<i64>) -> Result<(), QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET remind_at = ?1 WHERE id = ?2", rusqlite::params![at, id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Active notes whose reminder time has passed, most overdue first. A reminder stays
+/// due until it's cleared or moved with `set_reminder`.
+#[cfg(feature = "gui")]
+pub fn due_reminders(conn: &Connection) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, remind_at FROM notes
+         WHERE remind_at <= strftime('%s', 'now') AND is_archived = 0
+         ORDER BY remind_at, id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+            "remind_at": row.get::<_, i64>(3)?,
+        }))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Take notes out of the inbox. Returns how many were still unprocessed.
+#[cfg(feature = "gui")]
+pub fn mark_processed(conn: &mut Connection, ids: &[u64]) -> Result<usize, QuickNoteError> {
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    for id in ids {
+        changed += tx.execute("UPDATE notes SET is_processed = 1 WHERE id = ?1 AND is_processed = 0", [id])?;
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Most recently updated notes; archived notes only with `include_archived`
+#[cfg(feature = "gui")]
+pub fn recent_notes(
+    conn: &Connection,
+    limit: usize,
+    preview_chars: usize,
+    date_format: Option<&str>,
+    include_archived: bool,
+) -> Result<Vec<NoteListItem>, QuickNoteError> {
+    query_list(conn, "ORDER BY updated_at_ms DESC, id DESC", limit as i64, preview_chars, date_format, include_archived)
+}
+
+/// `clause` is appended after the archive filter: extra `AND` conditions, then `ORDER BY`
+#[cfg(feature = "gui")]
+fn query_list(
+    conn: &Connection,
+    clause: &str,
+    limit: i64,
+    preview_chars: usize,
+    date_format: Option<&str>,
+    include_archived: bool,
+) -> Result<Vec<NoteListItem>, QuickNoteError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, title, knowledge_type, tags, CASE WHEN encrypted THEN '' ELSE content END,
+                created_at, updated_at, encrypted, is_archived, priority
+         FROM notes WHERE (?2 OR is_archived = 0) {} LIMIT ?1",
+        clause
+    ))?;
+    let mut rows = stmt.query(rusqlite::params![limit, include_archived])?;
+
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let created_at: i64 = row.get(5)?;
+        let updated_at: i64 = row.get(6)?;
+        notes.push(NoteListItem {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            knowledge_type: row.get(2)?,
+            tags: tags::stored_tags(row.get(3)?),
+            // 0 disables previews (lean queries for big vaults)
+            preview: match preview_chars {
+                0 => String::new(),
+                _ => make_preview(&row.get::<_, String>(4)?, preview_chars),
+            },
+            created_at,
+            updated_at,
+            created: format_epoch(created_at, date_format),
+            updated: format_epoch(updated_at, date_format),
+            encrypted: row.get(7)?,
+            archived: row.get(8)?,
+            priority: row.get(9)?,
+        });
+    }
+    Ok(notes)
+}
+
+/// Archive or unarchive a note. Archived notes are hidden from default listings and
+/// search, and skipped by review, but are otherwise untouched.
+#[cfg(feature = "gui")]
+pub fn set_archived(conn: &Connection, id: u64, archived: bool) -> Result<(), QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET is_archived = ?1 WHERE id = ?2", rusqlite::params![archived, id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Bump `updated_at` to now without changing the note, moving it to the top of the
+/// recent list ("still relevant"). Not an edit, so locked notes can be touched too.
+#[cfg(feature = "gui")]
+pub fn touch_note(conn: &Connection, id: u64) -> Result<(), QuickNoteError> {
+    let changed = conn.execute("UPDATE notes SET updated_at = strftime('%s', 'now') WHERE id = ?1", [id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Count an open of a note (the editor showing it). Not an edit: `updated_at` is left
+/// alone and locked notes count too.
+#[cfg(feature = "gui")]
+pub fn record_open(conn: &Connection, id: u64) -> Result<(), QuickNoteError> {
+    let changed = conn.execute(
+        "UPDATE notes SET open_count = open_count + 1, last_opened_at = strftime('%s', 'now') WHERE id = ?1",
+        [id],
+    )?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Archiving candidates: active notes not updated for `older_than_days` and opened at
+/// most `max_opens` times, stalest first (fewest opens, then least recently updated).
+/// Notes opened within the window are still in use and left out, however old they are,
+/// as are locked and always-on-top notes, which are kept on purpose.
+#[cfg(feature = "gui")]
+pub fn stale_notes(conn: &Connection, older_than_days: u32, max_opens: u32) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, updated_at, open_count, last_opened_at,
+                (strftime('%s', 'now') - updated_at) / 86400
+         FROM notes
+         WHERE is_archived = 0 AND is_locked = 0 AND always_top = 0
+           AND updated_at <= strftime('%s', 'now') - ?1 * 86400
+           AND open_count <= ?2
+           AND (last_opened_at IS NULL OR last_opened_at <= strftime('%s', 'now') - ?1 * 86400)
+         ORDER BY open_count, updated_at_ms, id",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![older_than_days, max_opens], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+            "updated_at": row.get::<_, i64>(3)?,
+            "open_count": row.get::<_, u32>(4)?,
+            "last_opened_at": row.get::<_, Option<i64>>(5)?,
+            "age_days": row.get::<_, i64>(6)?,
+        }))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Plain-text preview of Markdown content, cut at a word boundary near `max_chars`.
+/// Counts and slices by `char`, so multibyte text is never split mid-character.
+#[cfg(feature = "gui")]
+pub fn make_preview(content: &str, max_chars: usize) -> String {
+    let mut words = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        // Drop fence markers (```rust) but keep the code itself
+        if line.starts_with("```") || line.starts_with("~~~") {
+            continue;
+        }
+        let line = line.trim_start_matches(['#', '>']).trim_start();
+        let line = line
+            .strip_prefix("- ")
+            .or_else(|| line.strip_prefix("* "))
+            .unwrap_or(line);
+        words.extend(line.split_whitespace().map(|w| w.replace(['*', '`'], "")));
+    }
+    let text = words.into_iter().filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+
+    let cut: String = text.chars().take(max_chars).collect();
+    // Back off to the last space unless that would throw away most of the preview
+    let cut = match cut.rfind(' ') {
+        Some(pos) if cut[..pos].chars().count() >= max_chars / 2 => &cut[..pos],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// Bulk-change the type of many notes and lock it so `recategorize_all` won't revert it.
+/// Returns how many notes actually changed type.
+#[cfg(feature = "gui")]
+pub fn reclassify(conn: &mut Connection, ids: &[u64], to_type: &str) -> Result<usize, QuickNoteError> {
+    let knowledge_type = KnowledgeType::from_db_str(to_type).ok_or_else(|| {
+        QuickNoteError::InvalidInput(format!(
+            "Unknown knowledge type '{}' (expected one of Concept, Snippet, Checklist, Note, Process, SQLQuery, DebugPattern)",
+            to_type
+        ))
+    })?;
+
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    for id in ids {
+        changed += tx.execute(
+            "UPDATE notes SET knowledge_type = ?1 WHERE id = ?2 AND knowledge_type IS NOT ?1",
+            rusqlite::params![knowledge_type.as_db_str(), id],
+        )?;
+        tx.execute("UPDATE notes SET type_locked = 1 WHERE id = ?1", [id])?;
+        refresh_metadata(&tx, *id)?;
+    }
+    tx.commit()?;
+
+    Ok(changed)
+}
+
+/// Copy a note as a starting point for a new one. The copy gets fresh timestamps, its own
+/// FTS row and a reset review schedule. Returns the new id.
+#[cfg(feature = "gui")]
+pub fn duplicate_note(conn: &Connection, id: u64) -> Result<u64, QuickNoteError> {
+    let changed = conn.execute(
+        "INSERT INTO notes (title, content, knowledge_type, tags, type_locked, encrypted, source, metadata, review_due)
+         SELECT title || ' (copy)', content, knowledge_type, tags, type_locked, encrypted, source, metadata,
+                CASE WHEN review_due IS NULL THEN NULL ELSE strftime('%s', 'now') END
+         FROM notes WHERE id = ?1",
+        [id],
+    )?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    let new_id = conn.last_insert_rowid() as u64;
+    links::sync_links(conn, new_id)?;
+    Ok(new_id)
+}
+
+/// Notes created on the month/day of `now` (epoch seconds) in any year, newest year
+/// first. Dates are UTC, like every other date the vault stores or compares.
+#[cfg(feature = "gui")]
+pub fn on_this_day(conn: &Connection, now: i64) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, created_at,
+                CAST(strftime('%Y', created_at, 'unixepoch') AS INTEGER) AS year
+         FROM notes
+         WHERE strftime('%m-%d', created_at, 'unixepoch') = strftime('%m-%d', ?1, 'unixepoch') AND is_archived = 0
+         ORDER BY year DESC, created_at_ms DESC, id DESC",
+    )?;
+    let rows = stmt.query_map([now], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+            "created_at": row.get::<_, i64>(3)?,
+            "year": row.get::<_, i64>(4)?,
+        }))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Permanently remove a note; the notes_ad trigger drops its FTS row
+#[cfg(feature = "gui")]
+pub fn delete_note(conn: &Connection, id: u64) -> Result<(), QuickNoteError> {
+    ensure_unlocked(conn, id)?;
+    let changed = conn.execute("DELETE FROM notes WHERE id = ?1", [id])?;
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Columns carried over by `copy_notes`; everything but the id
+#[cfg(feature = "gui")]
+const MOVED_COLUMNS: &str = "title, content, knowledge_type, tags, created_at, updated_at, type_locked, \
+     review_due, review_interval, review_streak, review_easiness, review_suspended, encrypted, source, \
+     review_lapses, is_locked, is_archived, is_processed, remind_at, always_top, metadata, \
+     created_at_ms, updated_at_ms, priority, open_count, last_opened_at";
+
+/// Move notes, with their attachments, review state and review log, into another vault's database.
+/// The copies are committed in `target` before the originals are archived in `source`;
+/// nothing is deleted, so a failure part-way leaves each note in at least one vault (at
+/// worst in both), never in neither, and a move can be undone from the archive. Locked
+/// notes are refused up front. Returns `(old_id, new_id)` pairs.
+#[cfg(feature = "gui")]
+pub fn move_notes(source: &mut Connection, target: &mut Connection, ids: &[u64]) -> Result<Vec<(u64, u64)>, QuickNoteError> {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+    for &id in &ids {
+        ensure_unlocked(source, id)?;
+    }
+
+    let moved = copy_notes(source, target, &ids)?;
+
+    // Only now is it safe to retire the originals
+    let tx = source.transaction()?;
+    for &id in &ids {
+        set_archived(&tx, id, true)?;
+    }
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// Copy notes, with their attachments, review state and review log, into another vault's
+/// database in one transaction. Returns `(old_id, new_id)` pairs.
+#[cfg(feature = "gui")]
+pub fn copy_notes(source: &Connection, target: &mut Connection, ids: &[u64]) -> Result<Vec<(u64, u64)>, QuickNoteError> {
+    let tx = target.transaction()?;
+    let mut copied = Vec::new();
+    {
+        let mut read_note = source.prepare(&format!("SELECT {} FROM notes WHERE id = ?1", MOVED_COLUMNS))?;
+        let mut read_attachments =
+            source.prepare("SELECT filename, mime, data, size, created_at FROM attachments WHERE note_id = ?1 ORDER BY id")?;
+        let columns = MOVED_COLUMNS.split(',').count();
+        let placeholders = (1..=columns).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+        let mut insert_note = tx.prepare(&format!("INSERT INTO notes ({}) VALUES ({})", MOVED_COLUMNS, placeholders))?;
+        let mut insert_attachment = tx.prepare(
+            "INSERT INTO attachments (note_id, filename, mime, data, size, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut read_log = source.prepare("SELECT reviewed_at, rating, easiness, interval FROM review_log WHERE note_id = ?1 ORDER BY id")?;
+        let mut insert_log =
+            tx.prepare("INSERT INTO review_log (note_id, reviewed_at, rating, easiness, interval) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+
+        for &id in ids {
+            let values: Vec<rusqlite::types::Value> = read_note.query_row([id], |row| {
+                (0..columns).map(|i| row.get(i)).collect()
+            })?;
+            insert_note.execute(rusqlite::params_from_iter(values))?;
+            let new_id = tx.last_insert_rowid() as u64;
+
+            let mut attachments = read_attachments.query([id])?;
+            while let Some(row) = attachments.next()? {
+                let values = (0..5).map(|i| row.get::<_, rusqlite::types::Value>(i)).collect::<Result<Vec<_>, _>>()?;
+                insert_attachment.execute(rusqlite::params_from_iter(
+                    std::iter::once(rusqlite::types::Value::Integer(new_id as i64)).chain(values),
+                ))?;
+            }
+            let mut log = read_log.query([id])?;
+            while let Some(row) = log.next()? {
+                let values = (0..4).map(|i| row.get::<_, rusqlite::types::Value>(i)).collect::<Result<Vec<_>, _>>()?;
+                insert_log.execute(rusqlite::params_from_iter(
+                    std::iter::once(rusqlite::types::Value::Integer(new_id as i64)).chain(values),
+                ))?;
+            }
+            links::sync_links(&tx, new_id)?;
+            copied.push((id, new_id));
+        }
+    }
+    tx.commit()?;
+    Ok(copied)
+}
+
+/// Tables holding note ids, as `(table, column)`; `embeddings` only exists with the
+/// `semantic` feature
+#[cfg(feature = "gui")]
+const NOTE_ID_REFS: &[(&str, &str)] = &[
+    ("note_links", "from_id"),
+    ("note_links", "to_id"),
+    ("attachments", "note_id"),
+    ("review_log", "note_id"),
+    ("read_later", "note_id"),
+    ("note_tags", "note_id"),
+    ("embeddings", "note_id"),
+];
+
+/// Renumber notes to a dense `1..=N` in their current order, updating every table that
+/// refers to them and the FTS index, then reset the id sequence. Rows already pointing at
+/// a missing note are dropped first (an unresolved link is just unresolved), since they
+/// would otherwise end up attached to whichever note takes that id. Runs in one
+/// transaction and rolls back if any reference is dangling afterwards. Returns how many
+/// notes got a new id.
+#[cfg(feature = "gui")]
+pub fn compact_ids(conn: &mut Connection) -> Result<usize, QuickNoteError> {
+    let tx = conn.transaction()?;
+    let refs: Vec<(&str, &str)> = NOTE_ID_REFS
+        .iter()
+        .copied()
+        .filter(|(table, _)| {
+            tx.query_row("SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |row| row.get(0))
+                .unwrap_or(false)
+        })
+        .collect();
+    for (table, column) in &refs {
+        let orphaned = format!("{column} IS NOT NULL AND {column} NOT IN (SELECT id FROM notes)");
+        match *column {
+            "to_id" => tx.execute(&format!("UPDATE {table} SET {column} = NULL WHERE {orphaned}"), [])?,
+            _ => tx.execute(&format!("DELETE FROM {table} WHERE {orphaned}"), [])?,
+        };
+    }
+
+    tx.execute_batch(
+        "CREATE TEMP TABLE compact_map (old INTEGER PRIMARY KEY, new INTEGER NOT NULL);
+         INSERT INTO compact_map (old, new) SELECT id, ROW_NUMBER() OVER (ORDER BY id) FROM notes;
+         DELETE FROM compact_map WHERE old = new;",
+    )?;
+    let renumbered: usize = tx.query_row("SELECT COUNT(*) FROM compact_map", [], |row| row.get(0))?;
+
+    // Two passes through negative ids, so no row ever collides with an id (or a
+    // note_links key) that hasn't been moved yet
+    let mut columns = vec![("notes", "id")];
+    columns.extend(refs.iter().copied());
+    for (table, column) in &columns {
+        tx.execute(
+            &format!("UPDATE {table} SET {column} = -(SELECT new FROM compact_map WHERE old = {column}) WHERE {column} IN (SELECT old FROM compact_map)"),
+            [],
+        )?;
+    }
+    for (table, column) in &columns {
+        tx.execute(&format!("UPDATE {table} SET {column} = -{column} WHERE {column} < 0"), [])?;
+    }
+    tx.execute("DROP TABLE temp.compact_map", [])?;
+    tx.execute("UPDATE sqlite_sequence SET seq = (SELECT COUNT(*) FROM notes) WHERE name = 'notes'", [])?;
+
+    for (table, column) in &refs {
+        let sql = format!("SELECT COUNT(*) FROM {table} WHERE {column} IS NOT NULL AND {column} NOT IN (SELECT id FROM notes)");
+        let dangling: i64 = tx.query_row(&sql, [], |row| row.get(0))?;
+        if dangling > 0 {
+            return Err(QuickNoteError::IntegrityCheck(format!("{} row(s) in {}.{} point at no note", dangling, table, column)));
+        }
+    }
+    let gaps: i64 = tx.query_row("SELECT COALESCE(MAX(id), 0) - COUNT(*) FROM notes", [], |row| row.get(0))?;
+    if gaps != 0 {
+        return Err(QuickNoteError::IntegrityCheck("note ids are not dense".to_string()));
+    }
+
+    // The FTS index is keyed by note id; rebuild it from the renumbered notes
+    if db::search_backend(&tx)? == db::SearchBackend::Fts5 {
+        db::rebuild_fts(&tx)?;
+        tx.execute("INSERT INTO notes_fts(notes_fts) VALUES ('integrity-check')", [])?;
+    }
+
+    tx.commit()?;
+    Ok(renumbered)
+}
+
+/// Split a note into one note per section, either at `##` headings (`by = "heading"`) or
+/// at `---` rules (`by = "hr"`). Sections inherit the parent's tags and source; heading
+/// sections are titled after their heading. Unless `keep_original`, the parent is archived
+/// (it stays recoverable, out of lists and search).
+/// Returns the new ids, or just `[id]` when the note has nothing to split on.
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn split_note(
+    conn: &mut Connection,
+    id: u64,
+    by: &str,
+    keep_original: bool,
+    tag_case: TagCase,
+    default_type: KnowledgeType,
+    skip_code_tags: bool,
+    packs: &[KeywordPack],
+) -> Result<Vec<u64>, QuickNoteError> {
+    let by_heading = match by {
+        "heading" => true,
+        "hr" => false,
+        _ => {
+            return Err(QuickNoteError::InvalidInput(format!(
+                "Unknown split mode '{}' (expected heading or hr)",
+                by
+            )))
+        }
+    };
+
+    let (title, content, tags, source, encrypted, enrolled): (String, String, Option<String>, Option<String>, bool, bool) = conn
+        .query_row(
+            "SELECT title, content, tags, source, encrypted, review_due IS NOT NULL FROM notes WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => QuickNoteError::NotFound(id),
+            e => e.into(),
+        })?;
+    if encrypted {
+        return Err(QuickNoteError::InvalidInput("Encrypted notes can't be split".to_string()));
+    }
+
+    let sections = split_sections(&content, by_heading);
+    if sections.len() < 2 {
+        return Ok(vec![id]);
+    }
+    let parent_tags = tags::stored_tags(tags);
+
+    let tx = conn.transaction()?;
+    let mut ids = Vec::new();
+    for (n, (heading, body)) in sections.iter().enumerate() {
+        let section_title = match heading {
+            Some(heading) => heading.clone(),
+            None => format!("{} ({})", title, n + 1),
+        };
+        let (knowledge_type, mut section_tags) = categorize_note(body, &section_title, tag_case, default_type, skip_code_tags, packs);
+        for tag in &parent_tags {
+            if !section_tags.contains(tag) {
+                section_tags.push(tag.clone());
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO notes (title, content, knowledge_type, tags, source, review_due)
+             VALUES (?1, ?2, ?3, ?4, ?5, CASE WHEN ?6 THEN strftime('%s', 'now') END)",
+            rusqlite::params![
+                section_title,
+                body,
+                knowledge_type.as_db_str(),
+                serde_json::to_string(&section_tags)?,
+                source,
+                enrolled,
+            ],
+        )?;
+        let new_id = tx.last_insert_rowid() as u64;
+        links::sync_links(&tx, new_id)?;
+        refresh_metadata(&tx, new_id)?;
+        ids.push(new_id);
+    }
+    if !keep_original {
+        set_archived(&tx, id, true)?;
+    }
+    tx.commit()?;
+
+    Ok(ids)
+}
+
+/// Cut Markdown into `(heading, body)` sections, ignoring split markers inside code fences.
+/// Empty sections (e.g. a blank preamble) are dropped.
+#[cfg(feature = "gui")]
+fn split_sections(content: &str, by_heading: bool) -> Vec<(Option<String>, String)> {
+    let mut sections = Vec::new();
+    let mut heading = None;
+    let mut body: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+
+        let marker = !in_fence
+            && if by_heading {
+                trimmed.starts_with("## ") || trimmed == "##"
+            } else {
+                trimmed.len() >= 3 && trimmed.chars().all(|c| c == '-')
+            };
+        if !marker {
+            body.push(line);
+            continue;
+        }
+
+        push_section(&mut sections, heading.take(), &body);
+        body.clear();
+        if by_heading {
+            let text = trimmed.trim_start_matches('#').trim();
+            heading = (!text.is_empty()).then(|| text.to_string());
+        }
+    }
+    push_section(&mut sections, heading, &body);
+
+    sections
+}
+
+#[cfg(feature = "gui")]
+fn push_section(sections: &mut Vec<(Option<String>, String)>, heading: Option<String>, body: &[&str]) {
+    let body = body.join("\n").trim().to_string();
+    if heading.is_some() || !body.is_empty() {
+        sections.push((heading, body));
+    }
+}
+
+/// In-note find: every non-overlapping match of `query` in one note's content, as
+/// `{line, start, end, text}` with a 1-based line number and character (not byte)
+/// offsets into that line
+#[cfg(feature = "gui")]
+pub fn search_in_note(
+    conn: &Connection,
+    id: u64,
+    query: &str,
+    case_sensitive: bool,
+    whole_word: bool,
+) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let (content, encrypted): (String, bool) = conn
+        .query_row("SELECT content, encrypted FROM notes WHERE id = ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => QuickNoteError::NotFound(id),
+            e => e.into(),
+        })?;
+    if encrypted {
+        return Err(QuickNoteError::InvalidInput("Encrypted notes can't be searched".to_string()));
+    }
+
+    let needle: Vec<char> = query.chars().collect();
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Compare char by char so case folding never shifts offsets (some lowercase forms
+    // have a different length than their uppercase)
+    let same = |a: char, b: char| a == b || (!case_sensitive && a.to_lowercase().eq(b.to_lowercase()));
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut matches = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut start = 0;
+        while start + needle.len() <= chars.len() {
+            let end = start + needle.len();
+            let found = chars[start..end].iter().zip(&needle).all(|(a, b)| same(*a, *b))
+                && (!whole_word
+                    || ((start == 0 || !is_word(chars[start - 1])) && (end == chars.len() || !is_word(chars[end]))));
+
+            if found {
+                matches.push(serde_json::json!({
+                    "line": number + 1,
+                    "start": start,
+                    "end": end,
+                    "text": line,
+                }));
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Recompute the facts `metadata` derives from a note's type and content: the SQL
+/// dialect of a `SQLQuery` note and the language of a `Snippet`. Call after any write.
+pub fn refresh_metadata(conn: &Connection, id: u64) -> Result<(), rusqlite::Error> {
+    refresh_sql_dialect(conn, id)?;
+    refresh_language(conn, id)
+}
+
+/// Re-detect a note's SQL dialect into `metadata.sql_dialect` (removing it for other
+/// types), unless the user set the dialect by hand
+fn refresh_sql_dialect(conn: &Connection, id: u64) -> Result<(), rusqlite::Error> {
+    let row: Option<(Option<String>, String)> = conn
+        .query_row(
+            "SELECT knowledge_type, content FROM notes
+             WHERE id = ?1 AND encrypted = 0 AND json_extract(metadata, '$.sql_dialect_manual') IS NOT 1",
+            [id],
+            |row| Ok(Some((row.get(0)?, row.get(1)?))),
+        )
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })?;
+    let Some((knowledge_type, content)) = row else {
+        return Ok(());
+    };
+
+    let dialect = match knowledge_type.as_deref().and_then(KnowledgeType::from_db_str) {
+        Some(KnowledgeType::SQLQuery) => Some(detect_sql_dialect(&content).as_db_str()),
+        _ => None,
+    };
+    conn.execute(
+        "UPDATE notes
+         SET metadata = CASE WHEN ?1 IS NULL THEN json_remove(metadata, '$.sql_dialect')
+                             ELSE json_set(metadata, '$.sql_dialect', ?1) END
+         WHERE id = ?2",
+        rusqlite::params![dialect, id],
+    )?;
+    Ok(())
+}
+
+/// Re-detect a `Snippet`'s language into `metadata.language` (see
+/// `categorize::detect_language`), removing it for other types and when unsure
+fn refresh_language(conn: &Connection, id: u64) -> Result<(), rusqlite::Error> {
+    let row: Option<(Option<String>, String)> = conn
+        .query_row("SELECT knowledge_type, content FROM notes WHERE id = ?1 AND encrypted = 0", [id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+    let Some((knowledge_type, content)) = row else {
+        return Ok(());
+    };
+
+    let language = match knowledge_type.as_deref().and_then(KnowledgeType::from_db_str) {
+        Some(KnowledgeType::Snippet) => detect_language(&content),
+        _ => None,
+    };
+    conn.execute(
+        "UPDATE notes
+         SET metadata = CASE WHEN ?1 IS NULL THEN json_remove(metadata, '$.language')
+                             ELSE json_set(metadata, '$.language', ?1) END
+         WHERE id = ?2",
+        rusqlite::params![language, id],
+    )?;
+    Ok(())
+}
+
+/// Detect the language of every `Snippet`, e.g. for notes saved before detection
+/// existed. Returns how many snippets have a language afterwards.
+#[cfg(feature = "gui")]
+pub fn detect_snippet_languages(conn: &mut Connection) -> Result<usize, QuickNoteError> {
+    let tx = conn.transaction()?;
+    let ids: Vec<u64> = tx
+        .prepare("SELECT id FROM notes WHERE knowledge_type = 'Snippet'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for id in &ids {
+        refresh_language(&tx, *id)?;
+    }
+    let detected = tx.query_row(
+        "SELECT COUNT(*) FROM notes WHERE knowledge_type = 'Snippet' AND json_extract(metadata, '$.language') IS NOT NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+    Ok(detected)
+}
+
+/// Snippets in `language` (a name or fence alias such as `rs`), most recently updated first
+#[cfg(feature = "gui")]
+pub fn notes_by_language(conn: &Connection, language: &str) -> Result<Vec<NoteSummary>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, tags, encrypted FROM notes
+         WHERE json_extract(metadata, '$.language') = ?1 AND is_archived = 0
+         ORDER BY updated_at_ms DESC, id DESC",
+    )?;
+    summaries(&mut stmt, [normalize_language(language)])
+}
+
+/// Override the detected SQL dialect of a note; `None` returns it to auto-detection
+#[cfg(feature = "gui")]
+pub fn set_sql_dialect(conn: &Connection, id: u64, dialect: Option<&str>) -> Result<(), QuickNoteError> {
+    let changed = match dialect {
+        Some(value) => {
+            let dialect = SqlDialect::from_db_str(value).ok_or_else(|| {
+                QuickNoteError::InvalidInput(format!(
+                    "Unknown SQL dialect '{}' (expected postgres, mysql, sqlserver or unknown)",
+                    value
+                ))
+            })?;
+            conn.execute(
+                "UPDATE notes
+                 SET metadata = json_set(metadata, '$.sql_dialect', ?1, '$.sql_dialect_manual', json('true'))
+                 WHERE id = ?2",
+                rusqlite::params![dialect.as_db_str(), id],
+            )?
+        }
+        None => conn.execute(
+            "UPDATE notes SET metadata = json_remove(metadata, '$.sql_dialect_manual') WHERE id = ?1",
+            [id],
+        )?,
+    };
+
+    if changed == 0 {
+        return Err(QuickNoteError::NotFound(id));
+    }
+    refresh_sql_dialect(conn, id)?;
+    Ok(())
+}
+
+/// SQL notes of one dialect (`unknown` lists those no dialect could be detected for)
+#[cfg(feature = "gui")]
+pub fn notes_by_dialect(conn: &Connection, dialect: &str) -> Result<Vec<NoteSummary>, QuickNoteError> {
+    let dialect = SqlDialect::from_db_str(dialect)
+        .ok_or_else(|| QuickNoteError::InvalidInput(format!("Unknown SQL dialect '{}'", dialect)))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, tags, encrypted FROM notes
+         WHERE json_extract(metadata, '$.sql_dialect') = ?1 AND is_archived = 0
+         ORDER BY updated_at_ms DESC, id DESC",
+    )?;
+    summaries(&mut stmt, [dialect.as_db_str()])
+}
+
+/// Notes whose source starts with `prefix` (e.g. a site's base URL or a book title),
+/// most recently updated first
+#[cfg(feature = "gui")]
+pub fn notes_by_source(conn: &Connection, prefix: &str) -> Result<Vec<NoteSummary>, QuickNoteError> {
+    let prefix = prefix.trim();
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // substr() instead of LIKE so `_`/`%` in URLs aren't treated as wildcards
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, tags, encrypted FROM notes
+         WHERE substr(source, 1, length(?1)) = ?1 AND is_archived = 0
+         ORDER BY updated_at_ms DESC, id DESC",
+    )?;
+    summaries(&mut stmt, [prefix])
+}
+
+/// Vault summary for the home screen: note count, notes per type, distinct tags and
+/// how many notes are enrolled in review
+#[cfg(feature = "gui")]
+pub fn vault_stats(conn: &Connection) -> Result<serde_json::Value, QuickNoteError> {
+    let (note_count, enrolled, inbox): (u64, u64, u64) = conn.query_row(
+        "SELECT COUNT(*), COUNT(review_due), COUNT(*) FILTER (WHERE is_processed = 0 AND is_archived = 0) FROM notes",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let tag_count: u64 = conn.query_row(
+        "SELECT COUNT(DISTINCT t.value) FROM notes, json_each(notes.tags) t WHERE json_valid(notes.tags)",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let mut by_type = serde_json::Map::new();
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(knowledge_type, 'Concept'), COUNT(*) FROM notes GROUP BY 1 ORDER BY 1",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        by_type.insert(row.get(0)?, row.get::<_, u64>(1)?.into());
+    }
+
+    Ok(serde_json::json!({
+        "note_count": note_count,
+        "by_type": by_type,
+        "tag_count": tag_count,
+        "review_enrolled": enrolled,
+        "inbox": inbox,
+    }))
+}
+
+/// Active notes with fewer than `min_chars` non-whitespace characters, not counting
+/// `#tags` and heading markers, shortest first. Encrypted notes are skipped since their
+/// stored content says nothing about their length.
+#[cfg(feature = "gui")]
+pub fn stub_notes(conn: &Connection, min_chars: usize) -> Result<Vec<serde_json::Value>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, content FROM notes
+         WHERE is_archived = 0 AND NOT encrypted",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut stubs = Vec::new();
+    while let Some(row) = rows.next()? {
+        let content: String = row.get(3)?;
+        let chars: usize = content
+            .split_whitespace()
+            .filter(|word| !word.starts_with('#'))
+            .map(|word| word.chars().count())
+            .sum();
+        if chars < min_chars {
+            stubs.push((chars, row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?));
+        }
+    }
+
+    stubs.sort_by_key(|(chars, id, _, _)| (*chars, *id));
+    Ok(stubs
+        .into_iter()
+        .map(|(chars, id, title, knowledge_type)| {
+            serde_json::json!({
+                "id": id,
+                "title": title,
+                "knowledge_type": knowledge_type,
+                "chars": chars,
+            })
+        })
+        .collect())
+}
+
+/// Words `text_stats` leaves out of its top terms when the config doesn't list its own
+#[cfg(feature = "gui")]
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been", "but", "by", "can",
+    "could", "do", "does", "for", "from", "get", "had", "has", "have", "he", "her", "his", "how", "i", "if", "in",
+    "into", "is", "it", "its", "just", "me", "more", "my", "no", "not", "of", "on", "one", "only", "or", "other",
+    "our", "out", "she", "so", "some", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "to", "up", "use", "was", "we", "were", "what", "when", "which", "who", "why", "will", "with", "would",
+    "you", "your",
+];
+
+/// Writing statistics over every unencrypted note's title and content: total and average
+/// words, words per knowledge type and the `top` most frequent terms outside `stopwords`
+/// (`ENGLISH_STOPWORDS` when `None`). A word is a run of letters, digits and apostrophes,
+/// compared lowercase; terms that are only digits are left out of the ranking. Notes are
+/// read one row at a time, so the vault's text is never all in memory at once.
+#[cfg(feature = "gui")]
+pub fn text_stats(conn: &Connection, stopwords: Option<&[String]>, top: usize) -> Result<serde_json::Value, QuickNoteError> {
+    let stopwords: std::collections::HashSet<String> = match stopwords {
+        Some(words) => words.iter().map(|w| w.to_lowercase()).collect(),
+        None => ENGLISH_STOPWORDS.iter().map(|w| w.to_string()).collect(),
+    };
+    let mut terms: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+    let mut by_type: std::collections::BTreeMap<String, u64> = std::collections::BTreeMap::new();
+    let (mut notes, mut total_words, mut encrypted) = (0u64, 0u64, 0u64);
+
+    let mut stmt = conn.prepare("SELECT title, content, COALESCE(knowledge_type, 'Concept'), encrypted FROM notes")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if row.get::<_, bool>(3)? {
+            encrypted += 1;
+            continue;
+        }
+        let mut words = 0;
+        for text in [row.get::<_, String>(0)?, row.get::<_, String>(1)?] {
+            for word in text.split(|c: char| !c.is_alphanumeric() && c != '\'').map(|w| w.trim_matches('\'')).filter(|w| !w.is_empty()) {
+                words += 1;
+                let word = word.to_lowercase();
+                if !word.chars().all(|c| c.is_numeric()) && !stopwords.contains(&word) {
+                    *terms.entry(word).or_default() += 1;
+                }
+            }
+        }
+        notes += 1;
+        total_words += words;
+        *by_type.entry(row.get(2)?).or_default() += words;
+    }
+
+    let mut top_terms: Vec<(String, u64)> = terms.into_iter().collect();
+    top_terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_terms.truncate(top);
+
+    Ok(serde_json::json!({
+        "notes": notes,
+        "encrypted_skipped": encrypted,
+        "total_words": total_words,
+        "average_words": if notes == 0 { 0.0 } else { total_words as f64 / notes as f64 },
+        "by_type": by_type,
+        "top_terms": top_terms
+            .into_iter()
+            .map(|(term, count)| serde_json::json!({ "term": term, "count": count }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    fn content(conn: &Connection, id: u64) -> String {
+        conn.query_row("SELECT content FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    fn fts_ids(conn: &Connection, query: &str) -> Vec<u64> {
+        let mut stmt = conn.prepare("SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?1 ORDER BY rowid").unwrap();
+        let ids = stmt.query_map([query], |row| row.get(0)).unwrap();
+        ids.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn append_adds_a_line_and_reindexes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Standup", "monday: planning");
+        conn.execute("UPDATE notes SET updated_at = 1000 WHERE id = ?1", [id]).unwrap();
+
+        append_note(&conn, id, "tuesday: deploy", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        assert_eq!(content(&conn, id), "monday: planning\ntuesday: deploy");
+        assert_eq!(fts_ids(&conn, "deploy"), vec![id]);
+        let updated_at: i64 = conn.query_row("SELECT updated_at FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert!(updated_at > 1000);
+    }
+
+    #[test]
+    fn append_to_empty_note_has_no_leading_newline() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Journal", "");
+
+        append_note(&conn, id, "first entry", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        assert_eq!(content(&conn, id), "first entry");
+    }
+
+    #[test]
+    fn append_with_timestamp_prefixes_iso_time() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Journal", "");
+
+        append_note(&conn, id, "entry", true, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        let line = content(&conn, id);
+        let (stamp, text) = line.split_once("] ").unwrap();
+        assert_eq!(text, "entry");
+        assert!(chrono::NaiveDateTime::parse_from_str(stamp, "[%Y-%m-%dT%H:%M:%SZ").is_ok(), "{}", stamp);
+    }
+
+    #[test]
+    fn append_enforces_byte_limit() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Log", "abcd");
+
+        // 4 bytes + newline + 5 bytes lands exactly on the limit
+        append_note(&conn, id, "efghi", false, 10, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+        let err = append_note(&conn, id, "", false, 10, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap_err();
+
+        assert!(matches!(err, QuickNoteError::NoteTooLarge { size: 11, limit: 10 }));
+        assert_eq!(content(&conn, id), "abcd\nefghi");
+    }
+
+    #[test]
+    fn append_refuses_locked_note() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Frozen", "keep");
+        conn.execute("UPDATE notes SET is_locked = 1 WHERE id = ?1", [id]).unwrap();
+
+        assert!(matches!(append_note(&conn, id, "more", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]), Err(QuickNoteError::Locked(_))));
+        assert_eq!(content(&conn, id), "keep");
+    }
+
+    #[test]
+    fn append_to_missing_note_is_not_found() {
+        let conn = test_vault();
+        assert!(matches!(append_note(&conn, 42, "x", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]), Err(QuickNoteError::NotFound(42))));
+    }
+
+    fn find(conn: &Connection, id: u64, query: &str, case_sensitive: bool, whole_word: bool) -> Vec<(u64, u64, u64)> {
+        let hits = search_in_note(conn, id, query, case_sensitive, whole_word).unwrap();
+        hits.iter()
+            .map(|hit| (hit["line"].as_u64().unwrap(), hit["start"].as_u64().unwrap(), hit["end"].as_u64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn in_note_find_reports_every_match_on_a_line() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Log", "intro\nerror, Error and ERROR\nerrors");
+
+        assert_eq!(find(&conn, id, "error", false, false), vec![(2, 0, 5), (2, 7, 12), (2, 17, 22), (3, 0, 5)]);
+        assert_eq!(find(&conn, id, "error", true, false), vec![(2, 0, 5), (3, 0, 5)]);
+        assert_eq!(find(&conn, id, "error", false, true), vec![(2, 0, 5), (2, 7, 12), (2, 17, 22)]);
+        assert_eq!(find(&conn, id, "aa", false, false), vec![]);
+    }
+
+    #[test]
+    fn in_note_find_uses_char_offsets() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Notes", "Ünïcödé café, CAFÉ");
+
+        assert_eq!(find(&conn, id, "café", false, false), vec![(1, 8, 12), (1, 14, 18)]);
+        let hit = &search_in_note(&conn, id, "CAFÉ", true, true).unwrap()[0];
+        assert_eq!(hit["text"], "Ünïcödé café, CAFÉ");
+    }
+
+    #[test]
+    fn in_note_find_refuses_encrypted_notes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Secret", "c1phertext");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [id]).unwrap();
+
+        assert!(matches!(search_in_note(&conn, id, "c1", false, false), Err(QuickNoteError::InvalidInput(_))));
+        assert!(matches!(search_in_note(&conn, 99, "x", false, false), Err(QuickNoteError::NotFound(99))));
+    }
+
+    fn sql_note(conn: &Connection, title: &str, content: &str) -> u64 {
+        let id = test_note(conn, title, content);
+        conn.execute("UPDATE notes SET knowledge_type = 'SQLQuery' WHERE id = ?1", [id]).unwrap();
+        refresh_metadata(conn, id).unwrap();
+        id
+    }
+
+    fn dialect_ids(conn: &Connection, dialect: &str) -> Vec<u64> {
+        notes_by_dialect(conn, dialect).unwrap().iter().map(|n| n.id).collect()
+    }
+
+    #[test]
+    fn notes_by_dialect_uses_detected_or_manual_dialect() {
+        let conn = test_vault();
+        let pg = sql_note(&conn, "Upsert", "INSERT INTO t VALUES (1) RETURNING id");
+        let plain = sql_note(&conn, "Plain", "SELECT 1");
+        test_note(&conn, "Not SQL", "RETURNING customers");
+
+        assert_eq!(dialect_ids(&conn, "postgres"), vec![pg]);
+        assert_eq!(dialect_ids(&conn, "unknown"), vec![plain]);
+
+        set_sql_dialect(&conn, plain, Some("mysql")).unwrap();
+        update(&conn, plain, "SELECT 2", 1024).unwrap();
+        assert_eq!(dialect_ids(&conn, "mysql"), vec![plain]);
+
+        set_sql_dialect(&conn, plain, None).unwrap();
+        assert_eq!(dialect_ids(&conn, "unknown"), vec![plain]);
+        assert!(matches!(notes_by_dialect(&conn, "oracle"), Err(QuickNoteError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn archived_note_is_hidden_from_lists_unless_requested() {
+        let conn = test_vault();
+        let kept = test_note(&conn, "Kept", "");
+        let stale = sourced(&conn, "Stale", "https://example.com/old");
+        set_archived(&conn, stale, true).unwrap();
+
+        let ids = |include_archived: bool| -> Vec<u64> {
+            let items = list_notes(&conn, 140, None, include_archived, None, false).unwrap();
+            items.iter().map(|n| n.id).collect()
+        };
+        assert_eq!(ids(false), vec![kept]);
+        assert_eq!(ids(true), vec![stale, kept]);
+        assert!(notes_by_source(&conn, "https://example.com").unwrap().is_empty());
+
+        set_archived(&conn, stale, false).unwrap();
+        assert_eq!(ids(false), vec![stale, kept]);
+        assert!(matches!(set_archived(&conn, 99, true), Err(QuickNoteError::NotFound(99))));
+    }
+
+    #[test]
+    fn vault_stats_summarizes_the_vault() {
+        let conn = test_vault();
+        let sql = test_note(&conn, "Query", SQL);
+        let plain = test_note(&conn, "Plain", "");
+        test_note(&conn, "Captured", "");
+        conn.execute(r#"UPDATE notes SET knowledge_type = 'SQLQuery', tags = '["db","sql"]', review_due = 1 WHERE id = ?1"#, [sql]).unwrap();
+        conn.execute(r#"UPDATE notes SET tags = '["db"]' WHERE id = ?1"#, [plain]).unwrap();
+        conn.execute("UPDATE notes SET is_processed = 0 WHERE title = 'Captured'", []).unwrap();
+
+        let stats = vault_stats(&conn).unwrap();
+
+        assert_eq!(
+            stats,
+            serde_json::json!({
+                "note_count": 3,
+                "by_type": { "Concept": 2, "SQLQuery": 1 },
+                "tag_count": 2,
+                "review_enrolled": 1,
+                "inbox": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn locked_note_refuses_edits_until_unlocked() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Cheat sheet", "SELECT 1");
+
+        assert!(toggle_lock(&conn, id).unwrap());
+        assert!(matches!(update(&conn, id, "changed", 1024), Err(QuickNoteError::Locked(_))));
+        assert!(matches!(append_note(&conn, id, "more", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]), Err(QuickNoteError::Locked(_))));
+        assert!(matches!(delete_note(&conn, id), Err(QuickNoteError::Locked(_))));
+        // Still readable and searchable
+        assert_eq!(content(&conn, id), "SELECT 1");
+        assert_eq!(fts_ids(&conn, "select"), vec![id]);
+
+        assert!(!toggle_lock(&conn, id).unwrap());
+        update(&conn, id, "changed", 1024).unwrap();
+        assert_eq!(content(&conn, id), "changed");
+        delete_note(&conn, id).unwrap();
+        assert!(matches!(toggle_lock(&conn, id), Err(QuickNoteError::NotFound(_))));
+    }
+
+    fn split(conn: &mut Connection, id: u64, by: &str, keep_original: bool) -> Result<Vec<u64>, QuickNoteError> {
+        split_note(conn, id, by, keep_original, TagCase::Lower, KnowledgeType::Concept, false, &[])
+    }
+
+    fn title(conn: &Connection, id: u64) -> String {
+        conn.query_row("SELECT title FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    fn archived(conn: &Connection, id: u64) -> bool {
+        conn.query_row("SELECT is_archived FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn split_by_heading_inherits_tags() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Topics", "## Indexes\nuse btree\n## Vacuum\nrun nightly #ops");
+        conn.execute(r#"UPDATE notes SET tags = '["db"]' WHERE id = ?1"#, [id]).unwrap();
+
+        let ids = split(&mut conn, id, "heading", true).unwrap();
+
+        assert_eq!(ids.len(), 2);
+        assert_eq!(title(&conn, ids[0]), "Indexes");
+        assert_eq!(title(&conn, ids[1]), "Vacuum");
+        assert_eq!(stored_tags(&conn, ids[0]), r#"["db"]"#);
+        assert_eq!(stored_tags(&conn, ids[1]), r#"["ops","db"]"#);
+        assert!(!archived(&conn, id));
+    }
+
+    #[test]
+    fn split_without_keep_archives_the_original() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Log", "first part\n---\nsecond part");
+
+        let ids = split(&mut conn, id, "hr", false).unwrap();
+
+        assert_eq!(ids.len(), 2);
+        assert_eq!(content(&conn, id), "first part\n---\nsecond part");
+        assert!(archived(&conn, id));
+        assert_eq!(title(&conn, ids[1]), "Log (2)");
+    }
+
+    #[test]
+    fn split_without_split_points_returns_original() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Single", "just one topic");
+
+        assert_eq!(split(&mut conn, id, "heading", false).unwrap(), vec![id]);
+        assert!(!archived(&conn, id));
+        assert!(matches!(split(&mut conn, id, "chapter", false), Err(QuickNoteError::InvalidInput(_))));
+    }
+
+    fn sourced(conn: &Connection, title: &str, source: &str) -> u64 {
+        let id = test_note(conn, title, "");
+        conn.execute("UPDATE notes SET source = ?1 WHERE id = ?2", rusqlite::params![source, id]).unwrap();
+        id
+    }
+
+    #[test]
+    fn notes_by_source_matches_prefix() {
+        let conn = test_vault();
+        let post = sourced(&conn, "Post", "https://blog.example.com/2024/indexes");
+        let other_post = sourced(&conn, "Other post", "https://blog.example.com/2023/vacuum");
+        sourced(&conn, "Elsewhere", "https://example.com/blog.example.com");
+        sourced(&conn, "Wildcard", "https://blogxexample.com/");
+        test_note(&conn, "No source", "");
+
+        let mut ids: Vec<u64> = notes_by_source(&conn, " https://blog.example.com/ ").unwrap().iter().map(|n| n.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![post, other_post]);
+        assert!(notes_by_source(&conn, "https://blog_example").unwrap().is_empty());
+        assert!(notes_by_source(&conn, "  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_keeps_or_clears_source() {
+        let conn = test_vault();
+        let id = sourced(&conn, "Post", "Designing Data-Intensive Applications");
+        let source = |conn: &Connection| -> Option<String> {
+            conn.query_row("SELECT source FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+        };
+
+        update(&conn, id, "edited", 1024).unwrap();
+        assert_eq!(source(&conn).as_deref(), Some("Designing Data-Intensive Applications"));
+
+        update_note(&conn, id, "Post", "edited", Some(" "), 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+        assert_eq!(source(&conn), None);
+    }
+
+    fn created(conn: &Connection, title: &str, at: i64) -> u64 {
+        let id = test_note(conn, title, "");
+        conn.execute("UPDATE notes SET created_at = ?1 WHERE id = ?2", rusqlite::params![at, id]).unwrap();
+        id
+    }
+
+    /// 2024-03-15 12:00:00 UTC
+    const MARCH_15: i64 = 1_710_504_000;
+
+    #[test]
+    fn on_this_day_matches_month_and_day_across_years() {
+        let conn = test_vault();
+        let three_years = created(&conn, "Three years ago, just after midnight", 1_615_766_430);
+        let last_year = created(&conn, "Last year, just before midnight", 1_678_924_799);
+        let today = created(&conn, "Today", MARCH_15 - 3600);
+        created(&conn, "Yesterday, just before midnight", 1_710_460_799);
+        created(&conn, "Next month last year", 1_681_552_800);
+
+        let hits = on_this_day(&conn, MARCH_15).unwrap();
+        let ids: Vec<u64> = hits.iter().map(|hit| hit["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, vec![today, last_year, three_years]);
+        let years: Vec<i64> = hits.iter().map(|hit| hit["year"].as_i64().unwrap()).collect();
+        assert_eq!(years, vec![2024, 2023, 2021]);
+    }
+
+    #[test]
+    fn on_this_day_on_a_leap_day_only_matches_leap_days() {
+        let conn = test_vault();
+        let leap = created(&conn, "Leap day 2020", 1_582_999_200);
+        created(&conn, "March 1st 2023", 1_677_657_600);
+        created(&conn, "February 28th 2023", 1_677_571_200);
+
+        let hits = on_this_day(&conn, 1_709_197_200).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit["id"].as_u64().unwrap()).collect::<Vec<_>>(), vec![leap]);
+    }
+
+    #[test]
+    fn on_this_day_skips_archived_notes() {
+        let conn = test_vault();
+        let kept = created(&conn, "Last year", 1_678_878_000);
+        let archived = created(&conn, "Archived last year", 1_678_878_000);
+        set_archived(&conn, archived, true).unwrap();
+
+        let hits = on_this_day(&conn, MARCH_15).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit["id"].as_u64().unwrap()).collect::<Vec<_>>(), vec![kept]);
+    }
+
+    #[test]
+    fn duplicate_copies_fields_and_resets_review() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Runbook", "restart the worker");
+        conn.execute(
+            r#"UPDATE notes SET knowledge_type = 'Process', tags = '["ops"]', review_due = 1000, review_streak = 4 WHERE id = ?1"#,
+            [id],
+        )
+        .unwrap();
+
+        let copy = duplicate_note(&conn, id).unwrap();
+
+        assert_ne!(copy, id);
+        let (title, kind, tags, due, streak): (String, String, String, i64, i64) = conn
+            .query_row(
+                "SELECT title, knowledge_type, tags, review_due, review_streak FROM notes WHERE id = ?1",
+                [copy],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!((title.as_str(), kind.as_str(), tags.as_str()), ("Runbook (copy)", "Process", r#"["ops"]"#));
+        assert!(due > 1000);
+        assert_eq!(streak, 0);
+        assert_eq!(fts_ids(&conn, "worker"), vec![id, copy]);
+    }
+
+    #[test]
+    fn editing_duplicate_leaves_original_alone() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Runbook", "restart the worker");
+        let copy = duplicate_note(&conn, id).unwrap();
+
+        update(&conn, copy, "drain the queue", 1024).unwrap();
+
+        assert_eq!(content(&conn, id), "restart the worker");
+        assert_eq!(fts_ids(&conn, "worker"), vec![id]);
+        assert_eq!(fts_ids(&conn, "queue"), vec![copy]);
+    }
+
+    #[test]
+    fn duplicate_of_missing_note_is_not_found() {
+        let conn = test_vault();
+        assert!(matches!(duplicate_note(&conn, 7), Err(QuickNoteError::NotFound(7))));
+    }
+
+    fn update(conn: &Connection, id: u64, content: &str, max_bytes: usize) -> Result<(), QuickNoteError> {
+        update_note(conn, id, "Title", content, None, max_bytes, TagCase::Lower, KnowledgeType::Concept, false, &[])
+    }
+
+    #[test]
+    fn note_size_limit_is_inclusive() {
+        assert!(check_note_size("abcd", 4).is_ok());
+        assert!(matches!(check_note_size("abcde", 4), Err(QuickNoteError::NoteTooLarge { size: 5, limit: 4 })));
+    }
+
+    #[test]
+    fn note_size_counts_utf8_bytes() {
+        // Two chars, four bytes
+        assert!(check_note_size("éé", 4).is_ok());
+        assert!(matches!(check_note_size("éé", 3), Err(QuickNoteError::NoteTooLarge { size: 4, limit: 3 })));
+    }
+
+    #[test]
+    fn update_enforces_byte_limit_at_boundary() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "old");
+
+        update(&conn, id, "12345678", 8).unwrap();
+        assert_eq!(content(&conn, id), "12345678");
+        assert!(matches!(update(&conn, id, "123456789", 8), Err(QuickNoteError::NoteTooLarge { size: 9, limit: 8 })));
+        assert_eq!(content(&conn, id), "12345678");
+    }
+
+    #[test]
+    fn update_recategorizes_and_merges_tags() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "plain");
+        conn.execute(r#"UPDATE notes SET tags = '["manual"]', knowledge_type = 'Concept' WHERE id = ?1"#, [id]).unwrap();
+
+        update(&conn, id, &format!("{} #reporting", SQL), 1024).unwrap();
+
+        assert_eq!(note_type(&conn, id).as_deref(), Some("SQLQuery"));
+        assert_eq!(stored_tags(&conn, id), r#"["manual","reporting"]"#);
+    }
+
+    #[test]
+    fn update_keeps_locked_type() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "plain");
+        conn.execute("UPDATE notes SET knowledge_type = 'Process', type_locked = 1 WHERE id = ?1", [id]).unwrap();
+
+        update(&conn, id, SQL, 1024).unwrap();
+
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Process"));
+    }
+
+    #[test]
+    fn append_picks_up_new_tags() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Journal", "day one");
+        conn.execute(r#"UPDATE notes SET tags = '["journal"]' WHERE id = ?1"#, [id]).unwrap();
+
+        append_note(&conn, id, "found the leak #memory", false, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        assert_eq!(stored_tags(&conn, id), r#"["journal","memory"]"#);
+    }
+
+    #[test]
+    fn reclassify_changes_and_locks_types() {
+        let mut conn = test_vault();
+        let a = test_note(&conn, "Active users", SQL);
+        let b = test_note(&conn, "Already typed", "body");
+        conn.execute("UPDATE notes SET knowledge_type = 'Snippet' WHERE id = ?1", [b]).unwrap();
+
+        assert_eq!(reclassify(&mut conn, &[a, b], "Snippet").unwrap(), 1);
+        assert_eq!(note_type(&conn, a).as_deref(), Some("Snippet"));
+        // Locked, so a recategorize run leaves the SQL note alone
+        assert_eq!(recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 0);
+        assert_eq!(note_type(&conn, a).as_deref(), Some("Snippet"));
+        assert_eq!(fts_ids(&conn, "users"), vec![a]);
+    }
+
+    #[test]
+    fn reclassify_rejects_unknown_type() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Note", "body");
+
+        assert!(matches!(reclassify(&mut conn, &[id], "Poem"), Err(QuickNoteError::InvalidInput(_))));
+        let locked: bool = conn.query_row("SELECT type_locked FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert!(!locked);
+    }
+
+    #[test]
+    fn preview_strips_markdown_and_fences() {
+        let content = "# Title\n> quoted **bold**\n- item `code`\n```rust\nlet x = 1;\n```";
+        assert_eq!(make_preview(content, 140), "Title quoted bold item code let x = 1;");
+    }
+
+    #[test]
+    fn preview_cuts_at_word_boundary() {
+        assert_eq!(make_preview("alpha beta gamma delta", 13), "alpha beta…");
+        assert_eq!(make_preview("alpha beta", 10), "alpha beta");
+    }
+
+    #[test]
+    fn preview_never_splits_multibyte_chars() {
+        // No spaces to back off to, so the cut lands mid-text
+        assert_eq!(make_preview("日本語のテキストです", 4), "日本語の…");
+        assert_eq!(make_preview("héllo wörld ñandú", 13), "héllo wörld…");
+        assert_eq!(make_preview("🦀🦀🦀", 2), "🦀🦀…");
+    }
+
+    fn note_type(conn: &Connection, id: u64) -> Option<String> {
+        conn.query_row("SELECT knowledge_type FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    fn stored_tags(conn: &Connection, id: u64) -> String {
+        conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    const SQL: &str = "SELECT id, name FROM users WHERE active = 1 ORDER BY name";
+
+    #[test]
+    fn recategorize_all_counts_type_changes() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Active users", SQL);
+        conn.execute("UPDATE notes SET knowledge_type = 'Concept' WHERE id = ?1", [id]).unwrap();
+
+        assert_eq!(recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 1);
+        assert_eq!(note_type(&conn, id).as_deref(), Some("SQLQuery"));
+        // Re-running is a no-op for the type
+        assert_eq!(recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn recategorize_all_only_default_skips_typed_notes() {
+        let mut conn = test_vault();
+        let typed = test_note(&conn, "Active users", SQL);
+        conn.execute("UPDATE notes SET knowledge_type = 'Checklist' WHERE id = ?1", [typed]).unwrap();
+        let fallback = test_note(&conn, "More users", SQL);
+        conn.execute("UPDATE notes SET knowledge_type = 'Concept' WHERE id = ?1", [fallback]).unwrap();
+
+        assert_eq!(recategorize_all(&mut conn, true, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 1);
+        assert_eq!(note_type(&conn, typed).as_deref(), Some("Checklist"));
+        assert_eq!(note_type(&conn, fallback).as_deref(), Some("SQLQuery"));
+    }
+
+    #[test]
+    fn recategorize_all_respects_type_lock() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Active users", SQL);
+        conn.execute("UPDATE notes SET knowledge_type = 'Concept', type_locked = 1 WHERE id = ?1", [id]).unwrap();
+
+        assert_eq!(recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 0);
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Concept"));
+    }
+
+    #[test]
+    fn recategorize_all_skips_encrypted_notes() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Secret", SQL);
+        conn.execute("UPDATE notes SET knowledge_type = 'Concept', encrypted = 1, tags = '[\"mine\"]' WHERE id = ?1", [id]).unwrap();
+
+        assert_eq!(recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap(), 0);
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Concept"));
+        assert_eq!(stored_tags(&conn, id), r#"["mine"]"#);
+    }
+
+    #[test]
+    fn recategorize_all_keeps_manual_tags() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "Deploy", "Rollout notes #release");
+        conn.execute(r#"UPDATE notes SET tags = '["oncall"]' WHERE id = ?1"#, [id]).unwrap();
+
+        recategorize_all(&mut conn, false, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap();
+
+        let tags: Vec<String> = serde_json::from_str(&stored_tags(&conn, id)).unwrap();
+        assert_eq!(tags[0], "oncall");
+        assert!(tags.contains(&"release".to_string()), "{:?}", tags);
+    }
+
+    fn recent_ids(conn: &Connection) -> Vec<u64> {
+        recent_notes(conn, 10, 40, None, false).unwrap().iter().map(|note| note.id).collect()
+    }
+
+    fn age(conn: &Connection, id: u64, updated_at: i64) {
+        conn.execute("UPDATE notes SET updated_at = ?1 WHERE id = ?2", rusqlite::params![updated_at, id]).unwrap();
+    }
+
+    fn updated_at(conn: &Connection, id: u64) -> i64 {
+        conn.query_row("SELECT updated_at FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn touch_bumps_updated_at_and_reorders_recent() {
+        let conn = test_vault();
+        let older = test_note(&conn, "Older", "a");
+        let newer = test_note(&conn, "Newer", "b");
+        age(&conn, older, 1_000);
+        age(&conn, newer, 2_000);
+        assert_eq!(recent_ids(&conn), vec![newer, older]);
+
+        touch_note(&conn, older).unwrap();
+        assert!(updated_at(&conn, older) > 2_000);
+        assert_eq!(recent_ids(&conn), vec![older, newer]);
+        assert_eq!(content(&conn, older), "a");
+    }
+
+    #[test]
+    fn update_bumps_updated_at() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "a");
+        age(&conn, id, 1_000);
+        update(&conn, id, "b", 100).unwrap();
+        assert!(updated_at(&conn, id) > 1_000);
+    }
+
+    #[test]
+    fn touch_missing_note_is_not_found() {
+        let conn = test_vault();
+        assert!(matches!(touch_note(&conn, 42), Err(QuickNoteError::NotFound(42))));
+    }
+
+    fn recategorize_one(conn: &Connection, id: u64) -> serde_json::Value {
+        recategorize_note(conn, id, TagCase::Lower, KnowledgeType::Concept, false, &[]).unwrap()
+    }
+
+    #[test]
+    fn recategorize_note_flips_type_after_edit() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "just a thought");
+        assert_eq!(recategorize_one(&conn, id)["knowledge_type"], "Concept");
+
+        conn.execute("UPDATE notes SET content = ?1 WHERE id = ?2", rusqlite::params![SQL, id]).unwrap();
+        let result = recategorize_one(&conn, id);
+        assert_eq!(result["knowledge_type"], "SQLQuery");
+        assert_eq!(note_type(&conn, id).as_deref(), Some("SQLQuery"));
+        assert!(result["confidence"].as_f64().unwrap() >= 0.5);
+    }
+
+    #[test]
+    fn recategorize_note_keeps_locked_type_and_manual_tags() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "#found");
+        conn.execute("UPDATE notes SET knowledge_type = 'Process', type_locked = 1, tags = '[\"manual\"]' WHERE id = ?1", [id])
+            .unwrap();
+        conn.execute("UPDATE notes SET content = ?1 WHERE id = ?2", rusqlite::params![format!("{} #found", SQL), id]).unwrap();
+
+        let result = recategorize_one(&conn, id);
+        assert_eq!(result["knowledge_type"], "Process");
+        assert_eq!(result["type_locked"], true);
+        assert_eq!(stored_tags(&conn, id), r#"["manual","found"]"#);
+    }
+
+    #[test]
+    fn tidy_keeps_code_indentation_and_trailing_spaces_in_fences() {
+        let content = "Steps:  \r\n```py\n    if x:  \n\n\n\n        run()\n```\r\nafter\t";
+        assert_eq!(tidy_content(content), "Steps:\n```py\n    if x:  \n\n\n\n        run()\n```\nafter");
+    }
+
+    #[test]
+    fn tidy_collapses_long_blank_runs_only() {
+        assert_eq!(tidy_content("a\n\n\n\n\nb"), "a\n\nb");
+        assert_eq!(tidy_content("a\n\n\nb"), "a\n\n\nb");
+        assert_eq!(tidy_content("a\r\rb\n"), "a\n\nb\n");
+    }
+
+    #[test]
+    fn tidy_note_rewrites_and_reindexes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Title", "tidyword   \n\n\n\n\nrest");
+        assert!(tidy_note(&conn, id).unwrap());
+        assert_eq!(content(&conn, id), "tidyword\n\nrest");
+        assert_eq!(fts_ids(&conn, "tidyword"), vec![id]);
+        assert!(!tidy_note(&conn, id).unwrap());
+    }
+
+    fn vault_rows(conn: &Connection) -> Vec<(String, String, bool)> {
+        let mut stmt = conn.prepare("SELECT title, content, is_archived FROM notes ORDER BY id").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    fn row(title: &str, content: &str, archived: bool) -> (String, String, bool) {
+        (title.to_string(), content.to_string(), archived)
+    }
+
+    #[test]
+    fn move_copies_into_target_and_archives_originals() {
+        let mut source = test_vault();
+        let first = test_note(&source, "First", "one");
+        let second = test_note(&source, "Second", "two");
+        test_note(&source, "Stays", "three");
+        crate::attachments::add_attachment(&source, first, "a.bin", &[0, 1, 2], 10).unwrap();
+        let mut target = test_vault();
+        test_note(&target, "Existing", "already there");
+
+        let moved = move_notes(&mut source, &mut target, &[second, first, first]).unwrap();
+        assert_eq!(moved.iter().map(|(old, _)| *old).collect::<Vec<_>>(), vec![first, second]);
+
+        assert_eq!(
+            vault_rows(&target),
+            vec![row("Existing", "already there", false), row("First", "one", false), row("Second", "two", false)]
+        );
+        assert_eq!(vault_rows(&source), vec![row("First", "one", true), row("Second", "two", true), row("Stays", "three", false)]);
+        let attachment = crate::attachments::list_attachments(&target, moved[0].1).unwrap();
+        assert_eq!(attachment.len(), 1);
+        // The originals keep their attachments too
+        assert_eq!(crate::attachments::list_attachments(&source, first).unwrap().len(), 1);
+        assert_eq!(fts_ids(&target, "two"), vec![moved[1].1]);
+    }
+
+    #[test]
+    fn failed_copy_rolls_back_target_and_leaves_source_alone() {
+        let mut source = test_vault();
+        let ids = [test_note(&source, "First", "one"), test_note(&source, "Second", "two")];
+        let mut target = test_vault();
+        // Fail on the second insert, mid-move
+        target
+            .execute_batch(
+                "CREATE TRIGGER full_disk BEFORE INSERT ON notes WHEN (SELECT COUNT(*) FROM notes) >= 1
+                 BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+            )
+            .unwrap();
+
+        assert!(move_notes(&mut source, &mut target, &ids).is_err());
+        assert!(vault_rows(&target).is_empty());
+        assert_eq!(vault_rows(&source), vec![row("First", "one", false), row("Second", "two", false)]);
+    }
+
+    #[test]
+    fn failed_archive_leaves_notes_in_both_vaults() {
+        let mut source = test_vault();
+        let ids = [test_note(&source, "First", "one"), test_note(&source, "Second", "two")];
+        source
+            .execute_batch(
+                "CREATE TRIGGER crash AFTER UPDATE OF is_archived ON notes WHEN new.title = 'Second'
+                 BEGIN SELECT RAISE(ABORT, 'crash'); END;",
+            )
+            .unwrap();
+        let mut target = test_vault();
+
+        assert!(move_notes(&mut source, &mut target, &ids).is_err());
+        assert_eq!(vault_rows(&target), vec![row("First", "one", false), row("Second", "two", false)]);
+        assert_eq!(vault_rows(&source), vec![row("First", "one", false), row("Second", "two", false)]);
+    }
+
+    #[test]
+    fn checklist_conversion_turns_only_list_lines_into_tasks() {
+        let conn = test_vault();
+        let id = test_note(
+            &conn,
+            "trip",
+            "Packing for the trip.\n- passport\n  * charger\n1. book taxi\n- [x] tickets\nRemember the plants.\n```\n- not a task\n```\n",
+        );
+
+        assert_eq!(convert_to_checklist(&conn, id).unwrap(), 3);
+        assert_eq!(
+            content(&conn, id),
+            "Packing for the trip.\n- [ ] passport\n  - [ ] charger\n- [ ] book taxi\n- [x] tickets\nRemember the plants.\n```\n- not a task\n```\n"
+        );
+        assert_eq!(note_type(&conn, id).as_deref(), Some(KnowledgeType::Checklist.as_db_str()));
+        let locked: bool = conn.query_row("SELECT type_locked FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert!(locked);
+        assert_eq!(fts_ids(&conn, "passport"), vec![id]);
+
+        // Converting again finds nothing left to turn into tasks
+        assert_eq!(convert_to_checklist(&conn, id).unwrap(), 0);
+    }
+
+    #[test]
+    fn checklist_conversion_refuses_encrypted_notes() {
+        let conn = test_vault();
+        let id = test_note(&conn, "secret", "- item");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [id]).unwrap();
+        assert!(matches!(convert_to_checklist(&conn, id), Err(QuickNoteError::InvalidInput(_))));
+        assert_eq!(content(&conn, id), "- item");
+    }
+
+    fn stub_ids(conn: &Connection, min_chars: usize) -> Vec<u64> {
+        stub_notes(conn, min_chars).unwrap().iter().map(|stub| stub["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn stubs_are_below_the_threshold_not_counting_tags() {
+        let conn = test_vault();
+        let tagged = test_note(&conn, "tagged", "## idea #todo #later\n");
+        let word = test_note(&conn, "word", "TODO");
+        // Exactly at the threshold isn't a stub; one character less is
+        test_note(&conn, "boundary", "ten chars!!");
+        let short = test_note(&conn, "short", " nine chars #tag");
+        test_note(&conn, "substantial", "A proper note with a couple of sentences in it.");
+
+        assert_eq!(stub_ids(&conn, 10), vec![tagged, word, short]);
+        let stubs = stub_notes(&conn, 10).unwrap();
+        assert_eq!((stubs[0]["chars"].as_u64(), stubs[2]["chars"].as_u64()), (Some(4), Some(9)));
+        assert!(stub_ids(&conn, 0).is_empty());
+    }
+
+    #[test]
+    fn stubs_skip_archived_and_encrypted_notes() {
+        let conn = test_vault();
+        let archived = test_note(&conn, "archived", "x");
+        set_archived(&conn, archived, true).unwrap();
+        let secret = test_note(&conn, "secret", "x");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [secret]).unwrap();
+        let stub = test_note(&conn, "stub", "x");
+
+        assert_eq!(stub_ids(&conn, 10), vec![stub]);
+    }
+
+    #[test]
+    fn find_by_title_ignores_case() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Connection Pooling", "body");
+
+        let note = find_by_title(&conn, "connection POOLING").unwrap().unwrap();
+        assert_eq!((note.id, note.content.as_str()), (id, "body"));
+        assert!(find_by_title(&conn, "Connection").unwrap().is_none());
+        assert!(find_by_title(&conn, "").unwrap().is_none());
+    }
+
+    #[test]
+    fn duplicate_titles_resolve_to_most_recently_updated() {
+        let conn = test_vault();
+        let older = test_note(&conn, "Standup", "monday");
+        let newer = test_note(&conn, "standup", "tuesday");
+        conn.execute("UPDATE notes SET updated_at = 100 WHERE id = ?1", [newer]).unwrap();
+        conn.execute("UPDATE notes SET updated_at = 200 WHERE id = ?1", [older]).unwrap();
+        assert_eq!(find_by_title(&conn, "STANDUP").unwrap().unwrap().id, older);
+
+        // Encrypted notes are found by title but never expose their content
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [older]).unwrap();
+        assert_eq!(find_by_title(&conn, "standup").unwrap().unwrap().content, "");
+    }
+
+    #[test]
+    fn taken_titles_are_reported_with_their_owner() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Runbook", "");
+        assert!(matches!(
+            ensure_title_available(&conn, "RUNBOOK"),
+            Err(QuickNoteError::DuplicateTitle(owner)) if owner == id
+        ));
+        assert!(ensure_title_available(&conn, "Runbook 2").is_ok());
+    }
+
+    #[test]
+    fn compaction_keeps_every_reference_pointing_at_the_same_note() {
+        let mut conn = test_vault();
+        let ids: Vec<u64> = ["gone", "alpha", "gone too", "beta", "gamma"]
+            .iter()
+            .map(|title| test_note(&conn, title, &format!("{} body", title)))
+            .collect();
+        let (alpha, beta, gamma) = (ids[1], ids[3], ids[4]);
+        conn.execute("UPDATE notes SET content = 'see [[gamma]]', tags = '[\"greek\"]' WHERE id = ?1", [beta]).unwrap();
+        links::sync_links(&conn, beta).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO attachments (note_id, filename, mime, data, size) VALUES ({gamma}, 'g.txt', 'text/plain', x'00', 1);
+             INSERT INTO review_log (note_id, rating, easiness, interval) VALUES ({alpha}, 'Good', 2.5, 1);
+             INSERT INTO read_later (note_id, position) VALUES ({beta}, 1);"
+        ))
+        .unwrap();
+        for id in [ids[0], ids[2]] {
+            delete_note(&conn, id).unwrap();
+        }
+
+        assert_eq!(compact_ids(&mut conn).unwrap(), 3);
+        let titles: Vec<(u64, String)> = {
+            let mut stmt = conn.prepare("SELECT id, title FROM notes ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+            rows.collect::<Result<_, _>>().unwrap()
+        };
+        assert_eq!(titles, [(1, "alpha".to_string()), (2, "beta".to_string()), (3, "gamma".to_string())]);
+
+        let one = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(one("SELECT to_id FROM note_links WHERE from_id = 2"), 3);
+        assert_eq!(one("SELECT note_id FROM attachments"), 3);
+        assert_eq!(one("SELECT note_id FROM review_log"), 1);
+        assert_eq!(one("SELECT note_id FROM read_later"), 2);
+        assert_eq!(one("SELECT note_id FROM note_tags JOIN tags ON tags.id = tag_id WHERE name = 'greek'"), 2);
+        assert_eq!(fts_ids(&conn, "gamma"), vec![2, 3]);
+
+        // New notes continue the dense sequence
+        assert_eq!(test_note(&conn, "delta", ""), 4);
+        assert_eq!(compact_ids(&mut conn).unwrap(), 0);
+    }
+
+    fn inbox_ids(conn: &Connection) -> Vec<u64> {
+        inbox(conn, 0, None).unwrap().iter().map(|note| note.id).collect()
+    }
+
+    #[test]
+    fn captures_wait_in_the_inbox_until_processed() {
+        let mut conn = test_vault();
+        let processed = test_note(&conn, "filed", "");
+        let ids: Vec<u64> = ["newer", "older", "archived"].iter().map(|title| test_note(&conn, title, "")).collect();
+        conn.execute("UPDATE notes SET is_processed = 0 WHERE id != ?1", [processed]).unwrap();
+        conn.execute("UPDATE notes SET created_at = 100 WHERE id = ?1", [ids[1]]).unwrap();
+        set_archived(&conn, ids[2], true).unwrap();
+
+        assert_eq!(inbox_ids(&conn), vec![ids[1], ids[0]]);
+        assert_eq!(vault_stats(&conn).unwrap()["inbox"], 2);
+
+        // Already-processed and unknown ids don't count
+        assert_eq!(mark_processed(&mut conn, &[ids[1], processed, 999]).unwrap(), 1);
+        assert_eq!(inbox_ids(&conn), vec![ids[0]]);
+        assert_eq!(vault_stats(&conn).unwrap()["inbox"], 1);
+    }
+
+    fn reminder_ids(conn: &Connection) -> Vec<u64> {
+        due_reminders(conn).unwrap().iter().map(|r| r["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn only_past_reminders_are_due() {
+        let conn = test_vault();
+        let now = chrono::Utc::now().timestamp();
+        let (yesterday, last_week, tomorrow) = (test_note(&conn, "a", ""), test_note(&conn, "b", ""), test_note(&conn, "c", ""));
+        conn.execute("UPDATE notes SET review_due = 500 WHERE id = ?1", [tomorrow]).unwrap();
+        set_reminder(&conn, yesterday, Some(now - 86_400)).unwrap();
+        set_reminder(&conn, last_week, Some(now - 7 * 86_400)).unwrap();
+        set_reminder(&conn, tomorrow, Some(now + 86_400)).unwrap();
+
+        assert_eq!(reminder_ids(&conn), vec![last_week, yesterday]);
+        assert_eq!(due_reminders(&conn).unwrap()[0]["remind_at"], now - 7 * 86_400);
+
+        // Clearing or archiving takes a reminder off the list; review scheduling is untouched
+        set_reminder(&conn, last_week, None).unwrap();
+        set_archived(&conn, yesterday, true).unwrap();
+        assert!(reminder_ids(&conn).is_empty());
+        let review_due: i64 = conn.query_row("SELECT review_due FROM notes WHERE id = ?1", [tomorrow], |row| row.get(0)).unwrap();
+        assert_eq!(review_due, 500);
+        assert!(matches!(set_reminder(&conn, 999, Some(now)), Err(QuickNoteError::NotFound(999))));
+    }
+
+    #[test]
+    fn snippets_are_listed_by_detected_language() {
+        let conn = test_vault();
+        let rust = test_note(&conn, "parser", "```rust\nfn main() {}\n```");
+        let python = test_note(&conn, "script", "def main(self):\n    print(self.name)");
+        let prose = test_note(&conn, "idea", "```rust\nfn main() {}\n```");
+        conn.execute("UPDATE notes SET knowledge_type = 'Snippet' WHERE id IN (?1, ?2)", [rust, python]).unwrap();
+        for id in [rust, python, prose] {
+            refresh_metadata(&conn, id).unwrap();
+        }
+
+        let ids = |language: &str| -> Vec<u64> { notes_by_language(&conn, language).unwrap().iter().map(|n| n.id).collect() };
+        assert_eq!(ids("rs"), vec![rust]);
+        assert_eq!(ids("Python"), vec![python]);
+        assert!(ids("go").is_empty());
+    }
+
+    fn edit(conn: &mut Connection, id: u64, patch: &EditPatch) -> Result<(), QuickNoteError> {
+        apply_edits(conn, id, patch, 1024, TagCase::Lower, KnowledgeType::Concept, false, &[])
+    }
+
+    #[test]
+    fn multi_field_patch_applies_together() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "draft", "old words");
+        let patch = EditPatch {
+            title: Some("Final".to_string()),
+            tags: Some(vec!["#Ops".to_string(), "ops".to_string()]),
+            knowledge_type: Some("Process".to_string()),
+            source: Some(" https://example.com ".to_string()),
+            ..EditPatch::default()
+        };
+
+        edit(&mut conn, id, &patch).unwrap();
+        assert_eq!(title(&conn, id), "Final");
+        assert_eq!(stored_tags(&conn, id), r#"["ops"]"#);
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Process"));
+        let source: Option<String> =
+            conn.query_row("SELECT source FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert_eq!(source.as_deref(), Some("https://example.com"));
+        // Content wasn't in the patch, so it (and the FTS index) is untouched
+        assert_eq!(content(&conn, id), "old words");
+        assert_eq!(fts_ids(&conn, "final"), vec![id]);
+    }
+
+    #[test]
+    fn invalid_or_failing_patch_changes_nothing() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "keep", "body");
+        let bad_type = EditPatch {
+            title: Some("changed".to_string()),
+            knowledge_type: Some("Bogus".to_string()),
+            ..EditPatch::default()
+        };
+        assert!(matches!(edit(&mut conn, id, &bad_type), Err(QuickNoteError::InvalidInput(_))));
+
+        // A write failing after the UPDATE (here the links refresh) rolls the UPDATE back too
+        conn.execute_batch(
+            "CREATE TRIGGER fail_links BEFORE INSERT ON note_links BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+        )
+        .unwrap();
+        let linking = EditPatch {
+            title: Some("changed".to_string()),
+            content: Some("see [[elsewhere]]".to_string()),
+            ..EditPatch::default()
+        };
+        assert!(edit(&mut conn, id, &linking).is_err());
+        assert_eq!((title(&conn, id), content(&conn, id)), ("keep".to_string(), "body".to_string()));
+        assert!(fts_ids(&conn, "changed").is_empty());
+    }
+
+    #[test]
+    fn content_patch_recategorizes_unless_type_is_set() {
+        let mut conn = test_vault();
+        let id = test_note(&conn, "query", "thoughts");
+        let sql = EditPatch {
+            content: Some(format!("{} #db", SQL)),
+            tags: Some(vec!["work".to_string()]),
+            ..EditPatch::default()
+        };
+        edit(&mut conn, id, &sql).unwrap();
+        assert_eq!(note_type(&conn, id).as_deref(), Some("SQLQuery"));
+        assert_eq!(stored_tags(&conn, id), r#"["work","db"]"#);
+
+        // A type chosen in the same patch wins over the categorizer
+        let typed = EditPatch {
+            content: Some(SQL.to_string()),
+            knowledge_type: Some("Snippet".to_string()),
+            ..EditPatch::default()
+        };
+        edit(&mut conn, id, &typed).unwrap();
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Snippet"));
+
+        // Title-only edits leave the type alone
+        conn.execute("UPDATE notes SET type_locked = 0, knowledge_type = 'Note' WHERE id = ?1", [id]).unwrap();
+        let rename = EditPatch { title: Some("renamed".to_string()), ..EditPatch::default() };
+        edit(&mut conn, id, &rename).unwrap();
+        assert_eq!(note_type(&conn, id).as_deref(), Some("Note"));
+    }
+
+    #[test]
+    fn corrupt_tags_cell_reads_as_no_tags() {
+        let mut conn = test_vault();
+        let good = test_note(&conn, "Good", "fine #ops");
+        conn.execute(r#"UPDATE notes SET tags = '["ops"]' WHERE id = ?1"#, [good]).unwrap();
+        let corrupt = test_note(&conn, "Corrupt", "## One\nfirst\n## Two\nsecond");
+        conn.execute(r#"UPDATE notes SET tags = '["ops"' WHERE id = ?1"#, [corrupt]).unwrap();
+
+        let listed = list_notes(&conn, 0, None, false, None, false).unwrap();
+        let tags: Vec<(u64, Vec<String>)> = listed.into_iter().map(|n| (n.id, n.tags)).collect();
+        assert!(tags.contains(&(good, vec!["ops".to_string()])));
+        assert!(tags.contains(&(corrupt, Vec::new())));
+        assert!(find_by_title(&conn, "corrupt").unwrap().unwrap().tags.is_empty());
+        assert_eq!(vault_stats(&conn).unwrap()["tag_count"], 1);
+
+        // Sections inherit nothing from an unreadable list, and editing rewrites it cleanly
+        let sections = split(&mut conn, corrupt, "heading", true).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert!(sections.iter().all(|id| stored_tags(&conn, *id) == "[]"));
+        update(&conn, corrupt, "now #fixed", 1024).unwrap();
+        assert_eq!(stored_tags(&conn, corrupt), r#"["fixed"]"#);
+    }
+
+    fn top_terms(stats: &serde_json::Value) -> Vec<(String, u64)> {
+        stats["top_terms"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["term"].as_str().unwrap().to_string(), t["count"].as_u64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn text_stats_rank_terms_and_count_words() {
+        let conn = test_vault();
+        test_note(&conn, "Index tuning", "The index on the orders table. Rebuild the index weekly!");
+        let sql = test_note(&conn, "Orders", "orders by region, 2024 orders");
+        conn.execute("UPDATE notes SET knowledge_type = 'SQLQuery' WHERE id = ?1", [sql]).unwrap();
+        let secret = test_note(&conn, "Secret", "index index index index");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [secret]).unwrap();
+
+        let stats = text_stats(&conn, None, 3).unwrap();
+        // "orders" (in any case) 4, "index" 3, then the ties at 1 alphabetically; stopwords
+        // and bare numbers never rank, and the encrypted note isn't read
+        assert_eq!(
+            top_terms(&stats),
+            vec![("orders".to_string(), 4), ("index".to_string(), 3), ("rebuild".to_string(), 1)]
+        );
+        assert_eq!((stats["notes"].as_u64(), stats["encrypted_skipped"].as_u64()), (Some(2), Some(1)));
+        assert_eq!(stats["total_words"], 18);
+        assert_eq!(stats["average_words"], 9.0);
+        assert_eq!(stats["by_type"], serde_json::json!({ "Concept": 12, "SQLQuery": 6 }));
+    }
+
+    #[test]
+    fn text_stats_use_configured_stopwords() {
+        let conn = test_vault();
+        test_note(&conn, "", "the index, the index, the table");
+
+        let stopwords = vec!["INDEX".to_string()];
+        let terms = top_terms(&text_stats(&conn, Some(&stopwords), 20).unwrap());
+        assert_eq!(terms, vec![("the".to_string(), 3), ("table".to_string(), 1)]);
+        assert_eq!(text_stats(&test_vault(), None, 20).unwrap()["average_words"], 0.0);
+    }
+
+    fn listed(conn: &Connection, min_priority: Option<u8>, by_priority: bool) -> Vec<(u64, u8)> {
+        let notes = list_notes(conn, 0, None, false, min_priority, by_priority).unwrap();
+        notes.iter().map(|n| (n.id, n.priority)).collect()
+    }
+
+    #[test]
+    fn list_sorts_and_filters_by_priority() {
+        let conn = test_vault();
+        let ids: Vec<u64> = (0..5).map(|i| test_note(&conn, &format!("note {}", i), "")).collect();
+        for (id, level) in ids.iter().zip([1, 3, 0, 3, 2]) {
+            set_priority(&conn, *id, level).unwrap();
+        }
+
+        // Highest first, newest first within a level
+        let sorted = listed(&conn, None, true);
+        assert_eq!(sorted, vec![(ids[3], 3), (ids[1], 3), (ids[4], 2), (ids[0], 1), (ids[2], 0)]);
+        assert_eq!(listed(&conn, Some(2), true), sorted[..3].to_vec());
+        assert_eq!(listed(&conn, None, false)[0], (ids[4], 2));
+
+        let high: Vec<u64> = high_priority_notes(&conn, 0, None).unwrap().iter().map(|n| n.id).collect();
+        assert_eq!(high.len(), 2);
+        assert!(high.contains(&ids[1]) && high.contains(&ids[3]));
+        let weights = crate::search::Weights { title: 1.0, content: 1.0 };
+        let found = crate::search::search_notes(&conn, "note", None, 100, 2, false, weights, false).unwrap();
+        assert!(found.notes.iter().any(|n| n.id == ids[1] && n.priority == 3));
+    }
+
+    #[test]
+    fn out_of_range_priority_is_a_typed_error() {
+        let conn = test_vault();
+        let id = test_note(&conn, "note", "");
+        for level in [-1, 4, 256] {
+            assert!(matches!(set_priority(&conn, id, level), Err(QuickNoteError::InvalidPriority(l)) if l == level));
+        }
+        assert!(matches!(list_notes(&conn, 0, None, false, Some(9), false), Err(QuickNoteError::InvalidPriority(9))));
+        assert!(matches!(set_priority(&conn, 999, 1), Err(QuickNoteError::NotFound(999))));
+        // The column refuses bad values written around `set_priority` too
+        assert!(conn.execute("UPDATE notes SET priority = 7 WHERE id = ?1", [id]).is_err());
+    }
+
+    #[test]
+    fn stale_notes_skip_old_notes_that_are_still_opened() {
+        let conn = test_vault();
+        let age = |id: u64, days: i64| {
+            conn.execute("UPDATE notes SET updated_at = strftime('%s', 'now') - ?1 * 86400 WHERE id = ?2", [days, id as i64])
+                .unwrap();
+        };
+        let forgotten = test_note(&conn, "Forgotten", "");
+        age(forgotten, 400);
+        let still_read = test_note(&conn, "Old but read", "");
+        age(still_read, 400);
+        record_open(&conn, still_read).unwrap();
+        let opened_long_ago = test_note(&conn, "Opened long ago", "");
+        age(opened_long_ago, 300);
+        conn.execute(
+            "UPDATE notes SET open_count = 1, last_opened_at = strftime('%s', 'now') - 200 * 86400 WHERE id = ?1",
+            [opened_long_ago],
+        )
+        .unwrap();
+        let recent = test_note(&conn, "Recent", "");
+        age(recent, 3);
+        let locked = test_note(&conn, "Locked", "");
+        age(locked, 400);
+        conn.execute("UPDATE notes SET is_locked = 1 WHERE id = ?1", [locked]).unwrap();
+        let pinned = test_note(&conn, "Pinned", "");
+        age(pinned, 400);
+        conn.execute("UPDATE notes SET always_top = 1 WHERE id = ?1", [pinned]).unwrap();
+
+        let stale = stale_notes(&conn, 90, 5).unwrap();
+        let ids: Vec<u64> = stale.iter().map(|n| n["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, vec![forgotten, opened_long_ago], "fewest opens first");
+        assert_eq!(stale[0]["age_days"], 400);
+        assert_eq!(stale[1]["open_count"], 1);
+
+        assert_eq!(stale_notes(&conn, 90, 0).unwrap().len(), 1, "max_opens caps the open count");
+        let untouched: bool = conn
+            .query_row("SELECT updated_at <= strftime('%s', 'now') - 399 * 86400 FROM notes WHERE id = ?1", [still_read], |row| row.get(0))
+            .unwrap();
+        assert!(untouched, "an open is not an edit");
+        assert!(matches!(record_open(&conn, 999), Err(QuickNoteError::NotFound(999))));
+    }
+
+    #[test]
+    fn duplicate_titles_group_case_variants_largest_first() {
+        let conn = test_vault();
+        let pair = [test_note(&conn, "Postgres tips", "a"), test_note(&conn, "POSTGRES TIPS", "b")];
+        let triple = [test_note(&conn, "todo", "1"), test_note(&conn, "Todo", "2"), test_note(&conn, "TODO", "3")];
+        conn.execute("UPDATE notes SET is_archived = 1 WHERE id = ?1", [triple[2]]).unwrap();
+        test_note(&conn, "Unique", "c");
+
+        let groups = duplicate_titles(&conn).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!((groups[0]["title"].as_str(), groups[0]["count"].as_u64()), (Some("todo"), Some(3)));
+        assert_eq!((groups[1]["title"].as_str(), groups[1]["count"].as_u64()), (Some("Postgres tips"), Some(2)));
+        let ids = |group: &serde_json::Value| -> Vec<u64> {
+            group["notes"].as_array().unwrap().iter().map(|n| n["id"].as_u64().unwrap()).collect()
+        };
+        assert_eq!(ids(&groups[0]), triple.to_vec());
+        assert_eq!(ids(&groups[1]), pair.to_vec());
+        assert_eq!(groups[0]["notes"][2]["archived"], true, "archived notes still collide");
+
+        conn.execute("UPDATE notes SET title = 'Postgres tuning' WHERE id = ?1", [pair[1]]).unwrap();
+        assert_eq!(duplicate_titles(&conn).unwrap().len(), 1);
+    }
+}
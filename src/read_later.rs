@@ -0,0 +1,129 @@
+//! An ordered "read later" queue of notes
+//!
+//! Separate from the inbox (triage) and reminders (time-based): just a list the user
+//! arranges by hand. Positions are kept dense, `0..len`, in the `read_later` table.
+
+use crate::error::QuickNoteError;
+use crate::notes::{self, NoteSummary};
+use rusqlite::Connection;
+
+/// Queue a note at `position` (0 is the front), or at the end when `None` or past it.
+/// A note already queued is moved there instead.
+pub fn add(conn: &mut Connection, note_id: u64, position: Option<usize>) -> Result<(), QuickNoteError> {
+    let tx = conn.transaction()?;
+    let exists: bool = tx.query_row("SELECT COUNT(*) > 0 FROM notes WHERE id = ?1", [note_id], |row| row.get(0))?;
+    if !exists {
+        return Err(QuickNoteError::NotFound(note_id));
+    }
+
+    // Removing first closes its gap (read_later_ad), so the shift below stays dense
+    tx.execute("DELETE FROM read_later WHERE note_id = ?1", [note_id])?;
+    let len: usize = tx.query_row("SELECT COUNT(*) FROM read_later", [], |row| row.get(0))?;
+    let position = position.unwrap_or(len).min(len);
+    tx.execute("UPDATE read_later SET position = position + 1 WHERE position >= ?1", [position])?;
+    tx.execute("INSERT INTO read_later (note_id, position) VALUES (?1, ?2)", rusqlite::params![note_id, position])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Take a note off the queue; returns whether it was queued
+pub fn remove(conn: &Connection, note_id: u64) -> Result<bool, QuickNoteError> {
+    Ok(conn.execute("DELETE FROM read_later WHERE note_id = ?1", [note_id])? > 0)
+}
+
+/// Put the queue in the order of `note_ids`, which must list every queued note exactly once
+pub fn reorder(conn: &mut Connection, note_ids: &[u64]) -> Result<(), QuickNoteError> {
+    let tx = conn.transaction()?;
+    let mut queued: Vec<u64> = tx
+        .prepare("SELECT note_id FROM read_later")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let mut requested = note_ids.to_vec();
+    queued.sort_unstable();
+    requested.sort_unstable();
+    if queued != requested {
+        return Err(QuickNoteError::InvalidInput(
+            "Reorder must list every note in the read-later queue exactly once".to_string(),
+        ));
+    }
+
+    for (position, note_id) in note_ids.iter().enumerate() {
+        tx.execute("UPDATE read_later SET position = ?1 WHERE note_id = ?2", rusqlite::params![position, note_id])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// The queue, front first
+pub fn list(conn: &Connection) -> Result<Vec<NoteSummary>, QuickNoteError> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.knowledge_type, n.tags, n.encrypted FROM read_later r
+         JOIN notes n ON n.id = r.note_id
+         ORDER BY r.position",
+    )?;
+    notes::summaries(&mut stmt, [])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    #[test]
+    fn queue_lists_notes_with_corrupt_tags() {
+        let mut conn = test_vault();
+        let first = test_note(&conn, "First", "");
+        let corrupt = test_note(&conn, "Corrupt", "");
+        conn.execute(r#"UPDATE notes SET tags = '["later"' WHERE id = ?1"#, [corrupt]).unwrap();
+        add(&mut conn, first, None).unwrap();
+        add(&mut conn, corrupt, Some(0)).unwrap();
+
+        let queue = list(&conn).unwrap();
+        let ids: Vec<u64> = queue.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![corrupt, first]);
+        assert!(queue[0].tags.is_empty());
+    }
+
+    /// `(note_id, position)` rows as stored
+    fn positions(conn: &Connection) -> Vec<(u64, i64)> {
+        conn.prepare("SELECT note_id, position FROM read_later ORDER BY position")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn reorder_persists_a_dense_order() {
+        let mut conn = test_vault();
+        let a = test_note(&conn, "A", "");
+        let b = test_note(&conn, "B", "");
+        let c = test_note(&conn, "C", "");
+        for id in [a, b, c] {
+            add(&mut conn, id, None).unwrap();
+        }
+        assert_eq!(positions(&conn), vec![(a, 0), (b, 1), (c, 2)]);
+
+        reorder(&mut conn, &[c, a, b]).unwrap();
+        assert_eq!(positions(&conn), vec![(c, 0), (a, 1), (b, 2)]);
+        let titles: Vec<String> = list(&conn).unwrap().into_iter().map(|n| n.title).collect();
+        assert_eq!(titles, vec!["C", "A", "B"]);
+
+        // A partial or duplicated order is refused and changes nothing
+        assert!(matches!(reorder(&mut conn, &[a, b]), Err(QuickNoteError::InvalidInput(_))));
+        assert!(matches!(reorder(&mut conn, &[a, a, b]), Err(QuickNoteError::InvalidInput(_))));
+        assert_eq!(positions(&conn), vec![(c, 0), (a, 1), (b, 2)]);
+
+        // Moving an existing entry, removing one and deleting a note all leave no gaps
+        add(&mut conn, b, Some(0)).unwrap();
+        assert_eq!(positions(&conn), vec![(b, 0), (c, 1), (a, 2)]);
+        assert!(remove(&conn, c).unwrap());
+        assert!(!remove(&conn, c).unwrap());
+        assert_eq!(positions(&conn), vec![(b, 0), (a, 1)]);
+        conn.execute("DELETE FROM notes WHERE id = ?1", [b]).unwrap();
+        assert_eq!(positions(&conn), vec![(a, 0)]);
+
+        assert!(matches!(add(&mut conn, 999, None), Err(QuickNoteError::NotFound(999))));
+    }
+}
@@ -0,0 +1,1027 @@
+//! Spaced-repetition scheduling (SM-2) and review sessions
+
+use crate::config::Sm2Params;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::time::Instant;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Easiness of a card that has never been rated (the `review_easiness` column default)
+pub const STARTING_EASINESS: f64 = 2.5;
+
+/// Highest easiness treated as SM-2's own: each perfect answer adds 0.1, so a card needs
+/// 75 of them to get here. Anything above came from another tool's scale.
+pub const MAX_EASINESS: f64 = 10.0;
+
+/// Answer buttons shown in review mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Rating {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "again" => Some(Self::Again),
+            "hard" => Some(Self::Hard),
+            "good" => Some(Self::Good),
+            "easy" => Some(Self::Easy),
+            _ => None,
+        }
+    }
+
+    /// Value stored in `review_log.rating`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Again => "again",
+            Self::Hard => "hard",
+            Self::Good => "good",
+            Self::Easy => "easy",
+        }
+    }
+
+    /// SM-2 response quality (0-5)
+    fn quality(&self) -> f64 {
+        match self {
+            Self::Again => 0.0,
+            Self::Hard => 3.0,
+            Self::Good => 4.0,
+            Self::Easy => 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReviewCard {
+    pub id: u64,
+    pub title: String,
+    pub content: String,
+    pub knowledge_type: Option<String>,
+    pub encrypted: bool,
+}
+
+/// Cards due now, most overdue first
+pub fn due_cards(conn: &Connection, limit: Option<usize>) -> Result<Vec<ReviewCard>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, CASE WHEN encrypted THEN '' ELSE content END, knowledge_type, encrypted FROM notes
+         WHERE review_due IS NOT NULL AND review_due <= strftime('%s', 'now')
+           AND review_suspended = 0 AND is_archived = 0
+         ORDER BY review_due ASC, id ASC
+         LIMIT ?1",
+    )?;
+    // SQLite treats a negative LIMIT as "no limit"
+    let limit = limit.map(|l| l as i64).unwrap_or(-1);
+
+    let cards = stmt.query_map([limit], |row| {
+        Ok(ReviewCard {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            knowledge_type: row.get(3)?,
+            encrypted: row.get(4)?,
+        })
+    })?;
+    cards.collect()
+}
+
+/// The one card to review next, for a one-at-a-time UI: the most overdue, with ties
+/// (cards due the same second, as after a bulk enroll) broken by id or, with `shuffle_ties`,
+/// at random. `None` once nothing is due.
+pub fn next_card(conn: &Connection, shuffle_ties: bool) -> Result<Option<ReviewCard>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id, title, CASE WHEN encrypted THEN '' ELSE content END, knowledge_type, encrypted FROM notes
+         WHERE review_due IS NOT NULL AND review_due <= strftime('%s', 'now')
+           AND review_suspended = 0 AND is_archived = 0
+         ORDER BY review_due ASC, CASE WHEN ?1 THEN random() ELSE id END ASC
+         LIMIT 1",
+        [shuffle_ties],
+        |row| {
+            Ok(ReviewCard {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                content: row.get(2)?,
+                knowledge_type: row.get(3)?,
+                encrypted: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Number of cards due now (what `due_cards(conn, None)` would return)
+pub fn due_count(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM notes
+         WHERE review_due IS NOT NULL AND review_due <= strftime('%s', 'now')
+           AND review_suspended = 0 AND is_archived = 0",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Longest horizon `forecast` will compute
+const MAX_FORECAST_DAYS: u32 = 365;
+
+/// Upcoming workload: `{date: "overdue", count}` for cards due before today, then one
+/// `{date: "YYYY-MM-DD", count}` per day for `days` days starting today (UTC). Suspended
+/// and archived cards are left out, as in `due_cards`.
+pub fn forecast(conn: &Connection, days: u32) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let overdue: u64 = conn.query_row(
+        "SELECT COUNT(*) FROM notes
+         WHERE review_due < CAST(strftime('%s', date('now')) AS INTEGER)
+           AND review_suspended = 0 AND is_archived = 0",
+        [],
+        |row| row.get(0),
+    )?;
+    let mut buckets = vec![serde_json::json!({ "date": "overdue", "count": overdue })];
+
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE days(n, day) AS (
+             SELECT 0, date('now') WHERE ?1 > 0
+             UNION ALL
+             SELECT n + 1, date('now', '+' || (n + 1) || ' days') FROM days WHERE n + 1 < ?1
+         ),
+         due(day, count) AS (
+             SELECT date(review_due, 'unixepoch'), COUNT(*) FROM notes
+             WHERE review_due IS NOT NULL AND review_suspended = 0 AND is_archived = 0
+             GROUP BY 1
+         )
+         SELECT days.day, COALESCE(due.count, 0) FROM days LEFT JOIN due ON due.day = days.day
+         ORDER BY days.n",
+    )?;
+    let rows = stmt.query_map([days.min(MAX_FORECAST_DAYS)], |row| {
+        Ok(serde_json::json!({
+            "date": row.get::<_, String>(0)?,
+            "count": row.get::<_, u64>(1)?,
+        }))
+    })?;
+    for row in rows {
+        buckets.push(row?);
+    }
+    Ok(buckets)
+}
+
+/// Cards the user struggles with: lowest easiness first, then most lapses. Only cards
+/// rated at least once (a nonzero interval) count, since unrated cards all sit at the
+/// starting easiness.
+pub fn hardest_cards(conn: &Connection, limit: usize) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, review_easiness, review_streak, review_lapses FROM notes
+         WHERE review_due IS NOT NULL AND review_interval > 0 AND is_archived = 0
+         ORDER BY review_easiness ASC, review_lapses DESC, id ASC
+         LIMIT ?1",
+    )?;
+    let rows = stmt.query_map([limit as i64], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+            "easiness": row.get::<_, f64>(3)?,
+            "streak": row.get::<_, i64>(4)?,
+            "lapses": row.get::<_, i64>(5)?,
+        }))
+    })?;
+    rows.collect()
+}
+
+/// "Leeches": cards lapsed at least `min_lapses` times, most lapses first. These are
+/// worth rewriting (or suspending), since another round of reviews rarely fixes them.
+/// Already suspended cards are included, flagged `suspended`.
+pub fn leeches(conn: &Connection, min_lapses: i64) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, review_lapses, review_easiness, review_suspended FROM notes
+         WHERE review_due IS NOT NULL AND review_lapses >= ?1 AND is_archived = 0
+         ORDER BY review_lapses DESC, review_easiness ASC, id ASC",
+    )?;
+    let rows = stmt.query_map([min_lapses.max(1)], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+            "lapses": row.get::<_, i64>(3)?,
+            "easiness": row.get::<_, f64>(4)?,
+            "suspended": row.get::<_, bool>(5)?,
+        }))
+    })?;
+    rows.collect()
+}
+
+/// Take a card out of (or put it back into) the review queue without losing its schedule.
+/// Returns whether the card exists.
+pub fn set_suspended(conn: &Connection, id: u64, suspended: bool) -> Result<bool, rusqlite::Error> {
+    let changed = conn.execute("UPDATE notes SET review_suspended = ?1 WHERE id = ?2", rusqlite::params![suspended, id])?;
+    Ok(changed > 0)
+}
+
+/// Active notes not enrolled in review (no `review_due`), oldest first
+pub fn unenrolled_notes(conn: &Connection) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type FROM notes
+         WHERE review_due IS NULL AND is_archived = 0
+         ORDER BY created_at_ms, id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+        }))
+    })?;
+    rows.collect()
+}
+
+/// Enroll notes in review, due now. Notes already enrolled keep their schedule.
+/// Returns the number of notes newly enrolled.
+pub fn enroll(conn: &mut Connection, ids: &[u64]) -> Result<usize, rusqlite::Error> {
+    let tx = conn.transaction()?;
+    let mut enrolled = 0;
+    for id in ids {
+        enrolled += tx.execute(
+            "UPDATE notes SET review_due = strftime('%s', 'now') WHERE id = ?1 AND review_due IS NULL",
+            [id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(enrolled)
+}
+
+/// A card's review state, as captured by `rate_card` for undo
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewSnapshot {
+    pub id: u64,
+    pub due: Option<i64>,
+    pub interval: i64,
+    pub streak: i64,
+    pub easiness: f64,
+    pub lapses: i64,
+    pub suspended: bool,
+    /// `review_log` row written by the rating, removed again on restore
+    pub log_id: Option<i64>,
+}
+
+/// Apply an SM-2 rating to a card, schedule its next review and log it to `review_log`
+/// (in one transaction, so the log never disagrees with the card). A lapse that brings the
+/// card to `params.leech_lapses` suspends it.
+/// Returns the card's state from before the rating, which `restore` can put back.
+pub fn rate_card(conn: &mut Connection, id: u64, rating: Rating, params: &Sm2Params) -> Result<ReviewSnapshot, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let mut before = tx.query_row(
+        "SELECT review_due, review_interval, review_streak, review_easiness, review_lapses, review_suspended
+         FROM notes WHERE id = ?1",
+        [id],
+        |row| {
+            Ok(ReviewSnapshot {
+                id,
+                due: row.get(0)?,
+                interval: row.get(1)?,
+                streak: row.get(2)?,
+                easiness: row.get(3)?,
+                lapses: row.get(4)?,
+                suspended: row.get(5)?,
+                log_id: None,
+            })
+        },
+    )?;
+    let (interval, streak, easiness, lapses) = (before.interval, before.streak, before.easiness, before.lapses);
+
+    let quality = rating.quality();
+    let (interval, streak, lapses) = if quality < 3.0 {
+        // Lapse: start the card over
+        (params.first_interval, 0, lapses + 1)
+    } else {
+        let streak = streak + 1;
+        let interval = match streak {
+            1 => params.first_interval,
+            2 => params.second_interval,
+            _ => ((interval as f64) * easiness).round() as i64,
+        };
+        (interval, streak, lapses)
+    };
+    let interval = fuzz_interval(interval, id, streak + lapses, params.interval_fuzz_percent);
+    let miss = 5.0 - quality;
+    let suspended = before.suspended || (lapses > before.lapses && params.leech_lapses.is_some_and(|limit| lapses >= limit));
+    let easiness = (easiness + params.easiness_bonus - miss * (params.penalty_linear + miss * params.penalty_quadratic))
+        .max(params.easiness_floor);
+
+    tx.execute(
+        "UPDATE notes
+         SET review_interval = ?1, review_streak = ?2, review_easiness = ?3, review_lapses = ?4,
+             review_due = strftime('%s', 'now') + ?5, review_suspended = ?6
+         WHERE id = ?7",
+        rusqlite::params![interval, streak, easiness, lapses, interval * SECONDS_PER_DAY, suspended, id],
+    )?;
+    tx.execute(
+        "INSERT INTO review_log (note_id, rating, easiness, interval) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![id, rating.as_str(), easiness, interval],
+    )?;
+    before.log_id = Some(tx.last_insert_rowid());
+    tx.commit()?;
+    Ok(before)
+}
+
+/// Spread `interval` by up to ± `percent`, never below one day. The offset is derived
+/// from the card id and `step` (how far along the card is), so a given card and rating
+/// history always get the same schedule while different cards scatter.
+fn fuzz_interval(interval: i64, id: u64, step: i64, percent: f64) -> i64 {
+    if percent <= 0.0 {
+        return interval;
+    }
+    // splitmix64, mapped onto -1.0..1.0
+    let mut z = id.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (step as u64);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    let unit = (z >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+
+    ((interval as f64) * (1.0 + unit * percent / 100.0)).round().max(1.0) as i64
+}
+
+/// Rescale imported cards' easiness from their deck's scale, `min..=max` (e.g. Anki's
+/// 1300-2500 ease factors), onto `easiness_floor..=STARTING_EASINESS`. Values outside the
+/// source scale are clamped to it first, so the result always schedules sensibly. Only
+/// enrolled cards whose easiness isn't an SM-2 value (below `easiness_floor` or above
+/// `MAX_EASINESS`) are touched; the vault's own cards and unenrolled notes keep theirs.
+/// Runs in one transaction; returns the number of cards whose easiness changed.
+pub fn normalize_easiness(conn: &mut Connection, min: f64, max: f64, params: &Sm2Params) -> Result<usize, Box<dyn std::error::Error>> {
+    if !(min.is_finite() && max.is_finite() && min < max) {
+        return Err(format!("Invalid easiness scale {}..{}: min must be below max", min, max).into());
+    }
+    let (low, high) = (params.easiness_floor, STARTING_EASINESS.max(params.easiness_floor));
+
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    {
+        let mut stmt = tx.prepare(
+            "SELECT id, review_easiness FROM notes
+             WHERE review_due IS NOT NULL AND (review_easiness < ?1 OR review_easiness > ?2)",
+        )?;
+        let cards = stmt.query_map(rusqlite::params![low, MAX_EASINESS], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, f64>(1)?)))?.collect::<Result<Vec<_>, _>>()?;
+        for (id, easiness) in cards {
+            let scaled = low + (easiness.clamp(min, max) - min) * (high - low) / (max - min);
+            if (scaled - easiness).abs() > 1e-9 {
+                tx.execute("UPDATE notes SET review_easiness = ?1 WHERE id = ?2", rusqlite::params![scaled, id])?;
+                changed += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Put a card's review state back as it was in `snapshot`, dropping the rating's log entry
+pub fn restore(conn: &mut Connection, snapshot: &ReviewSnapshot) -> Result<(), rusqlite::Error> {
+    let tx = conn.transaction()?;
+    if let Some(log_id) = snapshot.log_id {
+        tx.execute("DELETE FROM review_log WHERE id = ?1", [log_id])?;
+    }
+    tx.execute(
+        "UPDATE notes
+         SET review_due = ?1, review_interval = ?2, review_streak = ?3, review_easiness = ?4, review_lapses = ?5,
+             review_suspended = ?6
+         WHERE id = ?7",
+        rusqlite::params![snapshot.due, snapshot.interval, snapshot.streak, snapshot.easiness, snapshot.lapses, snapshot.suspended, snapshot.id],
+    )?;
+    tx.commit()
+}
+
+/// Every logged rating of a card, oldest first: `{reviewed_at, rating, easiness, interval}`
+/// with the easiness and interval (days) that rating produced
+pub fn card_history(conn: &Connection, id: u64) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT reviewed_at, rating, easiness, interval FROM review_log
+         WHERE note_id = ?1
+         ORDER BY reviewed_at, id",
+    )?;
+    let rows = stmt.query_map([id], |row| {
+        Ok(serde_json::json!({
+            "reviewed_at": row.get::<_, i64>(0)?,
+            "rating": row.get::<_, String>(1)?,
+            "easiness": row.get::<_, f64>(2)?,
+            "interval": row.get::<_, i64>(3)?,
+        }))
+    })?;
+    rows.collect()
+}
+
+/// Logged ratings across all cards, newest first: `{note_id, title, rating, reviewed_at,
+/// interval}`. `from` and `to` (epoch seconds, both inclusive) bound `reviewed_at`;
+/// `rating` keeps only that rating, e.g. `Again` to find struggle points.
+pub fn review_log(
+    conn: &Connection,
+    from: Option<i64>,
+    to: Option<i64>,
+    rating: Option<Rating>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<serde_json::Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT l.note_id, n.title, l.rating, l.reviewed_at, l.interval FROM review_log l
+         JOIN notes n ON n.id = l.note_id
+         WHERE (?1 IS NULL OR l.reviewed_at >= ?1)
+           AND (?2 IS NULL OR l.reviewed_at <= ?2)
+           AND (?3 IS NULL OR l.rating = ?3)
+         ORDER BY l.reviewed_at DESC, l.id DESC
+         LIMIT ?4 OFFSET ?5",
+    )?;
+    let params = rusqlite::params![from, to, rating.map(|r| r.as_str()), limit as i64, offset as i64];
+    let rows = stmt.query_map(params, |row| {
+        Ok(serde_json::json!({
+            "note_id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "rating": row.get::<_, String>(2)?,
+            "reviewed_at": row.get::<_, i64>(3)?,
+            "interval": row.get::<_, i64>(4)?,
+        }))
+    })?;
+    rows.collect()
+}
+
+/// A bounded study session. Lives in memory only: every rating is already persisted
+/// by `rate_card`, so closing the app mid-session just loses the session stats.
+#[derive(Debug)]
+pub struct ReviewSession {
+    started_at: Instant,
+    max_minutes: u32,
+    card_ids: Vec<u64>,
+    ratings: Vec<(u64, Rating)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionStats {
+    pub cards_total: usize,
+    pub cards_done: usize,
+    pub accuracy: f64,
+    pub elapsed_secs: u64,
+    pub time_limit_reached: bool,
+    /// Seconds left before the time limit; `None` for an untimed session
+    pub remaining_secs: Option<u64>,
+}
+
+impl ReviewSession {
+    pub fn new(card_ids: Vec<u64>, max_minutes: u32) -> Self {
+        Self {
+            started_at: Instant::now(),
+            max_minutes,
+            card_ids,
+            ratings: Vec::new(),
+        }
+    }
+
+    /// Seconds left before `max_minutes` runs out; `None` without a limit
+    pub fn remaining_secs(&self) -> Option<u64> {
+        let limit = u64::from(self.max_minutes) * 60;
+        (self.max_minutes > 0).then(|| limit.saturating_sub(self.started_at.elapsed().as_secs()))
+    }
+
+    /// Whether a rating of card `id` must be refused: it's one of this session's cards
+    /// and the session's time is up
+    pub fn refuses(&self, id: u64) -> bool {
+        self.card_ids.contains(&id) && self.remaining_secs() == Some(0)
+    }
+
+    /// Record a rating if the card belongs to this session and its time isn't up
+    pub fn record(&mut self, id: u64, rating: Rating) {
+        if self.card_ids.contains(&id) && self.remaining_secs() != Some(0) {
+            self.ratings.retain(|(card, _)| *card != id);
+            self.ratings.push((id, rating));
+        }
+    }
+
+    /// Drop the card's rating again, e.g. after the rating was undone
+    pub fn forget(&mut self, id: u64) {
+        self.ratings.retain(|(card, _)| *card != id);
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        let elapsed = self.started_at.elapsed().as_secs();
+        let remaining_secs = self.remaining_secs();
+        let correct = self.ratings.iter().filter(|(_, r)| *r != Rating::Again).count();
+        let accuracy = if self.ratings.is_empty() {
+            0.0
+        } else {
+            correct as f64 / self.ratings.len() as f64
+        };
+
+        SessionStats {
+            cards_total: self.card_ids.len(),
+            cards_done: self.ratings.len(),
+            accuracy,
+            elapsed_secs: elapsed,
+            time_limit_reached: remaining_secs == Some(0),
+            remaining_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+    use std::time::Duration;
+
+    fn due_note(conn: &Connection, title: &str, due: i64) -> u64 {
+        let id = test_note(conn, title, "body");
+        conn.execute("UPDATE notes SET review_due = ?1 WHERE id = ?2", [due, id as i64]).unwrap();
+        id
+    }
+
+    #[test]
+    fn session_batch_is_most_overdue_first_and_capped() {
+        let conn = test_vault();
+        let later = due_note(&conn, "later", 300);
+        let first = due_note(&conn, "first", 100);
+        let second = due_note(&conn, "second", 200);
+
+        let cards = due_cards(&conn, Some(2)).unwrap();
+        assert_eq!(cards.iter().map(|c| c.id).collect::<Vec<_>>(), vec![first, second]);
+
+        // Archived cards drop out of the queue
+        crate::notes::set_archived(&conn, first, true).unwrap();
+        assert_eq!(due_cards(&conn, Some(1)).unwrap()[0].id, second);
+        assert_eq!(due_cards(&conn, None).unwrap().last().map(|c| c.id), Some(later));
+    }
+
+    #[test]
+    fn session_stats_count_only_session_cards() {
+        let mut session = ReviewSession::new(vec![1, 2, 3], 0);
+        session.record(1, Rating::Good);
+        session.record(2, Rating::Again);
+        session.record(9, Rating::Good);
+
+        let stats = session.stats();
+        assert_eq!(stats.cards_total, 3);
+        assert_eq!(stats.cards_done, 2);
+        assert_eq!(stats.accuracy, 0.5);
+        assert!(!stats.time_limit_reached);
+    }
+
+    #[test]
+    fn session_rerating_and_undo_replace_the_rating() {
+        let mut session = ReviewSession::new(vec![1, 2], 0);
+        session.record(1, Rating::Again);
+        session.record(1, Rating::Easy);
+        assert_eq!(session.stats().cards_done, 1);
+        assert_eq!(session.stats().accuracy, 1.0);
+
+        session.forget(1);
+        assert_eq!(session.stats().cards_done, 0);
+        assert_eq!(session.stats().accuracy, 0.0);
+    }
+
+    #[test]
+    fn session_reports_time_limit() {
+        let mut session = ReviewSession::new(vec![1], 1);
+        assert!(!session.stats().time_limit_reached);
+
+        session.started_at = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        assert!(session.stats().time_limit_reached);
+    }
+
+    #[test]
+    fn expired_session_refuses_its_cards() {
+        let mut session = ReviewSession::new(vec![1, 2], 1);
+        assert!(matches!(session.remaining_secs(), Some(59..=60)));
+        session.record(1, Rating::Good);
+        assert!(!session.refuses(2));
+
+        session.started_at = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        assert_eq!(session.remaining_secs(), Some(0));
+        assert!(session.refuses(2));
+        assert!(!session.refuses(3), "cards outside the session can still be rated");
+        session.record(2, Rating::Again);
+        let stats = session.stats();
+        assert_eq!((stats.cards_done, stats.accuracy, stats.remaining_secs), (1, 1.0, Some(0)));
+
+        let untimed = ReviewSession::new(vec![1], 0);
+        assert_eq!(untimed.stats().remaining_secs, None);
+        assert!(!untimed.refuses(1));
+    }
+
+    fn interval_after_hard_reviews(floor: f64) -> i64 {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "card", 0);
+        conn.execute(
+            "UPDATE notes SET review_interval = 100, review_streak = 3, review_easiness = 1.3 WHERE id = ?1",
+            [id],
+        )
+        .unwrap();
+
+        let params = Sm2Params { easiness_floor: floor, ..Sm2Params::default() };
+        for _ in 0..3 {
+            rate_card(&mut conn, id, Rating::Hard, &params).unwrap();
+        }
+        conn.query_row("SELECT review_interval FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn lower_easiness_floor_lets_intervals_shrink_further() {
+        // 100 * 1.3 * 1.3 * 1.3 with the default floor; easiness sinks to 1.0 otherwise
+        assert_eq!(interval_after_hard_reviews(1.3), 220);
+        assert_eq!(interval_after_hard_reviews(1.0), 154);
+    }
+
+    #[test]
+    fn due_count_skips_future_suspended_and_archived_cards() {
+        let conn = test_vault();
+        due_note(&conn, "due", 100);
+        due_note(&conn, "future", 4_102_444_800);
+        let suspended = due_note(&conn, "suspended", 100);
+        let archived = due_note(&conn, "archived", 100);
+        test_note(&conn, "not enrolled", "");
+        conn.execute("UPDATE notes SET review_suspended = 1 WHERE id = ?1", [suspended]).unwrap();
+        conn.execute("UPDATE notes SET is_archived = 1 WHERE id = ?1", [archived]).unwrap();
+
+        assert_eq!(due_count(&conn).unwrap(), 1);
+    }
+
+    fn unenrolled_ids(conn: &Connection) -> Vec<u64> {
+        unenrolled_notes(conn).unwrap().iter().map(|note| note["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn unenrolled_lists_only_notes_without_review_data() {
+        let conn = test_vault();
+        let bare = test_note(&conn, "bare", "body");
+        due_note(&conn, "enrolled", 100);
+        let archived = test_note(&conn, "archived", "body");
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        assert_eq!(unenrolled_ids(&conn), vec![bare]);
+    }
+
+    #[test]
+    fn enroll_keeps_existing_schedules() {
+        let mut conn = test_vault();
+        let bare = test_note(&conn, "bare", "body");
+        let enrolled = due_note(&conn, "enrolled", 100);
+
+        assert_eq!(enroll(&mut conn, &[bare, enrolled]).unwrap(), 1);
+        assert!(unenrolled_ids(&conn).is_empty());
+        let due: i64 = conn.query_row("SELECT review_due FROM notes WHERE id = ?1", [enrolled], |row| row.get(0)).unwrap();
+        assert_eq!(due, 100);
+    }
+
+    fn review_state(conn: &Connection, id: u64) -> (Option<i64>, i64, i64, f64, i64) {
+        conn.query_row(
+            "SELECT review_due, review_interval, review_streak, review_easiness, review_lapses FROM notes WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn undo_restores_pre_rating_state() {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        conn.execute(
+            "UPDATE notes SET review_interval = 6, review_streak = 2, review_easiness = 2.4 WHERE id = ?1",
+            [id],
+        )
+        .unwrap();
+        let before = review_state(&conn, id);
+
+        let snapshot = rate_card(&mut conn, id, Rating::Again, &Sm2Params::default()).unwrap();
+        assert_ne!(review_state(&conn, id), before);
+        assert_eq!(card_history(&conn, id).unwrap().len(), 1);
+
+        restore(&mut conn, &snapshot).unwrap();
+        assert_eq!(review_state(&conn, id), before);
+        assert!(card_history(&conn, id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn undone_rating_drops_out_of_session_stats() {
+        let mut session = ReviewSession::new(vec![1, 2], 0);
+        session.record(1, Rating::Good);
+        session.record(2, Rating::Again);
+        session.forget(2);
+        let stats = session.stats();
+        assert_eq!(stats.cards_done, 1);
+        assert_eq!(stats.accuracy, 1.0);
+    }
+
+    /// A card due `days` days after today's midnight (UTC), plus an hour
+    fn due_in_days(conn: &Connection, days: i64) -> u64 {
+        let midnight: i64 = conn.query_row("SELECT CAST(strftime('%s', date('now')) AS INTEGER)", [], |row| row.get(0)).unwrap();
+        due_note(conn, "card", midnight + days * 86_400 + 3_600)
+    }
+
+    #[test]
+    fn forecast_buckets_staggered_due_dates() {
+        let conn = test_vault();
+        due_in_days(&conn, -1);
+        due_in_days(&conn, -30);
+        due_in_days(&conn, 0);
+        due_in_days(&conn, 2);
+        due_in_days(&conn, 2);
+        due_in_days(&conn, 3);
+        let suspended = due_in_days(&conn, 1);
+        conn.execute("UPDATE notes SET review_suspended = 1 WHERE id = ?1", [suspended]).unwrap();
+        test_note(&conn, "unenrolled", "body");
+
+        let buckets = forecast(&conn, 3).unwrap();
+        let counts: Vec<u64> = buckets.iter().map(|b| b["count"].as_u64().unwrap()).collect();
+        assert_eq!(counts, vec![2, 1, 0, 2]);
+        assert_eq!(buckets[0]["date"], "overdue");
+        let today: String = conn.query_row("SELECT date('now')", [], |row| row.get(0)).unwrap();
+        assert_eq!(buckets[1]["date"], today.as_str());
+    }
+
+    #[test]
+    fn forecast_with_no_days_is_just_overdue() {
+        let conn = test_vault();
+        due_in_days(&conn, -2);
+        assert_eq!(forecast(&conn, 0).unwrap(), vec![serde_json::json!({ "date": "overdue", "count": 1 })]);
+    }
+
+    #[test]
+    fn again_ratings_count_lapses() {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        let params = Sm2Params::default();
+        rate_card(&mut conn, id, Rating::Again, &params).unwrap();
+        rate_card(&mut conn, id, Rating::Good, &params).unwrap();
+        rate_card(&mut conn, id, Rating::Again, &params).unwrap();
+        assert_eq!(review_state(&conn, id).4, 2);
+    }
+
+    #[test]
+    fn hardest_cards_order_by_easiness_then_lapses() {
+        let conn = test_vault();
+        let set = |title: &str, easiness: f64, lapses: i64| {
+            let id = due_note(&conn, title, 100);
+            conn.execute(
+                "UPDATE notes SET review_interval = 3, review_easiness = ?1, review_lapses = ?2 WHERE id = ?3",
+                rusqlite::params![easiness, lapses, id],
+            )
+            .unwrap();
+            id
+        };
+        let easy = set("easy", 2.6, 0);
+        let hard = set("hard", 1.3, 1);
+        let hardest = set("hardest", 1.3, 4);
+        // Never reviewed (interval 0) and archived cards are left out
+        due_note(&conn, "new", 100);
+        let archived = set("archived", 1.3, 9);
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        let cards = hardest_cards(&conn, 10).unwrap();
+        let ids: Vec<u64> = cards.iter().map(|c| c["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, vec![hardest, hard, easy]);
+        assert_eq!(cards[0]["title"], "hardest");
+        assert_eq!(cards[0]["lapses"], 4);
+        assert_eq!(cards[0]["easiness"], 1.3);
+        assert_eq!(hardest_cards(&conn, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn card_history_follows_each_rating() {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        let params = Sm2Params { interval_fuzz_percent: 0.0, ..Sm2Params::default() };
+
+        let mut expected = Vec::new();
+        for rating in [Rating::Good, Rating::Good, Rating::Hard, Rating::Again] {
+            rate_card(&mut conn, id, rating, &params).unwrap();
+            let (_, interval, _, easiness, _) = review_state(&conn, id);
+            expected.push((rating.as_str().to_string(), easiness, interval));
+        }
+
+        let history = card_history(&conn, id).unwrap();
+        let logged: Vec<(String, f64, i64)> = history
+            .iter()
+            .map(|h| (h["rating"].as_str().unwrap().to_string(), h["easiness"].as_f64().unwrap(), h["interval"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(logged, expected);
+        let intervals: Vec<i64> = logged.iter().map(|(_, _, interval)| *interval).collect();
+        assert_eq!(intervals[..2], [params.first_interval, params.second_interval]);
+        assert_eq!(intervals[3], params.first_interval);
+        assert!(card_history(&conn, id + 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_log_write_leaves_the_card_unrated() {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        let before = review_state(&conn, id);
+        conn.execute_batch(
+            "CREATE TRIGGER fail_log BEFORE INSERT ON review_log BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+        )
+        .unwrap();
+
+        assert!(rate_card(&mut conn, id, Rating::Good, &Sm2Params::default()).is_err());
+        assert_eq!(review_state(&conn, id), before);
+    }
+
+    fn log_rating(conn: &Connection, id: u64, rating: Rating, at: i64) {
+        conn.execute(
+            "INSERT INTO review_log (note_id, rating, easiness, interval, reviewed_at) VALUES (?1, ?2, 2.5, ?3, ?3)",
+            rusqlite::params![id, rating.as_str(), at],
+        )
+        .unwrap();
+    }
+
+    fn logged_at(entries: &[serde_json::Value]) -> Vec<i64> {
+        entries.iter().map(|e| e["reviewed_at"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn review_log_filters_by_inclusive_date_range_newest_first() {
+        let conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        for (rating, at) in [(Rating::Good, 10), (Rating::Again, 20), (Rating::Good, 30), (Rating::Again, 40)] {
+            log_rating(&conn, id, rating, at);
+        }
+
+        assert_eq!(logged_at(&review_log(&conn, None, None, None, 10, 0).unwrap()), [40, 30, 20, 10]);
+        assert_eq!(logged_at(&review_log(&conn, Some(20), Some(30), None, 10, 0).unwrap()), [30, 20]);
+        assert_eq!(logged_at(&review_log(&conn, Some(31), None, None, 10, 0).unwrap()), [40]);
+
+        let first = &review_log(&conn, None, Some(10), None, 10, 0).unwrap()[0];
+        assert_eq!(first["title"], "card");
+        assert_eq!((first["note_id"].as_u64(), first["interval"].as_i64()), (Some(id), Some(10)));
+    }
+
+    #[test]
+    fn review_log_filters_by_rating_and_pages() {
+        let conn = test_vault();
+        let id = due_note(&conn, "card", 100);
+        for (rating, at) in [(Rating::Good, 10), (Rating::Again, 20), (Rating::Good, 30), (Rating::Again, 40)] {
+            log_rating(&conn, id, rating, at);
+        }
+
+        assert_eq!(logged_at(&review_log(&conn, None, None, Some(Rating::Again), 10, 0).unwrap()), [40, 20]);
+        assert_eq!(logged_at(&review_log(&conn, None, None, None, 2, 1).unwrap()), [30, 20]);
+        assert!(review_log(&conn, None, None, Some(Rating::Easy), 10, 0).unwrap().is_empty());
+    }
+
+    fn leech_ids(conn: &Connection, min_lapses: i64) -> Vec<u64> {
+        leeches(conn, min_lapses).unwrap().iter().map(|card| card["id"].as_u64().unwrap()).collect()
+    }
+
+    fn suspended(conn: &Connection, id: u64) -> bool {
+        conn.query_row("SELECT review_suspended FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn cards_past_the_lapse_threshold_are_leeches() {
+        let mut conn = test_vault();
+        let leech = due_note(&conn, "leech", 100);
+        let shaky = due_note(&conn, "shaky", 100);
+        let params = Sm2Params::default();
+        for _ in 0..3 {
+            rate_card(&mut conn, leech, Rating::Again, &params).unwrap();
+        }
+        rate_card(&mut conn, shaky, Rating::Again, &params).unwrap();
+        assert!(leech_ids(&conn, 4).is_empty());
+
+        let reported = leeches(&conn, 3).unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!((reported[0]["id"].as_u64(), reported[0]["lapses"].as_i64()), (Some(leech), Some(3)));
+        // Without leech_lapses configured nothing is suspended
+        assert_eq!(reported[0]["suspended"], false);
+        assert_eq!(leech_ids(&conn, 1), vec![leech, shaky]);
+    }
+
+    #[test]
+    fn leeches_are_auto_suspended_when_configured() {
+        let mut conn = test_vault();
+        let id = due_note(&conn, "leech", 100);
+        let params = Sm2Params { leech_lapses: Some(2), ..Sm2Params::default() };
+
+        rate_card(&mut conn, id, Rating::Again, &params).unwrap();
+        assert!(!suspended(&conn, id));
+        let snapshot = rate_card(&mut conn, id, Rating::Again, &params).unwrap();
+        assert!(suspended(&conn, id));
+        assert_eq!(leeches(&conn, 2).unwrap()[0]["suspended"], true);
+
+        // Undoing the lapse un-suspends; resuming by hand keeps it resumed until the next lapse
+        restore(&mut conn, &snapshot).unwrap();
+        assert!(!suspended(&conn, id));
+        rate_card(&mut conn, id, Rating::Again, &params).unwrap();
+        assert!(set_suspended(&conn, id, false).unwrap());
+        rate_card(&mut conn, id, Rating::Good, &params).unwrap();
+        assert!(!suspended(&conn, id));
+        assert!(!set_suspended(&conn, 999, true).unwrap());
+    }
+
+    #[test]
+    fn next_card_walks_the_queue_until_empty() {
+        let mut conn = test_vault();
+        let later = due_note(&conn, "later", 200);
+        let first = due_note(&conn, "first", 100);
+        let tied = due_note(&conn, "tied", 200);
+        let suspended = due_note(&conn, "suspended", 50);
+        conn.execute("UPDATE notes SET review_suspended = 1 WHERE id = ?1", [suspended]).unwrap();
+        due_note(&conn, "future", i64::MAX / 2);
+
+        let mut seen = Vec::new();
+        while let Some(card) = next_card(&conn, false).unwrap() {
+            seen.push(card.id);
+            rate_card(&mut conn, card.id, Rating::Good, &Sm2Params::default()).unwrap();
+            assert!(seen.len() <= 3, "rated cards must leave the queue");
+        }
+        // Most overdue first, ties by id
+        assert_eq!(seen, vec![first, later, tied]);
+        assert!(next_card(&conn, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn shuffled_ties_still_respect_overdueness() {
+        let conn = test_vault();
+        let oldest = due_note(&conn, "oldest", 100);
+        let tied: Vec<u64> = (0..5).map(|i| due_note(&conn, &format!("tied {}", i), 200)).collect();
+
+        assert_eq!(next_card(&conn, true).unwrap().unwrap().id, oldest);
+        conn.execute("UPDATE notes SET review_due = NULL WHERE id = ?1", [oldest]).unwrap();
+        for _ in 0..20 {
+            assert!(tied.contains(&next_card(&conn, true).unwrap().unwrap().id));
+        }
+    }
+
+    #[test]
+    fn imported_easiness_is_rescaled_into_sm2_range() {
+        let mut conn = test_vault();
+        let easiness = |conn: &Connection, id: u64| -> f64 {
+            conn.query_row("SELECT review_easiness FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+        };
+        // Anki-style ease factors, including outliers on both sides
+        let mut ids = Vec::new();
+        for (i, ease) in [1300.0, 1900.0, 2500.0, 9000.0, 100.0].into_iter().enumerate() {
+            let id = due_note(&conn, &format!("Card {}", i), 100);
+            conn.execute("UPDATE notes SET review_easiness = ?1 WHERE id = ?2", rusqlite::params![ease, id]).unwrap();
+            ids.push(id);
+        }
+
+        assert_eq!(normalize_easiness(&mut conn, 1300.0, 2500.0, &Sm2Params::default()).unwrap(), 5);
+        let scaled: Vec<f64> = ids.iter().map(|&id| easiness(&conn, id)).collect();
+        let expected = [1.3, 1.9, 2.5, 2.5, 1.3];
+        for (got, want) in scaled.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-9, "{:?}", scaled);
+        }
+
+        assert!(normalize_easiness(&mut conn, 2500.0, 1300.0, &Sm2Params::default()).is_err());
+        assert!(normalize_easiness(&mut conn, 1300.0, f64::NAN, &Sm2Params::default()).is_err());
+    }
+
+    #[test]
+    fn normalizing_leaves_native_cards_and_unenrolled_notes_alone() {
+        let mut conn = test_vault();
+        let easiness = |conn: &Connection, id: u64| -> f64 {
+            conn.query_row("SELECT review_easiness FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+        };
+        let native = due_note(&conn, "Native card", 100);
+        let seasoned = due_note(&conn, "Seasoned card", 100);
+        conn.execute("UPDATE notes SET review_easiness = 3.1 WHERE id = ?1", [seasoned]).unwrap();
+        let unenrolled = test_note(&conn, "Plain note", "body");
+        let imported = due_note(&conn, "Imported card", 100);
+        conn.execute("UPDATE notes SET review_easiness = 2500 WHERE id = ?1", [imported]).unwrap();
+
+        assert_eq!(normalize_easiness(&mut conn, 1300.0, 2500.0, &Sm2Params::default()).unwrap(), 1);
+        assert_eq!(
+            [native, seasoned, unenrolled, imported].map(|id| easiness(&conn, id)),
+            [STARTING_EASINESS, 3.1, STARTING_EASINESS, STARTING_EASINESS]
+        );
+        assert_eq!(normalize_easiness(&mut conn, 1300.0, 2500.0, &Sm2Params::default()).unwrap(), 0, "running it again changes nothing");
+    }
+
+    #[test]
+    fn interval_fuzz_scatters_identically_rated_cards() {
+        let intervals_after = |params: &Sm2Params| -> Vec<i64> {
+            let mut conn = test_vault();
+            let ids: Vec<u64> = (0..6).map(|i| due_note(&conn, &format!("card {}", i), 100)).collect();
+            ids.iter()
+                .map(|&id| {
+                    for _ in 0..4 {
+                        rate_card(&mut conn, id, Rating::Good, params).unwrap();
+                    }
+                    review_state(&conn, id).1
+                })
+                .collect()
+        };
+
+        let exact = intervals_after(&Sm2Params::default());
+        assert!(exact.windows(2).all(|w| w[0] == w[1]), "no fuzz by default: {:?}", exact);
+
+        let fuzzy = Sm2Params { interval_fuzz_percent: 10.0, ..Sm2Params::default() };
+        let scattered = intervals_after(&fuzzy);
+        assert!(scattered.iter().any(|&i| i != scattered[0]), "fuzz spreads due dates: {:?}", scattered);
+        let base = exact[0] as f64;
+        assert!(scattered.iter().all(|&i| (i as f64 - base).abs() <= (base * 0.1).ceil()), "{:?} vs {}", scattered, base);
+        assert_eq!(intervals_after(&fuzzy), scattered, "fuzz is reproducible per card");
+    }
+
+    #[test]
+    fn fuzzed_interval_never_drops_below_a_day() {
+        for id in 0..200 {
+            assert!(fuzz_interval(1, id, 1, 25.0) >= 1);
+        }
+        assert_eq!(fuzz_interval(40, 7, 3, 0.0), 40);
+    }
+}
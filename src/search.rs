@@ -0,0 +1,1149 @@
+//! Full-text search shared by the CLI and the Tauri backend
+
+use crate::categorize::KnowledgeType;
+#[cfg(feature = "gui")]
+use crate::config::TagCase;
+use crate::db::{self, SearchBackend};
+use crate::error::QuickNoteError;
+#[cfg(feature = "gui")]
+use crate::export::html_escape;
+#[cfg(feature = "gui")]
+use crate::notes::make_preview;
+use crate::notes::Note;
+#[cfg(feature = "gui")]
+use crate::tags::normalize_tag;
+use crate::tags::stored_tags;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+#[cfg(feature = "gui")]
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub notes: Vec<Note>,
+    /// More notes matched than were returned; the UI should suggest narrowing the query
+    pub truncated: bool,
+    /// The query was under `min_query_len` characters, so no search ran
+    pub too_short: bool,
+}
+
+/// Whether the trimmed query is shorter than `min_len`, counted in chars (Unicode scalar
+/// values) rather than bytes, so `数据` is two characters, not six bytes
+fn is_too_short(query: &str, min_len: usize) -> bool {
+    query.trim().chars().count() < min_len
+}
+
+/// bm25 column weights for ranking FTS5 matches: a term in a column weighted 10 counts
+/// ten times as much toward relevance as the same term in a column weighted 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    pub title: f64,
+    pub content: f64,
+}
+
+/// Search notes using FTS5, or plain `LIKE` matching when this SQLite build lacks it.
+/// Encrypted notes only match on (and expose) their title; their content comes back empty.
+/// FTS5 results are ranked by bm25 relevance under `weights`; `LIKE` results newest first.
+///
+/// At most `limit` notes are returned, and never more than the `max_results` safety cap,
+/// so a broad query on a big vault can't flood the UI. Archived notes are skipped unless
+/// `include_archived`. Queries shorter than `min_query_len` characters aren't run at all.
+///
+/// A stored `knowledge_type` that isn't a known type reads as `Concept`, unless
+/// `strict_types`, when it fails the search with `QuickNoteError::UnknownKnowledgeType`
+/// so the corruption gets noticed.
+#[allow(clippy::too_many_arguments)]
+pub fn search_notes(
+    conn: &Connection,
+    query: &str,
+    limit: Option<usize>,
+    max_results: usize,
+    min_query_len: usize,
+    include_archived: bool,
+    weights: Weights,
+    strict_types: bool,
+) -> Result<SearchResults, Box<dyn std::error::Error>> {
+    if is_too_short(query, min_query_len) {
+        return Ok(SearchResults { notes: Vec::new(), truncated: false, too_short: true });
+    }
+    let limit = limit.unwrap_or(max_results).min(max_results);
+    let (clause, params) = match_clause(conn, query, include_archived)?;
+
+    // Fetch one extra row to learn whether the limit cut anything off
+    let mut stmt = conn.prepare(&format!(
+        "SELECT n.id, n.title, CASE WHEN n.encrypted THEN '' ELSE n.content END,
+                n.knowledge_type, n.tags, n.created_at, n.updated_at, n.encrypted, n.source, n.is_archived,
+                n.always_top, n.priority
+         {} ORDER BY {} LIMIT {}",
+        clause,
+        ranking(conn, weights)?,
+        limit + 1
+    ))?;
+    let mut notes = read_notes(stmt.query(rusqlite::params_from_iter(params))?, strict_types)?;
+
+    let truncated = notes.len() > limit;
+    notes.truncate(limit);
+    Ok(SearchResults { notes, truncated, too_short: false })
+}
+
+/// A note field that `search_projected` can return
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Title,
+    Content,
+    Snippet,
+    KnowledgeType,
+    Tags,
+    CreatedAt,
+    UpdatedAt,
+    Encrypted,
+    Source,
+    Archived,
+    AlwaysTop,
+    Priority,
+}
+
+#[cfg(feature = "gui")]
+const FIELDS: [Field; 13] = [
+    Field::Id,
+    Field::Title,
+    Field::Content,
+    Field::Snippet,
+    Field::KnowledgeType,
+    Field::Tags,
+    Field::CreatedAt,
+    Field::UpdatedAt,
+    Field::Encrypted,
+    Field::Source,
+    Field::Archived,
+    Field::AlwaysTop,
+    Field::Priority,
+];
+
+#[cfg(feature = "gui")]
+impl Field {
+    /// JSON key, matching the serialized `Note`
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Title => "title",
+            Self::Content => "content",
+            Self::Snippet => "snippet",
+            Self::KnowledgeType => "knowledge_type",
+            Self::Tags => "tags",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+            Self::Encrypted => "encrypted",
+            Self::Source => "source",
+            Self::Archived => "archived",
+            Self::AlwaysTop => "always_top",
+            Self::Priority => "priority",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "n.id",
+            Self::Title => "n.title",
+            Self::Content | Self::Snippet => "CASE WHEN n.encrypted THEN '' ELSE n.content END",
+            Self::KnowledgeType => "COALESCE(n.knowledge_type, 'Concept')",
+            Self::Tags => "n.tags",
+            Self::CreatedAt => "n.created_at",
+            Self::UpdatedAt => "n.updated_at",
+            Self::Encrypted => "n.encrypted",
+            Self::Source => "n.source",
+            Self::Archived => "n.is_archived",
+            Self::AlwaysTop => "n.always_top",
+            Self::Priority => "n.priority",
+        }
+    }
+}
+
+/// Which fields `search_projected` returns, and how long snippets are
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone)]
+pub struct Projection {
+    fields: Vec<Field>,
+    snippet_chars: usize,
+    highlight: Option<Highlight>,
+}
+
+/// Turns the `snippet` field into an excerpt around the first matching term, with each
+/// term wrapped in `open`/`close` (`<mark>` for the GUI, ANSI colour codes for a
+/// terminal). With `escape_html`, on by default alongside `<mark>`, the note text is
+/// HTML-escaped before the delimiters go in, so the excerpt is safe to render as markup.
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Highlight {
+    pub open: String,
+    pub close: String,
+    /// Characters of context kept on each side of the first match
+    pub context_chars: usize,
+    /// Escape `&`, `<`, `>` and quotes in the text; turn off for non-HTML delimiters
+    pub escape_html: bool,
+}
+
+#[cfg(feature = "gui")]
+impl Default for Highlight {
+    fn default() -> Self {
+        Self {
+            open: "<mark>".to_string(),
+            close: "</mark>".to_string(),
+            context_chars: 40,
+            escape_html: true,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Highlight {
+    /// Reject delimiters that would make highlights invisible or ambiguous
+    pub fn validate(&self) -> Result<(), String> {
+        if self.open.is_empty() || self.close.is_empty() {
+            return Err("Highlight delimiters must not be empty".to_string());
+        }
+        if self.open == self.close {
+            return Err("Highlight open and close delimiters must differ".to_string());
+        }
+        Ok(())
+    }
+
+    /// Excerpt of `content` (as plain text, see `make_preview`) around the first term of
+    /// `query` it contains, case-insensitively, with every term occurrence in the excerpt
+    /// highlighted. Notes that only matched on their title get a plain leading preview.
+    pub fn snippet(&self, content: &str, query: &str) -> String {
+        let text: Vec<char> = make_preview(content, usize::MAX).chars().collect();
+        // One lowercase char per original char, so indices line up between the two
+        let lower: Vec<char> = text.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+        let mut terms: Vec<Vec<char>> = query
+            .split_whitespace()
+            .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|t| !t.is_empty() && !["AND", "OR", "NOT", "NEAR"].contains(t))
+            .map(|t| t.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect())
+            .collect();
+        // Longest first, so `index` doesn't cut `indexes` short
+        terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+
+        let mut matches = Vec::new();
+        let mut i = 0;
+        while i < lower.len() {
+            match terms.iter().find(|t| lower[i..].starts_with(t)) {
+                Some(term) => {
+                    matches.push((i, i + term.len()));
+                    i += term.len();
+                }
+                None => i += 1,
+            }
+        }
+        let Some(&(first_start, first_end)) = matches.first() else {
+            return self.text(&make_preview(content, 2 * self.context_chars));
+        };
+
+        let start = first_start.saturating_sub(self.context_chars);
+        let mut end = (first_end + self.context_chars).min(text.len());
+        // Don't cut a highlighted term in half at the end of the window
+        if let Some(&(_, match_end)) = matches.iter().find(|(s, e)| *s < end && *e > end) {
+            end = match_end;
+        }
+
+        let mut out = String::new();
+        if start > 0 {
+            out.push('…');
+        }
+        let segment = |from: usize, to: usize| self.text(&text[from..to].iter().collect::<String>());
+        let mut pos = start;
+        for &(s, e) in matches.iter().filter(|(s, e)| *s >= start && *e <= end) {
+            out.push_str(&segment(pos, s));
+            out.push_str(&self.open);
+            out.push_str(&segment(s, e));
+            out.push_str(&self.close);
+            pos = e;
+        }
+        out.push_str(&segment(pos, end));
+        if end < text.len() {
+            out.push('…');
+        }
+        out
+    }
+
+    /// Note text as it goes into a snippet, escaped if `escape_html`
+    fn text(&self, text: &str) -> String {
+        if self.escape_html {
+            html_escape(text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Projection {
+    /// Parse field names (`["id", "title", "snippet"]`), rejecting unknown ones
+    pub fn parse(names: &[String], snippet_chars: usize) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        for name in names {
+            let field = FIELDS.iter().find(|f| f.name() == name.trim()).ok_or_else(|| {
+                let known: Vec<&str> = FIELDS.iter().map(Field::name).collect();
+                format!("Unknown search field '{}' (expected one of {})", name, known.join(", "))
+            })?;
+            if !fields.contains(field) {
+                fields.push(*field);
+            }
+        }
+        if fields.is_empty() {
+            return Err("At least one search field is required".to_string());
+        }
+        Ok(Self { fields, snippet_chars, highlight: None })
+    }
+
+    /// Highlight matches in the `snippet` field, which must be requested
+    pub fn with_highlight(mut self, highlight: Highlight) -> Result<Self, String> {
+        highlight.validate()?;
+        if !self.fields.contains(&Field::Snippet) {
+            return Err("Highlighting applies to the 'snippet' field; include it in the search fields".to_string());
+        }
+        self.highlight = Some(highlight);
+        Ok(self)
+    }
+}
+
+/// `search_notes` results with only the projected fields per note
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct ProjectedResults {
+    pub notes: Vec<serde_json::Map<String, serde_json::Value>>,
+    pub truncated: bool,
+    pub too_short: bool,
+}
+
+/// `search_notes`, but selecting and returning only the fields in `projection`, so list
+/// views don't pay for full content they never show. `snippet` is a content preview, or
+/// a highlighted excerpt when the projection has a `Highlight`.
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn search_projected(
+    conn: &Connection,
+    query: &str,
+    projection: &Projection,
+    limit: Option<usize>,
+    max_results: usize,
+    min_query_len: usize,
+    include_archived: bool,
+    weights: Weights,
+) -> Result<ProjectedResults, Box<dyn std::error::Error>> {
+    if is_too_short(query, min_query_len) {
+        return Ok(ProjectedResults { notes: Vec::new(), truncated: false, too_short: true });
+    }
+    let limit = limit.unwrap_or(max_results).min(max_results);
+    let (clause, params) = match_clause(conn, query, include_archived)?;
+
+    let columns: Vec<&str> = projection.fields.iter().map(Field::column).collect();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} {} ORDER BY {} LIMIT {}",
+        columns.join(", "),
+        clause,
+        ranking(conn, weights)?,
+        limit + 1
+    ))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut note = serde_json::Map::new();
+        for (i, field) in projection.fields.iter().enumerate() {
+            let value = match field {
+                Field::Id => serde_json::json!(row.get::<_, u64>(i)?),
+                Field::Title | Field::Content | Field::KnowledgeType => serde_json::json!(row.get::<_, String>(i)?),
+                Field::Snippet => {
+                    let content = row.get::<_, String>(i)?;
+                    match &projection.highlight {
+                        Some(highlight) => serde_json::json!(highlight.snippet(&content, query)),
+                        None => serde_json::json!(make_preview(&content, projection.snippet_chars)),
+                    }
+                }
+                Field::Tags => serde_json::json!(stored_tags(row.get(i)?)),
+                Field::CreatedAt | Field::UpdatedAt | Field::Priority => serde_json::json!(row.get::<_, i64>(i)?),
+                Field::Encrypted | Field::Archived | Field::AlwaysTop => serde_json::json!(row.get::<_, bool>(i)?),
+                Field::Source => serde_json::json!(row.get::<_, Option<String>>(i)?),
+            };
+            note.insert(field.name().to_string(), value);
+        }
+        notes.push(note);
+    }
+
+    let truncated = notes.len() > limit;
+    notes.truncate(limit);
+    Ok(ProjectedResults { notes, truncated, too_short: false })
+}
+
+/// Number of notes `search_notes` would match with no limit or cap, without reading them
+#[cfg(feature = "gui")]
+pub fn search_count(
+    conn: &Connection,
+    query: &str,
+    min_query_len: usize,
+    include_archived: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if is_too_short(query, min_query_len) {
+        return Ok(0);
+    }
+    let (clause, params) = match_clause(conn, query, include_archived)?;
+    let count = conn.query_row(&format!("SELECT COUNT(*) {}", clause), rusqlite::params_from_iter(params), |row| {
+        row.get(0)
+    })?;
+    Ok(count)
+}
+
+/// Largest compiled regex `regex_search` accepts, in bytes. The regex crate runs in
+/// linear time, so this only guards against patterns like `\w{1000}{1000}` that would
+/// take huge amounts of memory to build.
+#[cfg(feature = "gui")]
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Notes whose content matches the regex `pattern`, newest first, each with its first
+/// match (`start`/`end` in UTF-16 code units, as the editor counts them). Encrypted and,
+/// unless `include_archived`, archived notes are skipped.
+#[cfg(feature = "gui")]
+pub fn regex_search(
+    conn: &Connection,
+    pattern: &str,
+    limit: Option<usize>,
+    max_results: usize,
+    include_archived: bool,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let regex = regex::RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| match e {
+            regex::Error::CompiledTooBig(_) => "Pattern is too complex; try a simpler regex".to_string(),
+            e => format!("Invalid regex: {}", e),
+        })?;
+    let limit = limit.unwrap_or(max_results).min(max_results);
+
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, content FROM notes
+         WHERE NOT encrypted AND (?1 OR NOT is_archived)
+         ORDER BY updated_at_ms DESC, id DESC",
+    )?;
+    let mut rows = stmt.query([include_archived])?;
+
+    let mut matches = Vec::new();
+    while matches.len() < limit {
+        let Some(row) = rows.next()? else {
+            break;
+        };
+        let content: String = row.get(3)?;
+        if let Some(found) = regex.find(&content) {
+            let start = content[..found.start()].encode_utf16().count();
+            matches.push(serde_json::json!({
+                "id": row.get::<_, u64>(0)?,
+                "title": row.get::<_, String>(1)?,
+                "knowledge_type": row.get::<_, Option<String>>(2)?,
+                "match": found.as_str(),
+                "start": start,
+                "end": start + found.as_str().encode_utf16().count(),
+            }));
+        }
+    }
+    Ok(matches)
+}
+
+/// Ids of every note matching `query` (as in `search_notes`; a blank query matches all)
+/// that also has type `knowledge_type` and `tag` or one of its children (`db` matches
+/// `db/postgres`), newest first. No limit or cap: this selects notes to export.
+#[cfg(feature = "gui")]
+pub fn filter_ids(
+    conn: &Connection,
+    query: &str,
+    knowledge_type: Option<KnowledgeType>,
+    tag: Option<&str>,
+    tag_case: TagCase,
+    include_archived: bool,
+) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+    let (mut clause, mut params) = if query.trim().is_empty() {
+        let archived = if include_archived { "" } else { " AND n.is_archived = 0" };
+        (format!("FROM notes n WHERE 1 = 1{}", archived), Vec::new())
+    } else {
+        match_clause(conn, query, include_archived)?
+    };
+
+    if let Some(kind) = knowledge_type {
+        params.push(Value::Text(kind.as_db_str().to_string()));
+        clause.push_str(&format!(" AND COALESCE(n.knowledge_type, 'Concept') = ?{}", params.len()));
+    }
+    if let Some(tag) = tag {
+        let tag = normalize_tag(tag, tag_case).ok_or_else(|| format!("Invalid tag '{}'", tag))?;
+        params.push(Value::Text(tag));
+        clause.push_str(&format!(
+            " AND json_valid(n.tags) AND EXISTS (SELECT 1 FROM json_each(n.tags) t
+                          WHERE t.value = ?{i} OR substr(t.value, 1, length(?{i}) + 1) = ?{i} || '/')",
+            i = params.len()
+        ));
+    }
+
+    let mut stmt = conn.prepare(&format!("SELECT n.id {} ORDER BY n.updated_at_ms DESC, n.id DESC", clause))?;
+    let ids = stmt.query_map(rusqlite::params_from_iter(params), |row| row.get(0))?;
+    Ok(ids.collect::<Result<_, _>>()?)
+}
+
+/// `ORDER BY` terms for a `match_clause` query: `always_top` notes first, then bm25 (best
+/// first, newest breaking ties) with FTS5, which has no relevance score without it, else
+/// newest first
+fn ranking(conn: &Connection, weights: Weights) -> Result<String, rusqlite::Error> {
+    Ok(match db::search_backend(conn)? {
+        SearchBackend::Fts5 => format!(
+            "n.always_top DESC, bm25(notes_fts, {:?}, {:?}), n.updated_at_ms DESC, n.id DESC",
+            weights.title, weights.content
+        ),
+        SearchBackend::Like => "n.always_top DESC, n.updated_at_ms DESC, n.id DESC".to_string(),
+    })
+}
+
+/// `FROM ... WHERE ...` selecting the notes (aliased `n`) that match `query`, with its
+/// parameters. FTS5 when available; otherwise every whitespace-separated term must
+/// appear (via `LIKE`) in the title or, for non-encrypted notes, the content.
+fn match_clause(
+    conn: &Connection,
+    query: &str,
+    include_archived: bool,
+) -> Result<(String, Vec<Value>), rusqlite::Error> {
+    let mut params = Vec::new();
+    let mut clause = match db::search_backend(conn)? {
+        SearchBackend::Fts5 => {
+            params.push(Value::Text(query.to_string()));
+            String::from(
+                "FROM notes n
+                 JOIN notes_fts f ON n.id = f.rowid
+                 WHERE notes_fts MATCH ?1
+                   AND (n.encrypted = 0
+                        OR n.id IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH 'title : (' || ?1 || ')'))",
+            )
+        }
+        SearchBackend::Like => {
+            let mut clause = String::from("FROM notes n WHERE 1 = 1");
+            for (i, term) in query.split_whitespace().enumerate() {
+                clause.push_str(&format!(
+                    " AND (n.title LIKE ?{i} ESCAPE '\\' OR (n.encrypted = 0 AND n.content LIKE ?{i} ESCAPE '\\'))",
+                    i = i + 1
+                ));
+                params.push(Value::Text(format!("%{}%", escape_like(term))));
+            }
+            clause
+        }
+    };
+    if !include_archived {
+        clause.push_str(" AND n.is_archived = 0");
+    }
+    Ok((clause, params))
+}
+
+/// Map `id, title, content, knowledge_type, tags, created_at, updated_at, encrypted, source, is_archived, always_top, priority` rows;
+/// see `search_notes` for `strict_types`
+fn read_notes(mut rows: rusqlite::Rows<'_>, strict_types: bool) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id = row.get(0)?;
+        let knowledge_type = match row.get::<_, Option<String>>(3)? {
+            // Uncategorized notes are Concepts
+            None => KnowledgeType::Concept,
+            Some(value) => match KnowledgeType::from_db_str(&value) {
+                Some(kind) => kind,
+                None if strict_types => return Err(QuickNoteError::UnknownKnowledgeType { id, value }.into()),
+                None => KnowledgeType::Concept, // fallback
+            },
+        };
+        notes.push(Note {
+            id,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            knowledge_type,
+            tags: stored_tags(row.get(4)?),
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+            encrypted: row.get(7)?,
+            source: row.get(8)?,
+            archived: row.get(9)?,
+            always_top: row.get(10)?,
+            priority: row.get(11)?,
+        });
+    }
+    Ok(notes)
+}
+
+/// Maximum number of hits returned by `jump_search`
+#[cfg(feature = "gui")]
+const JUMP_SEARCH_LIMIT: usize = 20;
+
+/// Title-only lookup for the Ctrl+P palette: exact title first, then prefix hits, then
+/// other substring hits, each bucket newest first. Returns just `{id, title, knowledge_type}`.
+#[cfg(feature = "gui")]
+pub fn jump_search(conn: &Connection, query: &str) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = escape_like(query);
+
+    // LIKE is case-insensitive for ASCII; lower() handles the exact-match bucket
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type FROM notes
+         WHERE title LIKE '%' || ?1 || '%' ESCAPE '\\' AND is_archived = 0
+         ORDER BY CASE
+                      WHEN lower(title) = lower(?2) THEN 0
+                      WHEN title LIKE ?1 || '%' ESCAPE '\\' THEN 1
+                      ELSE 2
+                  END,
+                  updated_at_ms DESC, id DESC
+         LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![pattern, query, JUMP_SEARCH_LIMIT as i64], |row| {
+        Ok(serde_json::json!({
+            "id": row.get::<_, u64>(0)?,
+            "title": row.get::<_, String>(1)?,
+            "knowledge_type": row.get::<_, Option<String>>(2)?,
+        }))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// A note with attachments whose filenames match a search
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct AttachmentHit {
+    pub note_id: u64,
+    pub title: String,
+    pub filenames: Vec<String>,
+}
+
+/// Notes with an attachment whose filename contains every word of `query` (case-insensitive
+/// for ASCII), newest first, at most `limit` of them. Runs a `LIKE` scan over the
+/// attachments table, so `search_notes` only calls for it on request.
+#[cfg(feature = "gui")]
+pub fn attachment_hits(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    min_query_len: usize,
+    include_archived: bool,
+) -> Result<Vec<AttachmentHit>, Box<dyn std::error::Error>> {
+    if is_too_short(query, min_query_len) {
+        return Ok(Vec::new());
+    }
+    let mut clause = String::from(if include_archived { "1 = 1" } else { "n.is_archived = 0" });
+    let mut params = Vec::new();
+    for (i, term) in query.split_whitespace().enumerate() {
+        clause.push_str(&format!(" AND a.filename LIKE ?{} ESCAPE '\\'", i + 1));
+        params.push(Value::Text(format!("%{}%", escape_like(term))));
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT n.id, n.title, a.filename FROM attachments a
+         JOIN notes n ON n.id = a.note_id
+         WHERE {}
+         ORDER BY n.updated_at_ms DESC, n.id DESC, a.filename",
+        clause
+    ))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+    let mut hits: Vec<AttachmentHit> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let note_id: u64 = row.get(0)?;
+        if let Some(hit) = hits.last_mut().filter(|hit| hit.note_id == note_id) {
+            hit.filenames.push(row.get(2)?);
+        } else if hits.len() == limit {
+            break;
+        } else {
+            hits.push(AttachmentHit { note_id, title: row.get(1)?, filenames: vec![row.get(2)?] });
+        }
+    }
+    Ok(hits)
+}
+
+/// Escape LIKE wildcards so user input matches literally (used with `ESCAPE '\'`)
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    const WEIGHTS: Weights = Weights { title: 10.0, content: 1.0 };
+
+    fn search(conn: &Connection, query: &str) -> Vec<Note> {
+        search_notes(conn, query, None, 100, 2, false, WEIGHTS, false).unwrap().notes
+    }
+
+    fn secret_note(conn: &Connection) -> u64 {
+        let id = test_note(conn, "Secret plan", "c1phertextBLOB");
+        conn.execute("UPDATE notes SET encrypted = 1 WHERE id = ?1", [id]).unwrap();
+        id
+    }
+
+    #[test]
+    fn secret_body_never_appears_in_search_output() {
+        let conn = test_vault();
+        let id = secret_note(&conn);
+
+        assert!(search(&conn, "c1phertextBLOB").is_empty());
+
+        let results = search(&conn, "plan");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, id);
+        assert!(results[0].encrypted);
+        assert!(!serde_json::to_string(&results).unwrap().contains("c1phertext"));
+
+        let fields = ["id", "title", "content", "snippet", "encrypted"].map(String::from);
+        let projection = Projection::parse(&fields, 140).unwrap();
+        let projected = search_projected(&conn, "plan", &projection, None, 100, 2, false, WEIGHTS).unwrap();
+        let json = serde_json::to_string(&projected).unwrap();
+        assert!(json.contains("\"encrypted\":true"), "{}", json);
+        assert!(!json.contains("c1phertext"), "{}", json);
+    }
+
+    #[test]
+    fn plain_notes_report_not_encrypted() {
+        let conn = test_vault();
+        test_note(&conn, "Public plan", "visible body");
+
+        let results = search(&conn, "visible");
+        assert_eq!(results[0].content, "visible body");
+        assert!(!results[0].encrypted);
+    }
+
+    fn jump_titles(conn: &Connection, query: &str) -> Vec<String> {
+        jump_search(conn, query).unwrap().iter().map(|hit| hit["title"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn jump_search_ranks_exact_then_prefix_then_substring() {
+        let conn = test_vault();
+        for title in ["Using Docker", "Docker compose", "docker", "Dockerfile tips"] {
+            test_note(&conn, title, "");
+        }
+
+        assert_eq!(jump_titles(&conn, "DOCKER"), ["docker", "Dockerfile tips", "Docker compose", "Using Docker"]);
+    }
+
+    #[test]
+    fn jump_search_returns_minimal_payload_and_caps_hits() {
+        let conn = test_vault();
+        for i in 0..JUMP_SEARCH_LIMIT + 5 {
+            test_note(&conn, &format!("Note {}", i), "body");
+        }
+
+        let hits = jump_search(&conn, "note").unwrap();
+        assert_eq!(hits.len(), JUMP_SEARCH_LIMIT);
+        let keys: Vec<&String> = hits[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["id", "knowledge_type", "title"]);
+        assert!(jump_search(&conn, "  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn jump_search_treats_wildcards_literally() {
+        let conn = test_vault();
+        test_note(&conn, "100% done", "");
+        test_note(&conn, "1000 done", "");
+
+        assert_eq!(jump_titles(&conn, "100%"), ["100% done"]);
+    }
+
+    fn many_notes(conn: &Connection, count: usize) {
+        for i in 0..count {
+            test_note(conn, &format!("Note {}", i), "the common word");
+        }
+    }
+
+    #[test]
+    fn search_cap_applies_without_explicit_limit() {
+        let conn = test_vault();
+        many_notes(&conn, 12);
+
+        let results = search_notes(&conn, "common", None, 10, 2, false, WEIGHTS, false).unwrap();
+        assert_eq!(results.notes.len(), 10);
+        assert!(results.truncated);
+    }
+
+    #[test]
+    fn search_limit_cannot_exceed_cap() {
+        let conn = test_vault();
+        many_notes(&conn, 12);
+
+        let capped = search_notes(&conn, "common", Some(50), 10, 2, false, WEIGHTS, false).unwrap();
+        assert_eq!((capped.notes.len(), capped.truncated), (10, true));
+
+        let exact = search_notes(&conn, "common", Some(12), 20, 2, false, WEIGHTS, false).unwrap();
+        assert_eq!((exact.notes.len(), exact.truncated), (12, false));
+    }
+
+    /// A vault as `init_schema` leaves it when the linked SQLite lacks FTS5
+    fn vault_without_fts() -> Connection {
+        let conn = test_vault();
+        conn.execute_batch(
+            "DROP TRIGGER notes_ai; DROP TRIGGER notes_au; DROP TRIGGER notes_ad;
+             DROP TABLE notes_fts;",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn search_falls_back_to_like_without_fts() {
+        let conn = vault_without_fts();
+        let id = test_note(&conn, "Kafka consumer lag", "check the offsets_topic partition");
+        test_note(&conn, "Unrelated", "nothing here");
+        secret_note(&conn);
+
+        assert_eq!(db::search_backend(&conn).unwrap(), SearchBackend::Like);
+        assert_eq!(db::diagnostics(&conn).unwrap()["search_backend"], "Like");
+        let ids = |q: &str| search(&conn, q).iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids("consumer offsets_topic"), vec![id]);
+        assert_eq!(ids("KAFKA"), vec![id]);
+        assert!(ids("c1phertextBLOB").is_empty());
+    }
+
+    #[test]
+    fn fresh_vault_uses_fts5() {
+        let conn = test_vault();
+        assert_eq!(db::search_backend(&conn).unwrap(), SearchBackend::Fts5);
+        assert_eq!(db::diagnostics(&conn).unwrap()["search_backend"], "Fts5");
+    }
+
+    #[test]
+    fn archived_note_is_hidden_from_search_unless_requested() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Old runbook", "restart procedure");
+        crate::notes::set_archived(&conn, id, true).unwrap();
+
+        assert!(search(&conn, "restart").is_empty());
+        assert!(jump_search(&conn, "runbook").unwrap().is_empty());
+        let included = search_notes(&conn, "restart", None, 100, 2, true, WEIGHTS, false).unwrap();
+        assert_eq!(included.notes.len(), 1);
+        assert!(included.notes[0].archived);
+    }
+
+    #[test]
+    fn count_matches_full_result_length() {
+        let conn = test_vault();
+        many_notes(&conn, 7);
+        test_note(&conn, "Docker compose", "services and volumes");
+        test_note(&conn, "Unrelated", "nothing here");
+        let archived = test_note(&conn, "Old note", "the common word");
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        for query in ["common", "the common word", "docker", "common OR docker", "zzz"] {
+            let full = search_notes(&conn, query, None, 1_000, 2, false, WEIGHTS, false).unwrap();
+            assert_eq!(search_count(&conn, query, 2, false).unwrap(), full.notes.len(), "{}", query);
+        }
+        let full = search_notes(&conn, "common", None, 1_000, 2, true, WEIGHTS, false).unwrap();
+        assert_eq!(search_count(&conn, "common", 2, true).unwrap(), full.notes.len());
+        assert_eq!(full.notes.len(), 8);
+
+        // Malformed queries fail the same way in both
+        assert!(search_notes(&conn, "\"common", None, 1_000, 2, false, WEIGHTS, false).is_err());
+        assert!(search_count(&conn, "\"common", 2, false).is_err());
+    }
+
+    #[test]
+    fn count_of_too_short_query_is_zero() {
+        let conn = test_vault();
+        many_notes(&conn, 3);
+        assert_eq!(search_count(&conn, "a", 2, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn min_query_len_counts_trimmed_ascii_chars() {
+        assert!(is_too_short("a", 2));
+        assert!(is_too_short("  a  ", 2));
+        assert!(!is_too_short("db", 2));
+        assert!(!is_too_short("a", 1));
+        assert!(is_too_short("", 1));
+    }
+
+    #[test]
+    fn min_query_len_counts_cjk_chars_not_bytes() {
+        // 6 bytes, 2 chars
+        assert!(!is_too_short("数据", 2));
+        assert!(is_too_short("数据", 3));
+        // 3 bytes, 1 char
+        assert!(is_too_short("数", 2));
+        assert!(!is_too_short("数", 1));
+    }
+
+    #[test]
+    fn too_short_query_is_flagged_without_running() {
+        let conn = test_vault();
+        test_note(&conn, "a", "a");
+        let results = search_notes(&conn, "a", None, 100, 2, false, WEIGHTS, false).unwrap();
+        assert!(results.too_short);
+        assert!(results.notes.is_empty());
+        assert!(!search_notes(&conn, "ab", None, 100, 2, false, WEIGHTS, false).unwrap().too_short);
+    }
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn projection_without_content_omits_the_key() {
+        let conn = test_vault();
+        test_note(&conn, "Pool sizing", "size the pool per core");
+
+        let projection = Projection::parse(&fields(&["id", " title ", "snippet", "title"]), 140).unwrap();
+        let results = search_projected(&conn, "pool", &projection, None, 100, 2, false, WEIGHTS).unwrap();
+        assert_eq!(results.notes.len(), 1);
+        let keys: Vec<&String> = results.notes[0].keys().collect();
+        assert_eq!(keys.len(), 3);
+        assert!(!results.notes[0].contains_key("content"));
+        assert_eq!(results.notes[0]["snippet"], "size the pool per core");
+    }
+
+    #[test]
+    fn projection_rejects_unknown_or_no_fields() {
+        let err = Projection::parse(&fields(&["id", "body"]), 140).unwrap_err();
+        assert!(err.starts_with("Unknown search field 'body'"), "{}", err);
+        assert!(Projection::parse(&[], 140).is_err());
+    }
+
+    #[test]
+    fn regex_search_returns_first_match_span() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Hosts", "né: db at 10.0.0.12, cache at 10.0.0.13");
+        test_note(&conn, "Other", "no addresses");
+        secret_note(&conn);
+
+        let hits = regex_search(&conn, r"\d+\.\d+\.\d+\.\d+", None, 100, false).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["id"], id);
+        assert_eq!(hits[0]["match"], "10.0.0.12");
+        // UTF-16 offsets, so "é" counts once
+        assert_eq!((hits[0]["start"].as_u64(), hits[0]["end"].as_u64()), (Some(10), Some(19)));
+        assert!(regex_search(&conn, "c1pher", None, 100, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn regex_search_rejects_invalid_and_oversized_patterns() {
+        let conn = test_vault();
+        let invalid = regex_search(&conn, "(unclosed", None, 100, false).unwrap_err();
+        assert!(invalid.to_string().starts_with("Invalid regex:"), "{}", invalid);
+        let oversized = regex_search(&conn, r"\w{1000}{1000}", None, 100, false).unwrap_err();
+        assert_eq!(oversized.to_string(), "Pattern is too complex; try a simpler regex");
+    }
+
+    const POOL_NOTE: &str = "Tuning notes: always size the connection Pool per core so that worker threads never starve";
+
+    #[test]
+    fn snippet_highlights_with_html_or_custom_delimiters() {
+        let html = Highlight { context_chars: 12, ..Highlight::default() };
+        assert_eq!(html.snippet(POOL_NOTE, "pool"), "… connection <mark>Pool</mark> per core so…");
+
+        let wiki = Highlight { open: "[[".to_string(), close: "]]".to_string(), context_chars: 12, escape_html: false };
+        assert_eq!(wiki.snippet(POOL_NOTE, "pool"), "… connection [[Pool]] per core so…");
+    }
+
+    #[test]
+    fn snippet_highlights_every_term_in_the_window() {
+        let highlight = Highlight { open: "[[".to_string(), close: "]]".to_string(), context_chars: 30, escape_html: false };
+        assert_eq!(
+            highlight.snippet(POOL_NOTE, "pool AND core"),
+            "…s: always size the connection [[Pool]] per [[core]] so that worker threa…"
+        );
+        // Only the title matched: a plain leading preview of the content
+        assert_eq!(highlight.snippet("short body", "title"), "short body");
+    }
+
+    #[test]
+    fn html_snippet_escapes_note_text_but_not_the_marks() {
+        let content = "Render <img src=x onerror=alert(1)> & keep the pool <safe>";
+        let html = Highlight::default();
+        assert_eq!(
+            html.snippet(content, "pool"),
+            "…&lt;img src=x onerror=alert(1)&gt; &amp; keep the <mark>pool</mark> &lt;safe&gt;"
+        );
+        assert_eq!(html.snippet("a < b", "title"), "a &lt; b", "title-only matches are escaped too");
+
+        let raw = Highlight { escape_html: false, ..Highlight::default() };
+        assert_eq!(raw.snippet("a < b & pool", "pool"), "a < b & <mark>pool</mark>");
+    }
+
+    #[test]
+    fn highlight_rejects_empty_or_equal_delimiters() {
+        let empty = Highlight { open: String::new(), ..Highlight::default() };
+        assert!(empty.validate().is_err());
+        let same = Highlight { open: "**".to_string(), close: "**".to_string(), ..Highlight::default() };
+        assert_eq!(same.validate().unwrap_err(), "Highlight open and close delimiters must differ");
+        assert!(Highlight::default().validate().is_ok());
+    }
+
+    #[test]
+    fn projected_search_highlights_the_snippet_field() {
+        let conn = test_vault();
+        test_note(&conn, "Pool sizing", POOL_NOTE);
+        let highlight = Highlight { open: "[[".to_string(), close: "]]".to_string(), context_chars: 12, escape_html: false };
+
+        let without_snippet = Projection::parse(&fields(&["id"]), 140).unwrap();
+        assert!(without_snippet.with_highlight(highlight.clone()).is_err());
+
+        let projection = Projection::parse(&fields(&["id", "snippet"]), 140).unwrap().with_highlight(highlight).unwrap();
+        let results = search_projected(&conn, "pool", &projection, None, 100, 2, false, WEIGHTS).unwrap();
+        assert_eq!(results.notes[0]["snippet"], "… connection [[Pool]] per core so…");
+    }
+
+    fn ranked_titles(conn: &Connection, query: &str, weights: Weights) -> Vec<String> {
+        let results = search_notes(conn, query, None, 100, 2, false, weights, false).unwrap();
+        results.notes.into_iter().map(|note| note.title).collect()
+    }
+
+    #[test]
+    fn title_weight_ranks_title_matches_first() {
+        let conn = test_vault();
+        test_note(&conn, "Rollout", "the deploy pipeline deploy checklist, then deploy again");
+        test_note(&conn, "Deploy", "steps for shipping a release");
+        // Bumped last so update time alone would put the content match first
+        conn.execute("UPDATE notes SET updated_at = updated_at + 60 WHERE title = 'Rollout'", []).unwrap();
+
+        let favor_title = Weights { title: 10.0, content: 1.0 };
+        assert_eq!(ranked_titles(&conn, "deploy", favor_title), vec!["Deploy", "Rollout"]);
+        let favor_content = Weights { title: 0.0, content: 1.0 };
+        assert_eq!(ranked_titles(&conn, "deploy", favor_content), vec!["Rollout", "Deploy"]);
+    }
+
+    #[test]
+    fn like_fallback_ignores_weights_and_sorts_newest_first() {
+        let conn = vault_without_fts();
+        test_note(&conn, "Deploy", "steps");
+        let newer = test_note(&conn, "Rollout", "deploy later");
+        conn.execute("UPDATE notes SET updated_at = updated_at + 60 WHERE id = ?1", [newer]).unwrap();
+
+        let favor_title = Weights { title: 10.0, content: 1.0 };
+        assert_eq!(ranked_titles(&conn, "deploy", favor_title), vec!["Rollout", "Deploy"]);
+    }
+
+    #[test]
+    fn always_top_note_leads_the_results_it_matches() {
+        let conn = test_vault();
+        let runbook = test_note(&conn, "Runbook", "on call: page the owner, then deploy the fix");
+        test_note(&conn, "Deploy", "deploy steps: deploy, verify, deploy the rest");
+        let unrelated = test_note(&conn, "Canonical glossary", "terms");
+        for id in [runbook, unrelated] {
+            crate::notes::set_always_top(&conn, id, true).unwrap();
+        }
+
+        // bm25 alone ranks the title match first
+        let favor_title = Weights { title: 10.0, content: 1.0 };
+        let results = search_notes(&conn, "deploy", None, 100, 2, false, favor_title, false).unwrap();
+        let ranked: Vec<(&str, bool)> = results.notes.iter().map(|n| (n.title.as_str(), n.always_top)).collect();
+        // Flagged and matching comes first and is marked; flagged but not matching stays out
+        assert_eq!(ranked, vec![("Runbook", true), ("Deploy", false)]);
+
+        crate::notes::set_always_top(&conn, runbook, false).unwrap();
+        assert_eq!(ranked_titles(&conn, "deploy", favor_title), vec!["Deploy", "Runbook"]);
+        assert!(matches!(
+            crate::notes::set_always_top(&conn, 999, true),
+            Err(crate::error::QuickNoteError::NotFound(999))
+        ));
+    }
+
+    #[test]
+    fn always_top_applies_without_fts_too() {
+        let conn = vault_without_fts();
+        let runbook = test_note(&conn, "Runbook", "deploy");
+        let newer = test_note(&conn, "Deploy", "deploy");
+        conn.execute("UPDATE notes SET updated_at = updated_at + 60 WHERE id = ?1", [newer]).unwrap();
+        crate::notes::set_always_top(&conn, runbook, true).unwrap();
+
+        assert_eq!(ranked_titles(&conn, "deploy", WEIGHTS), vec!["Runbook", "Deploy"]);
+    }
+
+    #[test]
+    fn corrupt_tags_cell_does_not_break_search() {
+        let conn = test_vault();
+        let good = test_note(&conn, "Pool sizing", "size the pool");
+        conn.execute(r#"UPDATE notes SET tags = '["db/pool"]' WHERE id = ?1"#, [good]).unwrap();
+        let corrupt = test_note(&conn, "Pool leaks", "close the pool");
+        conn.execute(r#"UPDATE notes SET tags = '["db/pool"' WHERE id = ?1"#, [corrupt]).unwrap();
+
+        let results = search(&conn, "pool");
+        assert_eq!(results.len(), 2);
+        let leaks = results.iter().find(|n| n.id == corrupt).unwrap();
+        assert!(leaks.tags.is_empty());
+
+        let projection = Projection::parse(&fields(&["id", "tags"]), 140).unwrap();
+        let projected = search_projected(&conn, "leaks", &projection, None, 100, 2, false, WEIGHTS).unwrap();
+        assert_eq!(projected.notes[0]["tags"], serde_json::json!([]));
+
+        let tagged = filter_ids(&conn, "pool", None, Some("db"), TagCase::Lower, false).unwrap();
+        assert_eq!(tagged, vec![good]);
+    }
+
+    fn attach(conn: &Connection, note_id: u64, filename: &str) {
+        crate::attachments::add_attachment(conn, note_id, filename, b"png", 1024).unwrap();
+    }
+
+    #[test]
+    fn notes_are_found_by_attachment_filename() {
+        let conn = test_vault();
+        let diagram = test_note(&conn, "Architecture", "boxes and arrows");
+        attach(&conn, diagram, "Network-Diagram.png");
+        attach(&conn, diagram, "diagram_v2.svg");
+        attach(&conn, diagram, "notes.txt");
+        let other = test_note(&conn, "Old sketch", "");
+        attach(&conn, other, "network.png");
+
+        // The note text never mentions the filename, so FTS alone misses it
+        assert!(search(&conn, "diagram").is_empty());
+
+        let hits = attachment_hits(&conn, "diagram", 10, 2, false).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].note_id, hits[0].title.as_str()), (diagram, "Architecture"));
+        assert_eq!(hits[0].filenames, vec!["Network-Diagram.png", "diagram_v2.svg"]);
+
+        // Every word must match, and wildcards are literal
+        let both = attachment_hits(&conn, "network png", 10, 2, false).unwrap();
+        assert_eq!(both.len(), 2);
+        assert!(attachment_hits(&conn, "diagram%", 10, 2, false).unwrap().is_empty());
+        assert_eq!(attachment_hits(&conn, "network", 1, 2, false).unwrap().len(), 1);
+        assert!(attachment_hits(&conn, "d", 10, 2, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn attachment_hits_skip_archived_notes_unless_asked() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Archived", "");
+        attach(&conn, id, "diagram.png");
+        conn.execute("UPDATE notes SET is_archived = 1 WHERE id = ?1", [id]).unwrap();
+
+        assert!(attachment_hits(&conn, "diagram", 10, 2, false).unwrap().is_empty());
+        assert_eq!(attachment_hits(&conn, "diagram", 10, 2, true).unwrap()[0].note_id, id);
+    }
+
+    #[test]
+    fn bogus_stored_type_is_concept_unless_strict() {
+        let conn = test_vault();
+        let id = test_note(&conn, "Corrupted import", "imported body");
+        // Bypass the CHECK constraint the way a bad migration or foreign tool would
+        conn.execute_batch("PRAGMA ignore_check_constraints = ON").unwrap();
+        conn.execute("UPDATE notes SET knowledge_type = 'Recipe' WHERE id = ?1", [id]).unwrap();
+        conn.execute_batch("PRAGMA ignore_check_constraints = OFF").unwrap();
+
+        let lenient = search(&conn, "imported");
+        assert_eq!(lenient.len(), 1);
+        assert_eq!(lenient[0].knowledge_type, KnowledgeType::Concept);
+
+        let err = search_notes(&conn, "imported", None, 100, 2, false, WEIGHTS, true).unwrap_err();
+        match err.downcast_ref::<QuickNoteError>() {
+            Some(QuickNoteError::UnknownKnowledgeType { id: bad, value }) => assert_eq!((*bad, value.as_str()), (id, "Recipe")),
+            other => panic!("expected UnknownKnowledgeType, got {:?}", other),
+        }
+
+        conn.execute("UPDATE notes SET knowledge_type = NULL WHERE id = ?1", [id]).unwrap();
+        let strict = search_notes(&conn, "imported", None, 100, 2, false, WEIGHTS, true).unwrap();
+        assert_eq!(strict.notes[0].knowledge_type, KnowledgeType::Concept, "uncategorized is not corruption");
+    }
+}
@@ -0,0 +1,892 @@
+//! Tag parsing and hierarchical tag queries
+//!
+//! Tags may be nested with `/` (`#db/postgres/indexes`). A tag without slashes is
+//! just a one-level hierarchy, so flat tags keep working unchanged.
+
+use crate::config::TagCase;
+#[cfg(feature = "gui")]
+use crate::notes::{self, NoteSummary};
+use rusqlite::Connection;
+#[cfg(feature = "gui")]
+use serde::Serialize;
+#[cfg(feature = "gui")]
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Clean up a raw tag (with or without its leading `#`), collapsing empty path segments
+/// and applying `case`. Only letters, digits, `-` and `_` survive in each segment, so
+/// pasted emoji, combining marks and zero-width characters can't make a tag unsearchable.
+/// Returns `None` when nothing usable is left.
+pub fn normalize_tag(raw: &str, case: TagCase) -> Option<String> {
+    let path = raw
+        .trim_start_matches('#')
+        .split('/')
+        .map(|segment| segment.chars().filter(|c| is_tag_char(*c)).collect::<String>())
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if raw.trim_start_matches('#').chars().any(|c| c != '/' && !is_tag_char(c)) {
+        tracing::debug!("Tag {:?} normalized to {:?}", raw, path);
+    }
+
+    if path.is_empty() {
+        None
+    } else if case == TagCase::Lower {
+        Some(path.to_lowercase())
+    } else {
+        Some(path)
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Collect the distinct `#tags` in `content`, in order of first appearance. With
+/// `skip_code`, words inside ``` / ~~~ fences and inline `code` spans are ignored, so a
+/// Python or shell `# comment` doesn't turn into a tag.
+pub fn extract_tags(content: &str, case: TagCase, skip_code: bool) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        let prose = if skip_code {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+            strip_code_spans(line)
+        } else {
+            line.to_string()
+        };
+
+        for word in prose.split_whitespace().filter(|w| w.starts_with('#')) {
+            if let Some(tag) = normalize_tag(word, case) {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// Tags for a new note: the caller's `explicit` tags (normalized, in the given order)
+/// followed by any `extracted` ones not already among them
+pub fn merge_tags(explicit: &[String], extracted: Vec<String>, case: TagCase) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in explicit.iter().filter_map(|t| normalize_tag(t, case)).chain(extracted) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// A note's `tags` column as a list. A corrupt value (from an interrupted write or a
+/// manual edit) reads as no tags, so one bad row can't fail a whole query;
+/// `repair_tags_json` fixes the stored value.
+pub fn stored_tags(json: Option<String>) -> Vec<String> {
+    let json = json.unwrap_or_default();
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        tracing::warn!("Unreadable tags JSON {:?} ({}), treating it as empty", json, e);
+        Vec::new()
+    })
+}
+
+/// Replace inline code spans with a space. A span opens with a run of backticks and
+/// closes with a run of the same length; an unclosed run is literal text, as in Markdown.
+fn strip_code_spans(line: &str) -> String {
+    // Backtick runs as (byte offset, length)
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (i, _) in line.char_indices().filter(|(_, c)| *c == '`') {
+        match runs.last_mut() {
+            Some((start, len)) if *start + *len == i => *len += 1,
+            _ => runs.push((i, 1)),
+        }
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < runs.len() {
+        let (open, len) = runs[i];
+        match runs[i + 1..].iter().position(|&(_, l)| l == len) {
+            Some(offset) => {
+                let (close, _) = runs[i + 1 + offset];
+                out.push_str(&line[copied..open]);
+                out.push(' ');
+                copied = close + len;
+                i += offset + 2;
+            }
+            None => i += 1,
+        }
+    }
+    out.push_str(&line[copied..]);
+    out
+}
+
+/// Notes tagged with `prefix` itself or any tag nested below it
+#[cfg(feature = "gui")]
+pub fn notes_by_tag_prefix(conn: &Connection, prefix: &str, case: TagCase) -> Result<Vec<NoteSummary>, Box<dyn std::error::Error>> {
+    let prefix = match normalize_tag(prefix, case) {
+        Some(prefix) => prefix,
+        None => return Ok(Vec::new()),
+    };
+
+    // substr() instead of LIKE so `_`/`%` in tag names aren't treated as wildcards
+    let mut stmt = conn.prepare(
+        "SELECT id, title, knowledge_type, tags, encrypted FROM notes
+         WHERE is_archived = 0 AND json_valid(notes.tags) AND EXISTS (
+             SELECT 1 FROM json_each(notes.tags) t
+             WHERE t.value = ?1 OR substr(t.value, 1, length(?1) + 1) = ?1 || '/'
+         )
+         ORDER BY updated_at_ms DESC, id DESC",
+    )?;
+    Ok(notes::summaries(&mut stmt, [&prefix])?)
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    pub name: String,
+    pub path: String,
+    /// Distinct notes tagged with this path or any descendant
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+/// Build the nested tag hierarchy with per-node note counts
+#[cfg(feature = "gui")]
+pub fn tag_tree(conn: &Connection, case: TagCase) -> Result<Vec<TagNode>, Box<dyn std::error::Error>> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+
+    let mut stmt = conn.prepare("SELECT tags FROM notes WHERE is_archived = 0")?;
+    let rows = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
+    for row in rows {
+        let tags = stored_tags(row?);
+
+        // Every ancestor path counts once per note, even if several descendants are tagged
+        let mut paths = BTreeSet::new();
+        for tag in tags.iter().filter_map(|t| normalize_tag(t, case)) {
+            let segments: Vec<&str> = tag.split('/').collect();
+            for depth in 1..=segments.len() {
+                paths.insert(segments[..depth].join("/"));
+            }
+        }
+        for path in paths {
+            *counts.entry(path).or_insert(0) += 1;
+        }
+    }
+
+    Ok(build_level(&counts, ""))
+}
+
+/// Tags most often found on the same notes as `tag`, with the number of shared notes.
+/// Ties are broken alphabetically so suggestions don't reshuffle between calls.
+#[cfg(feature = "gui")]
+pub fn related_tags(
+    conn: &Connection,
+    tag: &str,
+    limit: usize,
+    case: TagCase,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let tag = match normalize_tag(tag, case) {
+        Some(tag) => tag,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT other.value, COUNT(DISTINCT notes.id) AS shared
+         FROM notes, json_each(notes.tags) this, json_each(notes.tags) other
+         WHERE json_valid(notes.tags) AND notes.is_archived = 0 AND this.value = ?1 AND other.value != ?1
+         GROUP BY other.value
+         ORDER BY shared DESC, other.value
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![tag, limit as i64], |row| {
+        Ok(serde_json::json!({
+            "tag": row.get::<_, String>(0)?,
+            "count": row.get::<_, u64>(1)?,
+        }))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Rename a tag and everything nested below it (`db` → `databases` also turns
+/// `db/postgres` into `databases/postgres`), merging with tags that already exist.
+/// Returns the number of notes changed.
+#[cfg(feature = "gui")]
+pub fn rename_tag(conn: &mut Connection, from: &str, to: &str, case: TagCase) -> Result<usize, Box<dyn std::error::Error>> {
+    let (from, to) = match (normalize_tag(from, case), normalize_tag(to, case)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err("Tag names must not be empty".into()),
+    };
+
+    rewrite_tags(conn, |tag| match tag.strip_prefix(&from) {
+        Some("") => to.clone(),
+        Some(rest) if rest.starts_with('/') => format!("{}{}", to, rest),
+        _ => tag.to_string(),
+    })
+}
+
+/// Fold every stored tag to lowercase and merge the duplicates this creates, for vaults
+/// written before `tag_case = "lower"`. Returns the number of notes changed.
+pub fn fold_tag_case(conn: &mut Connection) -> Result<usize, Box<dyn std::error::Error>> {
+    rewrite_tags(conn, |tag| normalize_tag(tag, TagCase::Lower).unwrap_or_default())
+}
+
+/// Repair every note's stored tag list after a bad import or interrupted write, then
+/// rebuild the normalized `tags`/`note_tags` tables from the result. Tags are
+/// re-normalized and de-duplicated, and a list that isn't valid JSON is rebuilt from the
+/// content's `#tags`. Runs in one transaction; returns the number of note-tag links.
+#[cfg(feature = "gui")]
+pub fn resync_tags(conn: &mut Connection, case: TagCase, skip_code: bool) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    fix_tag_lists(&tx, |id, tags| match tags {
+        Some(tags) => Ok(Some(merge_tags(&tags, Vec::new(), case))),
+        None => {
+            let content: String = tx.query_row(
+                "SELECT CASE WHEN encrypted THEN '' ELSE content END FROM notes WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )?;
+            Ok(Some(extract_tags(&content, case, skip_code)))
+        }
+    })?;
+    let links = crate::db::rebuild_note_tags(&tx)?;
+    tx.commit()?;
+    Ok(links)
+}
+
+/// Reset every `tags` value that isn't a JSON list of strings (or is NULL) to `[]`, in
+/// one transaction. Unlike `resync_tags` it touches nothing else. Returns the number of
+/// notes fixed.
+#[cfg(feature = "gui")]
+pub fn repair_tags_json(conn: &mut Connection) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let fixed = fix_tag_lists(&tx, |id, tags| {
+        Ok(match tags {
+            Some(_) => None,
+            None => {
+                tracing::warn!("Note {} had unreadable tags, reset to []", id);
+                Some(Vec::new())
+            }
+        })
+    })?;
+    tx.commit()?;
+    Ok(fixed)
+}
+
+/// The scan behind `resync_tags` and `repair_tags_json`: hand `fix` each note's id and
+/// stored tags (`None` when they aren't a JSON list of strings, or are NULL) and store
+/// whatever list it returns, if that differs. Returns the number of notes changed.
+#[cfg(feature = "gui")]
+fn fix_tag_lists(
+    conn: &Connection,
+    mut fix: impl FnMut(u64, Option<Vec<String>>) -> Result<Option<Vec<String>>, rusqlite::Error>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT id, tags FROM notes")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?)))?;
+    let mut changed = 0;
+    for row in rows {
+        let (id, json) = row?;
+        let stored = json.as_deref().and_then(|json| serde_json::from_str::<Vec<String>>(json).ok());
+        let Some(tags) = fix(id, stored)? else {
+            continue;
+        };
+
+        let fixed = serde_json::to_string(&tags)?;
+        if json.as_deref() != Some(fixed.as_str()) {
+            conn.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![fixed, id])?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// Spellings of one tag that `dedupe_tags` folds into a single canonical one
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct TagMerge {
+    pub canonical: String,
+    /// Stored spellings replaced by `canonical`, most used first
+    pub variants: Vec<String>,
+    /// Notes carrying at least one of the variants
+    pub notes: usize,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, Serialize)]
+pub struct TagDedupe {
+    pub merges: Vec<TagMerge>,
+    /// Notes rewritten (or, for a dry run, that would be)
+    pub notes_changed: usize,
+}
+
+/// Merge stored tags that differ only in case, stray `#`/whitespace or dropped characters
+/// (`#SQL`, `sql`, `# sql `), e.g. from vaults older than tag normalization. Spellings
+/// are grouped by their lowercase normalized form; the canonical one is the normalized
+/// spelling (under `case`) used by the most notes, ties going to the first in sort order.
+/// With `dry_run` only the plan is returned; otherwise every note is rewritten in one
+/// transaction.
+#[cfg(feature = "gui")]
+pub fn dedupe_tags(conn: &mut Connection, case: TagCase, dry_run: bool) -> Result<TagDedupe, Box<dyn std::error::Error>> {
+    // Lowercase normalized tag → stored spelling → notes using it
+    let mut groups: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    let mut note_tags: Vec<Vec<String>> = Vec::new();
+    {
+        let mut stmt = conn.prepare("SELECT tags FROM notes")?;
+        let rows = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
+        for row in rows {
+            let tags = stored_tags(row?);
+            for tag in tags.iter().collect::<BTreeSet<_>>() {
+                if let Some(key) = normalize_tag(tag, TagCase::Lower) {
+                    *groups.entry(key).or_default().entry(tag.clone()).or_default() += 1;
+                }
+            }
+            note_tags.push(tags);
+        }
+    }
+
+    let mut merges = Vec::new();
+    let mut canonical_of: BTreeMap<String, String> = BTreeMap::new();
+    for spellings in groups.values() {
+        let mut candidates: BTreeMap<String, usize> = BTreeMap::new();
+        for (spelling, count) in spellings {
+            if let Some(normalized) = normalize_tag(spelling, case) {
+                *candidates.entry(normalized).or_default() += count;
+            }
+        }
+        // max_by_key keeps the last maximum, so walk the candidates in reverse order
+        let Some((canonical, _)) = candidates.into_iter().rev().max_by_key(|(_, count)| *count) else {
+            continue;
+        };
+        let mut variants: Vec<(&String, &usize)> = spellings.iter().filter(|(spelling, _)| **spelling != canonical).collect();
+        if variants.is_empty() {
+            continue;
+        }
+        variants.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        for (variant, _) in &variants {
+            canonical_of.insert(variant.to_string(), canonical.clone());
+        }
+        merges.push(TagMerge {
+            notes: note_tags.iter().filter(|tags| tags.iter().any(|t| variants.iter().any(|(v, _)| *v == t))).count(),
+            variants: variants.into_iter().map(|(v, _)| v.clone()).collect(),
+            canonical,
+        });
+    }
+
+    let notes_changed = if dry_run {
+        note_tags.iter().filter(|tags| tags.iter().any(|t| canonical_of.contains_key(t))).count()
+    } else {
+        rewrite_tags(conn, |tag| canonical_of.get(tag).cloned().unwrap_or_else(|| tag.to_string()))?
+    };
+    Ok(TagDedupe { merges, notes_changed })
+}
+
+/// Apply `rewrite` to every tag of every note, dropping empty and duplicate results.
+/// Unreadable tags read as none (see `stored_tags`), so those notes are left for
+/// `repair_tags_json`.
+fn rewrite_tags(conn: &mut Connection, rewrite: impl Fn(&str) -> String) -> Result<usize, Box<dyn std::error::Error>> {
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+
+    {
+        let mut stmt = tx.prepare("SELECT id, tags FROM notes")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?)))?;
+        for row in rows {
+            let (id, json) = row?;
+            let tags = stored_tags(json);
+
+            let mut rewritten: Vec<String> = Vec::new();
+            for tag in tags.iter().map(|t| rewrite(t)) {
+                if !tag.is_empty() && !rewritten.contains(&tag) {
+                    rewritten.push(tag);
+                }
+            }
+
+            if rewritten != tags {
+                tx.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![serde_json::to_string(&rewritten)?, id])?;
+                changed += 1;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Add tags from the keyword → tag `dictionary` (see `Config::tag_keywords`) to every
+/// note whose title or content mentions a keyword. Tags a note already has are not
+/// repeated. With `dry_run` nothing is written. Returns `{id, title, added}` for each
+/// note that gains tags.
+#[cfg(feature = "gui")]
+pub fn auto_tag_all(
+    conn: &mut Connection,
+    dictionary: &BTreeMap<String, String>,
+    case: TagCase,
+    dry_run: bool,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let rules: Vec<(String, bool, String)> = dictionary
+        .iter()
+        .filter_map(|(keyword, tag)| {
+            let keyword = keyword.trim().to_lowercase();
+            let (keyword, prefix) = match keyword.strip_suffix('*') {
+                Some(stem) => (stem.to_string(), true),
+                None => (keyword, false),
+            };
+            let tag = normalize_tag(tag, case)?;
+            (!keyword.is_empty()).then_some((keyword, prefix, tag))
+        })
+        .collect();
+
+    let tx = conn.transaction()?;
+    let mut changes = Vec::new();
+    {
+        let mut stmt = tx.prepare("SELECT id, title, CASE WHEN encrypted THEN '' ELSE content END, tags FROM notes ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+        })?;
+        for row in rows {
+            let (id, title, content, json) = row?;
+            let text = format!("{}\n{}", title, content).to_lowercase();
+            let mut tags = stored_tags(json);
+
+            let mut added = Vec::new();
+            for (keyword, prefix, tag) in &rules {
+                if !tags.contains(tag) && mentions(&text, keyword, *prefix) {
+                    tags.push(tag.clone());
+                    added.push(tag.clone());
+                }
+            }
+            if added.is_empty() {
+                continue;
+            }
+            if !dry_run {
+                tx.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![serde_json::to_string(&tags)?, id])?;
+            }
+            changes.push(serde_json::json!({ "id": id, "title": title, "added": added }));
+        }
+    }
+
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(changes)
+}
+
+/// Whether lowercase `text` contains `keyword` as a whole word (or, with `prefix`, at
+/// the start of a word)
+#[cfg(feature = "gui")]
+fn mentions(text: &str, keyword: &str, prefix: bool) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(keyword).any(|(start, _)| {
+        let end = start + keyword.len();
+        let starts_word = !text[..start].chars().next_back().is_some_and(is_word_char);
+        let ends_word = prefix || !text[end..].chars().next().is_some_and(is_word_char);
+        starts_word && ends_word
+    })
+}
+
+#[cfg(feature = "gui")]
+fn build_level(counts: &BTreeMap<String, usize>, parent: &str) -> Vec<TagNode> {
+    counts
+        .iter()
+        .filter(|(path, _)| match parent {
+            "" => !path.contains('/'),
+            _ => path
+                .strip_prefix(parent)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .is_some_and(|rest| !rest.contains('/')),
+        })
+        .map(|(path, count)| TagNode {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            path: path.clone(),
+            count: *count,
+            children: build_level(counts, path),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_note, test_vault};
+
+    fn tagged(conn: &Connection, title: &str, tags: &str) -> u64 {
+        let id = test_note(conn, title, "");
+        conn.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", rusqlite::params![tags, id]).unwrap();
+        id
+    }
+
+    #[cfg(feature = "gui")]
+    fn prefix_ids(conn: &Connection, prefix: &str) -> Vec<u64> {
+        let mut ids: Vec<u64> = notes_by_tag_prefix(conn, prefix, TagCase::Lower).unwrap().iter().map(|n| n.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn extracts_nested_tags() {
+        assert_eq!(extract_tags("see #db/postgres/indexes and #Rust", TagCase::Lower, false), vec!["db/postgres/indexes", "rust"]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn prefix_matches_all_descendants() {
+        let conn = test_vault();
+        let indexes = tagged(&conn, "Indexes", r#"["db/postgres/indexes"]"#);
+        let vacuum = tagged(&conn, "Vacuum", r#"["db/postgres/vacuum"]"#);
+        let mysql = tagged(&conn, "MySQL", r#"["db/mysql"]"#);
+        let sibling = tagged(&conn, "Sibling", r#"["db/postgresql"]"#);
+
+        assert_eq!(prefix_ids(&conn, "db/postgres"), vec![indexes, vacuum]);
+        assert_eq!(prefix_ids(&conn, "#DB"), vec![indexes, vacuum, mysql, sibling]);
+        assert!(prefix_ids(&conn, "db/post").is_empty());
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn tag_that_is_both_leaf_and_prefix() {
+        let conn = test_vault();
+        let leaf = tagged(&conn, "General", r#"["db"]"#);
+        let nested = tagged(&conn, "Nested", r#"["db/postgres"]"#);
+        let both = tagged(&conn, "Both", r#"["db", "db/postgres"]"#);
+
+        assert_eq!(prefix_ids(&conn, "db"), vec![leaf, nested, both]);
+
+        let tree = tag_tree(&conn, TagCase::Lower).unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!((tree[0].path.as_str(), tree[0].count), ("db", 3));
+        assert_eq!((tree[0].children[0].path.as_str(), tree[0].children[0].count), ("db/postgres", 2));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn flat_tags_still_work() {
+        let conn = test_vault();
+        let id = tagged(&conn, "Flat", r#"["rust", "cli"]"#);
+
+        assert_eq!(prefix_ids(&conn, "rust"), vec![id]);
+        let tree = tag_tree(&conn, TagCase::Lower).unwrap();
+        assert_eq!(tree.iter().map(|n| (n.name.as_str(), n.count, n.children.len())).collect::<Vec<_>>(), vec![("cli", 1, 0), ("rust", 1, 0)]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn prefix_wildcards_are_literal() {
+        let conn = test_vault();
+        let id = tagged(&conn, "Underscore", r#"["my_db/x"]"#);
+        tagged(&conn, "Other", r#"["myxdb/x"]"#);
+
+        assert_eq!(prefix_ids(&conn, "my_db"), vec![id]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn prefix_skips_archived_notes() {
+        let conn = test_vault();
+        let live = tagged(&conn, "Live", r#"["db/postgres"]"#);
+        let archived = tagged(&conn, "Archived", r#"["db/postgres"]"#);
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        assert_eq!(prefix_ids(&conn, "db"), vec![live]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn archived_notes_are_left_out_of_tag_counts() {
+        let conn = test_vault();
+        tagged(&conn, "Live", r#"["rust", "cli"]"#);
+        let archived = tagged(&conn, "Archived", r#"["rust", "async", "old"]"#);
+        crate::notes::set_archived(&conn, archived, true).unwrap();
+
+        let tree = tag_tree(&conn, TagCase::Lower).unwrap();
+        assert_eq!(tree.iter().map(|n| (n.name.as_str(), n.count)).collect::<Vec<_>>(), vec![("cli", 1), ("rust", 1)]);
+        assert_eq!(related_tags(&conn, "rust", 10, TagCase::Lower).unwrap(), vec![serde_json::json!({ "tag": "cli", "count": 1 })]);
+        assert!(related_tags(&conn, "old", 10, TagCase::Lower).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fold_tag_case_lowercases_and_merges_duplicates() {
+        let mut conn = test_vault();
+        let mixed = tagged(&conn, "Mixed", r#"["SQL", "sql", "Db/Postgres", "rust"]"#);
+        let lower = tagged(&conn, "Lower", r#"["sql"]"#);
+
+        assert_eq!(fold_tag_case(&mut conn).unwrap(), 1);
+
+        let tags: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [mixed], |row| row.get(0)).unwrap();
+        assert_eq!(tags, r#"["sql","db/postgres","rust"]"#);
+        let tags: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [lower], |row| row.get(0)).unwrap();
+        assert_eq!(tags, r#"["sql"]"#);
+        assert_eq!(fold_tag_case(&mut conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn fold_tag_case_skips_corrupt_tags_cell() {
+        let mut conn = test_vault();
+        let corrupt = tagged(&conn, "Corrupt", r#"["sql""#);
+        let null = test_note(&conn, "Null", "");
+        conn.execute("UPDATE notes SET tags = NULL WHERE id = ?1", [null]).unwrap();
+        let mixed = tagged(&conn, "Mixed", r#"["SQL"]"#);
+
+        assert_eq!(fold_tag_case(&mut conn).unwrap(), 1);
+
+        let tags = |id: u64| -> Option<String> { conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap() };
+        assert_eq!(tags(corrupt).as_deref(), Some(r#"["sql""#));
+        assert_eq!(tags(null), None);
+        assert_eq!(tags(mixed).as_deref(), Some(r#"["sql"]"#));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn tag_queries_ignore_case_when_lowercasing() {
+        let conn = test_vault();
+        let id = tagged(&conn, "Query", r#"["sql/joins"]"#);
+
+        assert_eq!(prefix_ids(&conn, "#SQL"), vec![id]);
+        assert_eq!(extract_tags("#SQL and #Sql", TagCase::Lower, false), vec!["sql"]);
+        assert_eq!(extract_tags("#SQL and #Sql", TagCase::Preserve, false), vec!["SQL", "Sql"]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn rename_tag_moves_descendants_and_merges() {
+        let mut conn = test_vault();
+        let id = tagged(&conn, "Query", r#"["sql", "sql/joins", "database"]"#);
+
+        assert_eq!(rename_tag(&mut conn, "SQL", "database", TagCase::Lower).unwrap(), 1);
+
+        let tags: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert_eq!(tags, r#"["database","database/joins"]"#);
+        assert!(rename_tag(&mut conn, "#", "x", TagCase::Lower).is_err());
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn related_tags_ranks_co_occurring_tags() {
+        let conn = test_vault();
+        tagged(&conn, "A", r#"["rust", "async", "tokio"]"#);
+        tagged(&conn, "B", r#"["rust", "async"]"#);
+        tagged(&conn, "C", r#"["rust", "cli"]"#);
+        tagged(&conn, "D", r#"["python", "async"]"#);
+
+        let related = related_tags(&conn, "#Rust", 10, TagCase::Lower).unwrap();
+        assert_eq!(
+            related,
+            vec![
+                serde_json::json!({ "tag": "async", "count": 2 }),
+                serde_json::json!({ "tag": "cli", "count": 1 }),
+                serde_json::json!({ "tag": "tokio", "count": 1 }),
+            ]
+        );
+        assert_eq!(related_tags(&conn, "rust", 1, TagCase::Lower).unwrap().len(), 1);
+        assert!(related_tags(&conn, "go", 10, TagCase::Lower).unwrap().is_empty());
+    }
+
+    #[test]
+    fn normalize_strips_emoji_suffix() {
+        assert_eq!(normalize_tag("#done\u{2705}", TagCase::Lower).as_deref(), Some("done"));
+        assert_eq!(normalize_tag("#ship-it\u{1F680}/v2", TagCase::Preserve).as_deref(), Some("ship-it/v2"));
+    }
+
+    #[test]
+    fn normalize_strips_zero_width_and_combining_chars() {
+        assert_eq!(normalize_tag("#data\u{200B}base", TagCase::Lower).as_deref(), Some("database"));
+        assert_eq!(normalize_tag("#a\u{200D}b\u{FEFF}", TagCase::Lower).as_deref(), Some("ab"));
+        assert_eq!(normalize_tag("#cafe\u{301}", TagCase::Lower).as_deref(), Some("cafe"));
+        assert_eq!(normalize_tag("#caf\u{E9}", TagCase::Lower).as_deref(), Some("caf\u{E9}"));
+    }
+
+    #[test]
+    fn pure_emoji_tag_is_dropped() {
+        assert_eq!(normalize_tag("#\u{1F389}", TagCase::Lower), None);
+        assert_eq!(normalize_tag("#\u{1F389}/\u{200B}", TagCase::Lower), None);
+        assert_eq!(extract_tags("party #\u{1F389} and #real", TagCase::Lower, false), vec!["real"]);
+    }
+
+    #[test]
+    fn fenced_python_comment_is_not_a_tag() {
+        let content = "#real tag in prose\n```python\n# comment\nx = 1  #inline\n```\n~~~sh\n#!/bin/sh\n~~~\nafter #also";
+        assert_eq!(extract_tags(content, TagCase::Lower, true), vec!["real", "also"]);
+        // With the flag off every #word counts, shebang included
+        assert_eq!(extract_tags(content, TagCase::Lower, false), vec!["real", "inline", "bin/sh", "also"]);
+    }
+
+    #[test]
+    fn inline_code_spans_are_skipped() {
+        let content = "use `#[derive]` and ``a #b`` but #keep";
+        assert_eq!(extract_tags(content, TagCase::Lower, true), vec!["keep"]);
+    }
+
+    #[cfg(feature = "gui")]
+    fn dictionary(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(keyword, tag)| (keyword.to_string(), tag.to_string())).collect()
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn keywords_match_whole_words_only_unless_starred() {
+        let mut conn = test_vault();
+        let docker = test_note(&conn, "Compose file", "Run it with Docker.");
+        let dock = test_note(&conn, "Boats", "moored at the dock");
+
+        let whole = auto_tag_all(&mut conn, &dictionary(&[("dock", "harbour")]), TagCase::Lower, true).unwrap();
+        assert_eq!(whole, vec![serde_json::json!({ "id": dock, "title": "Boats", "added": ["harbour"] })]);
+
+        let starred = auto_tag_all(&mut conn, &dictionary(&[("DOCK*", "harbour")]), TagCase::Lower, true).unwrap();
+        let ids: Vec<u64> = starred.iter().map(|c| c["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, vec![docker, dock]);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn auto_tag_skips_existing_tags_and_dry_run_writes_nothing() {
+        let mut conn = test_vault();
+        let id = tagged(&conn, "Docker networking", r#"["containers"]"#);
+        let rules = dictionary(&[("docker", "#Containers"), ("networking", "net")]);
+
+        let preview = auto_tag_all(&mut conn, &rules, TagCase::Lower, true).unwrap();
+        assert_eq!(preview[0]["added"], serde_json::json!(["net"]));
+        let stored: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert_eq!(stored, r#"["containers"]"#);
+
+        auto_tag_all(&mut conn, &rules, TagCase::Lower, false).unwrap();
+        let stored: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap();
+        assert_eq!(stored, r#"["containers","net"]"#);
+        assert!(auto_tag_all(&mut conn, &rules, TagCase::Lower, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_explicit_order_and_case_rules() {
+        let explicit = ["Ops".to_string(), "#ops".to_string(), "Deploy".to_string()];
+        let extracted = vec!["deploy".to_string(), "ci".to_string()];
+        assert_eq!(merge_tags(&explicit, extracted.clone(), TagCase::Lower), ["ops", "deploy", "ci"]);
+        assert_eq!(merge_tags(&explicit, extracted, TagCase::Preserve), ["Ops", "ops", "Deploy", "deploy", "ci"]);
+        assert!(merge_tags(&[], Vec::new(), TagCase::Lower).is_empty());
+    }
+
+    #[cfg(feature = "gui")]
+    fn ids_tagged(conn: &Connection, tag: &str) -> Vec<u64> {
+        let mut stmt = conn
+            .prepare("SELECT nt.note_id FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE t.name = ?1 ORDER BY nt.note_id")
+            .unwrap();
+        let ids = stmt.query_map([tag], |row| row.get(0)).unwrap();
+        ids.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn resync_rebuilds_a_corrupted_join_table() {
+        let mut conn = test_vault();
+        let rust = tagged(&conn, "rust", r#"["rust", "Lang", "rust"]"#);
+        let both = tagged(&conn, "both", r#"["rust", "db"]"#);
+        let broken = test_note(&conn, "broken", "fixed via #recovered");
+        conn.execute("UPDATE notes SET tags = 'not json' WHERE id = ?1", [broken]).unwrap();
+
+        // Lose some links, invent others, and leave a tag nobody uses
+        conn.execute_batch(
+            "DELETE FROM note_tags WHERE tag_id = (SELECT id FROM tags WHERE name = 'rust');
+             INSERT INTO tags (name) VALUES ('ghost');
+             INSERT INTO note_tags (note_id, tag_id) SELECT 1, id FROM tags WHERE name = 'ghost';",
+        )
+        .unwrap();
+        assert!(ids_tagged(&conn, "rust").is_empty());
+
+        assert_eq!(resync_tags(&mut conn, TagCase::Lower, false).unwrap(), 5);
+        assert_eq!(ids_tagged(&conn, "rust"), vec![rust, both]);
+        assert_eq!(ids_tagged(&conn, "lang"), vec![rust]);
+        assert_eq!(ids_tagged(&conn, "recovered"), vec![broken]);
+        assert!(ids_tagged(&conn, "ghost").is_empty());
+        let ghost: i64 = conn.query_row("SELECT COUNT(*) FROM tags WHERE name = 'ghost'", [], |row| row.get(0)).unwrap();
+        assert_eq!(ghost, 0);
+        let stored: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [rust], |row| row.get(0)).unwrap();
+        assert_eq!(stored, r#"["rust","lang"]"#);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn repair_resets_only_unreadable_lists() {
+        let mut conn = test_vault();
+        let spaced = tagged(&conn, "spaced", r#"[ "a",  "b" ]"#);
+        let broken = tagged(&conn, "broken", "{oops");
+        let numbers = tagged(&conn, "numbers", "[1, 2]");
+
+        assert_eq!(repair_tags_json(&mut conn).unwrap(), 2);
+        let stored = |id: u64| -> String {
+            conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(stored(spaced), r#"[ "a",  "b" ]"#);
+        assert_eq!((stored(broken), stored(numbers)), ("[]".to_string(), "[]".to_string()));
+        assert_eq!(ids_tagged(&conn, "a"), vec![spaced]);
+    }
+
+    #[test]
+    fn stored_tags_reads_corrupt_or_missing_lists_as_empty() {
+        assert_eq!(stored_tags(Some(r#"["sql","db"]"#.to_string())), vec!["sql", "db"]);
+        for bad in [Some(r#"["sql""#.to_string()), Some("[1, 2]".to_string()), Some(String::new()), None] {
+            assert!(stored_tags(bad.clone()).is_empty(), "{:?}", bad);
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn tag_readers_skip_corrupt_cells() {
+        let mut conn = test_vault();
+        let good = tagged(&conn, "Good", r#"["db/postgres","sql"]"#);
+        tagged(&conn, "Corrupt", r#"["db/postgres""#);
+        let null = test_note(&conn, "Null", "mentions docker");
+        conn.execute("UPDATE notes SET tags = NULL WHERE id = ?1", [null]).unwrap();
+
+        assert_eq!(prefix_ids(&conn, "db"), vec![good]);
+        let tree = tag_tree(&conn, TagCase::Lower).unwrap();
+        let counts: Vec<(&str, usize)> = tree.iter().map(|node| (node.path.as_str(), node.count)).collect();
+        assert_eq!(counts, vec![("db", 1), ("sql", 1)]);
+        let related = related_tags(&conn, "sql", 10, TagCase::Lower).unwrap();
+        assert_eq!(related, vec![serde_json::json!({"tag": "db/postgres", "count": 1})]);
+        assert_eq!(dedupe_tags(&mut conn, TagCase::Lower, false).unwrap().notes_changed, 0);
+
+        // A note whose list was unreadable gains the matched tag like any untagged note
+        let added = auto_tag_all(&mut conn, &dictionary(&[("docker", "containers")]), TagCase::Lower, false).unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0]["id"], null);
+        let stored: String = conn.query_row("SELECT tags FROM notes WHERE id = ?1", [null], |row| row.get(0)).unwrap();
+        assert_eq!(stored, r#"["containers"]"#);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn case_variants_collapse_to_the_most_used_spelling() {
+        let mut conn = test_vault();
+        let upper = tagged(&conn, "Upper", r##"["#SQL","rust"]"##);
+        let lower = tagged(&conn, "Lower", r#"["sql"]"#);
+        let spaced = tagged(&conn, "Spaced", r##"["# sql ","sql"]"##);
+        let again = tagged(&conn, "Again", r#"["sql","SQL"]"#);
+
+        let plan = dedupe_tags(&mut conn, TagCase::Lower, true).unwrap();
+        assert_eq!(plan.merges.len(), 1);
+        assert_eq!(plan.merges[0].canonical, "sql");
+        assert_eq!(plan.merges[0].variants, ["# sql ", "#SQL", "SQL"]);
+        assert_eq!((plan.merges[0].notes, plan.notes_changed), (3, 3));
+        // A dry run writes nothing
+        assert_eq!(ids_tagged(&conn, "sql"), vec![lower, spaced, again]);
+
+        let done = dedupe_tags(&mut conn, TagCase::Lower, false).unwrap();
+        assert_eq!(done.notes_changed, 3);
+        let stored = |id: u64| -> String {
+            conn.query_row("SELECT tags FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(stored(upper), r#"["sql","rust"]"#);
+        assert_eq!((stored(spaced), stored(again)), (r#"["sql"]"#.to_string(), r#"["sql"]"#.to_string()));
+        // The join table follows the rewrite
+        assert_eq!(ids_tagged(&conn, "sql"), vec![upper, lower, spaced, again]);
+        assert!(ids_tagged(&conn, "SQL").is_empty());
+        assert!(dedupe_tags(&mut conn, TagCase::Lower, false).unwrap().merges.is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+//! Starter content for new notes, one template per knowledge type
+//!
+//! Templates may contain `{{name}}` placeholders. `{{date}}` (UTC, `YYYY-MM-DD`) and
+//! `{{title}}` are filled in on creation; `{{cursor}}` marks where the editor should put
+//! the caret and is removed from the note.
+
+use crate::categorize::KnowledgeType;
+use crate::config::TagCase;
+use crate::error::QuickNoteError;
+use crate::{links, notes, tags};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const CURSOR: &str = "{{cursor}}";
+
+/// Built-in template for `kind`
+pub fn builtin(kind: KnowledgeType) -> &'static str {
+    match kind {
+        KnowledgeType::Concept => "# {{title}}\n\n{{cursor}}\n\n## Why it matters\n\n## See also\n",
+        KnowledgeType::Snippet => "# {{title}}\n\n```\n{{cursor}}\n```\n\nUse when:\n",
+        KnowledgeType::Checklist => "# {{title}}\n\n- [ ] {{cursor}}\n- [ ] \n- [ ] \n",
+        KnowledgeType::Note => "# {{title}}\n\n_{{date}}_\n\n{{cursor}}\n",
+        KnowledgeType::Process => "# {{title}}\n\n1. {{cursor}}\n2. \n3. \n",
+        KnowledgeType::SQLQuery => "# {{title}}\n\n```sql\n{{cursor}}\n```\n\n#sql\n",
+        KnowledgeType::DebugPattern => {
+            "# {{title}}\n\nSeen: {{date}}\n\n## Symptom\n\n{{cursor}}\n\n## Cause\n\n## Fix\n\n#debug\n"
+        }
+    }
+}
+
+/// Replace each `{{name}}` with `vars[name]` (whitespace inside the braces is ignored).
+/// Placeholders without a value are left exactly as written, so a typo stays visible
+/// in the note instead of silently disappearing. Substituted values are not rescanned.
+pub fn render_template(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + 2 + len + 2];
+        out.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..start + 2 + len].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(placeholder),
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A note freshly created from a template
+#[derive(Debug, Serialize)]
+pub struct TemplatedNote {
+    pub id: u64,
+    pub title: String,
+    pub content: String,
+    pub knowledge_type: KnowledgeType,
+    pub tags: Vec<String>,
+    /// Caret position from `{{cursor}}`, in UTF-16 code units (what a JS textarea uses)
+    pub cursor: Option<usize>,
+}
+
+/// Create a note of `kind` from its built-in template, enrolled in review like any new note
+pub fn create_from_template(
+    conn: &Connection,
+    kind: KnowledgeType,
+    title: &str,
+    tag_case: TagCase,
+    skip_code_tags: bool,
+) -> Result<TemplatedNote, QuickNoteError> {
+    let vars = HashMap::from([
+        ("date".to_string(), chrono::Utc::now().format("%Y-%m-%d").to_string()),
+        ("title".to_string(), title.to_string()),
+    ]);
+
+    // Render either side of the first cursor marker separately, so a title that happens
+    // to contain `{{cursor}}` can't move the caret
+    let template = builtin(kind);
+    let (content, cursor) = match template.split_once(CURSOR) {
+        Some((before, after)) => {
+            let before = render_template(before, &vars);
+            let cursor = before.encode_utf16().count();
+            (before + &render_template(after, &vars), Some(cursor))
+        }
+        None => (render_template(template, &vars), None),
+    };
+
+    let tags = tags::extract_tags(&content, tag_case, skip_code_tags);
+    conn.execute(
+        "INSERT INTO notes (title, content, knowledge_type, tags, review_due)
+         VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'))",
+        rusqlite::params![title, content, kind.as_db_str(), serde_json::to_string(&tags)?],
+    )?;
+    let id = conn.last_insert_rowid() as u64;
+    links::sync_links(conn, id)?;
+    notes::refresh_metadata(conn, id)?;
+
+    Ok(TemplatedNote {
+        id,
+        title: title.to_string(),
+        content,
+        knowledge_type: kind,
+        tags,
+        cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_vault;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn substitutes_date_and_title() {
+        let vars = vars(&[("date", "2026-10-16"), ("title", "Pool sizing")]);
+        assert_eq!(render_template("# {{title}}\n_{{ date }}_", &vars), "# Pool sizing\n_2026-10-16_");
+    }
+
+    #[test]
+    fn unknown_placeholders_stay_literal() {
+        let vars = vars(&[("title", "{{date}}")]);
+        assert_eq!(render_template("{{titel}} {{title}} {{date}} {{open", &vars), "{{titel}} {{date}} {{date}} {{open");
+    }
+
+    #[test]
+    fn created_note_fills_placeholders_and_places_cursor() {
+        let conn = test_vault();
+        let note = create_from_template(&conn, KnowledgeType::Note, "Café {{cursor}}", TagCase::Lower, false).unwrap();
+
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(note.content, format!("# Café {{{{cursor}}}}\n\n_{}_\n\n\n", today));
+        // The title's own marker doesn't move the caret
+        let before = format!("# Café {{{{cursor}}}}\n\n_{}_\n\n", today);
+        assert_eq!(note.cursor, Some(before.encode_utf16().count()));
+
+        let (content, due): (String, Option<i64>) = conn
+            .query_row("SELECT content, review_due FROM notes WHERE id = ?1", [note.id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(content, note.content);
+        assert!(due.is_some());
+    }
+
+    #[test]
+    fn template_tags_are_stored() {
+        let conn = test_vault();
+        let note = create_from_template(&conn, KnowledgeType::SQLQuery, "Dupes", TagCase::Lower, false).unwrap();
+        assert_eq!(note.tags, vec!["sql"]);
+        assert_eq!(note.cursor, Some("# Dupes\n\n```sql\n".len()));
+    }
+}